@@ -0,0 +1,155 @@
+use crate::geom::{Frame, Vector3};
+use crate::shapes::Intersection;
+use crate::texture::Texture;
+
+// bump.rs
+//
+// Normal maps and bump maps add detail to a surface by tilting its shading
+// normal, without changing its shape. Both are drawn in the surface's
+// tangent frame: the directions in which its texture coordinates u and v
+// increase, and the normal.
+
+/// Step in texture coordinates for measuring the slope of a height map
+const HEIGHT_STEP: f64 = 1e-4;
+
+#[derive(Debug, Clone)]
+pub enum Bump {
+    /// A tangent-space normal map, whose red, green and blue values from 0
+    /// to 1 give the normal's components from -1 to 1 along u, v and the
+    /// surface normal. Flat is (0.5, 0.5, 1).
+    NormalMap(Texture),
+    /// A height map, whose luminance times `scale` is how far each point
+    /// seems to be raised along the normal, in scene units
+    Height { map: Texture, scale: f64 },
+}
+
+impl Bump {
+    /// Tilts the hit's normal
+    pub fn apply(&self, hit: &mut Intersection) {
+        // maps are drawn on the outside of the surface
+        let outward = if hit.ray_hit_outer_surface {
+            hit.normal
+        } else {
+            -hit.normal
+        };
+        let normal = match self {
+            Bump::NormalMap(texture) => {
+                let color = texture.value_at(hit);
+                let frame = Frame::from_tangents(outward, hit.dpdu, hit.dpdv);
+                frame.to_world(&Vector3::new(
+                    2.0 * color.red - 1.0,
+                    2.0 * color.green - 1.0,
+                    2.0 * color.blue - 1.0,
+                ))
+            }
+            Bump::Height { map, scale } => {
+                let (mut dpdu, mut dpdv) = (hit.dpdu, hit.dpdv);
+                if dpdu.cross(&dpdv).length_squared() < 1e-20 {
+                    let frame = Frame::from_tangents(outward, dpdu, dpdv);
+                    dpdu = frame.tangent;
+                    dpdv = frame.bitangent;
+                }
+                let height = |du: f64, dv: f64| {
+                    let uv = (hit.uv.0 + du, hit.uv.1 + dv);
+                    // solid textures need the point to move too
+                    let p = hit.p + du * dpdu + dv * dpdv;
+                    scale * map.filtered_value(uv, hit.footprint, &p).luminance()
+                };
+                let h = height(0.0, 0.0);
+                let slope_u = (height(HEIGHT_STEP, 0.0) - h) / HEIGHT_STEP;
+                let slope_v = (height(0.0, HEIGHT_STEP) - h) / HEIGHT_STEP;
+                // the raised surface p + h n, ignoring how n itself turns
+                let raised = (dpdu + slope_u * outward).cross(&(dpdv + slope_v * outward));
+                if raised.dot(&outward) < 0.0 {
+                    -raised
+                } else {
+                    raised
+                }
+            }
+        };
+        if normal.length_squared() <= 0.0 || normal.dot(&outward) <= 0.0 {
+            // a normal facing into the surface would shade it from behind
+            return;
+        }
+        let normal = normal.to_unit_vector();
+        hit.normal = if hit.ray_hit_outer_surface {
+            normal
+        } else {
+            -normal
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bump;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, Quad, INFINITY};
+    use crate::texture::{Texture, TextureAxis};
+
+    /// The unit square in the xz-plane, facing up, with u along +x and v
+    /// along -z
+    fn floor() -> Quad {
+        Quad::new(
+            Point3::new(0., 0., 1.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 0., -1.),
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        )
+    }
+
+    fn assert_close(a: Vector3, b: Vector3) {
+        assert!((a - b).norm() < 1e-6, "{:?} is not {:?}", a, b);
+    }
+
+    #[test]
+    fn normal_maps_tilt_normals_in_tangent_space() {
+        let floor = floor();
+        let down = Ray::new(Point3::new(0.5, 1., 0.5), Vector3::new(0., -1., 0.));
+        let mut hit = floor.hit(&down, Interval::new(0., INFINITY)).unwrap();
+        Bump::NormalMap(Color::new(0.5, 0.5, 1.).into()).apply(&mut hit);
+        assert_close(hit.normal, Vector3::new(0., 1., 0.));
+
+        // tilted 45 degrees towards u
+        let mut hit = floor.hit(&down, Interval::new(0., INFINITY)).unwrap();
+        Bump::NormalMap(Color::new(1., 0.5, 1.).into()).apply(&mut hit);
+        let half = 0.5f64.sqrt();
+        assert_close(hit.normal, Vector3::new(half, half, 0.));
+
+        // and towards v, seen from below
+        let up = Ray::new(Point3::new(0.5, -1., 0.5), Vector3::new(0., 1., 0.));
+        let mut hit = floor.hit(&up, Interval::new(0., INFINITY)).unwrap();
+        Bump::NormalMap(Color::new(0.5, 1., 1.).into()).apply(&mut hit);
+        assert_close(hit.normal, Vector3::new(0., -half, half));
+    }
+
+    #[test]
+    fn bump_maps_tilt_normals_down_slopes() {
+        let floor = floor();
+        let down = Ray::new(Point3::new(0.5, 1., 0.5), Vector3::new(0., -1., 0.));
+        // rising by 0.5 across the square along u, which is +x
+        let ramp = Bump::Height {
+            map: Texture::Gradient {
+                start: Color::BLACK,
+                end: Color::WHITE,
+                axis: TextureAxis::U,
+            },
+            scale: 0.5,
+        };
+        let mut hit = floor.hit(&down, Interval::new(0., INFINITY)).unwrap();
+        ramp.apply(&mut hit);
+        assert_close(hit.normal, Vector3::new(-0.5, 1., 0.).to_unit_vector());
+
+        // a constant height changes nothing
+        let flat = Bump::Height {
+            map: Color::WHITE.into(),
+            scale: 2.,
+        };
+        let mut hit = floor.hit(&down, Interval::new(0., INFINITY)).unwrap();
+        flat.apply(&mut hit);
+        assert_close(hit.normal, Vector3::new(0., 1., 0.));
+    }
+}
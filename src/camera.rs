@@ -1,8 +1,14 @@
 use crate::canvas::{CanvasConfig, Resolution};
 use crate::color::Color;
 use crate::geom::{random_in_unit_disk, Point3, Vector3};
+use crate::image::Image;
+use crate::integrator::{
+    Integrator, IntegratorKind, MisHeuristic, PathTracer, DEFAULT_RUSSIAN_ROULETTE_DEPTH,
+};
+use crate::random;
 use crate::ray::Ray;
-use crate::shapes::{HittableObjects, Interval, INFINITY};
+use crate::shapes::HittableObjects;
+use crate::tonemap::ToneMapping;
 
 use indicatif::ProgressBar;
 use rand::prelude::*;
@@ -19,6 +25,19 @@ pub struct RenderConfig {
     pub samples_per_pixel: usize,
     // Maximum numbner of times a ray can bounce in the scene
     pub max_depth: i32,
+    // Number of bounces before paths may be ended by Russian roulette
+    pub russian_roulette_depth: i32,
+    // Seed for the random numbers used while rendering. Rendering the same
+    // scene with the same seed gives the same image.
+    pub seed: u64,
+    // Whether to show a progress bar while rendering
+    pub show_progress: bool,
+    // How the rendered image is converted for display
+    pub tone_mapping: ToneMapping,
+    // How light samples and scattered rays are combined
+    pub mis_heuristic: MisHeuristic,
+    // What's computed for each camera ray
+    pub integrator: IntegratorKind,
 }
 
 impl RenderConfig {
@@ -29,8 +48,61 @@ impl RenderConfig {
             width: canvas_config.width(),
             samples_per_pixel,
             max_depth,
+            russian_roulette_depth: DEFAULT_RUSSIAN_ROULETTE_DEPTH,
+            seed: 0,
+            show_progress: true,
+            tone_mapping: ToneMapping::default(),
+            mis_heuristic: MisHeuristic::default(),
+            integrator: IntegratorKind::default(),
         }
     }
+
+    /// A path tracer with these settings
+    pub fn path_tracer(&self) -> PathTracer {
+        PathTracer {
+            max_depth: self.max_depth,
+            heuristic: self.mis_heuristic,
+            russian_roulette_depth: self.russian_roulette_depth,
+        }
+    }
+
+    /// Changes the image size, keeping the other settings
+    pub fn set_resolution(&mut self, resolution: Resolution) {
+        self.height = resolution.height();
+        self.width = resolution.width();
+    }
+
+    pub fn aspect_ratio(&self) -> f64 {
+        self.width as f64 / self.height as f64
+    }
+}
+
+/// Where the camera is and how its lens is set up. The aspect ratio is left
+/// out, since it depends on the size of the image being rendered.
+#[derive(Copy, Clone, Debug)]
+pub struct CameraConfig {
+    pub look_from: Point3,
+    pub look_at: Point3,
+    pub view_up: Vector3,
+    /// vertical field-of-view, in degrees
+    pub vertical_fov: f64,
+    pub aperture: f64,
+    pub focus_dist: f64,
+}
+
+impl CameraConfig {
+    /// Creates a camera for images with the given aspect ratio
+    pub fn build(&self, aspect_ratio: f64) -> Camera {
+        Camera::new(
+            self.look_from,
+            self.look_at,
+            self.view_up,
+            self.vertical_fov,
+            aspect_ratio,
+            self.aperture,
+            self.focus_dist,
+        )
+    }
 }
 
 /// The `Camera`` struct creates rays and sends them into the scene
@@ -49,7 +121,6 @@ pub struct Camera {
     // focus_dist: f64,
     // theta: f64,
     // h: f64,
-    // viewport_height: f64,
     // viewport_width: f64,
     // focal_length: f64,
     origin: Point3,
@@ -58,8 +129,9 @@ pub struct Camera {
     vertical: Vector3,
     u: Vector3,
     v: Vector3,
-    w: Vector3,
     lens_radius: f64,
+    /// Height of the viewport one unit in front of the camera
+    viewport_height: f64,
 }
 
 fn degrees_to_radians(degrees: f64) -> f64 {
@@ -100,8 +172,8 @@ impl Camera {
             vertical,
             u,
             v,
-            w,
             lens_radius,
+            viewport_height,
         }
     }
 
@@ -114,93 +186,126 @@ impl Camera {
         Ray::new(self.origin + offset, direction)
     }
 
-    pub fn compute_ray_color(&self, r: Ray, objects: &HittableObjects, depth: i32) -> Color {
-        if depth <= 0 {
-            // If ray has bounced more than allowed number of bounces,
-            // stop collecting light for it
-            return Color::BLACK;
-        }
-
-        let intersection = objects.hit(&r, Interval::new(1e-3_f64, INFINITY));
-
-        match intersection {
-            Some(intersect) => {
-                let intersection_material = intersect.material;
-                let ray_and_color = intersection_material.scatter(r, &intersect);
-
-                match ray_and_color {
-                    Some((scattered_ray, attenuation)) => {
-                        attenuation.mult(self.compute_ray_color(scattered_ray, objects, depth - 1))
-                    }
-                    None => Color::BLACK,
-                }
-            }
-            None => {
-                let ray_direction = r.direction.to_unit_vector();
-                // y is [-1,1], so t is [0,1]
-                let t = 0.5 * (ray_direction.y + 1.0);
-                // linear interpolation between while and a light blue, based on y-component of ray
-                // blendedValue = (1−t)*startValue + t * endValue
-                (1.0 - t) * Color::WHITE + t * Color::new(0.5, 0.7, 1.0)
-            }
-        }
-    }
-
     pub fn sample_pixel(
         &self,
         i: usize,
         j: usize,
         objects: &HittableObjects,
-        max_depth: i32,
+        integrator: &dyn Integrator,
         w: f64,
         h: f64,
     ) -> Color {
-        let x = rand::thread_rng().gen::<f64>();
-        let y = rand::thread_rng().gen::<f64>();
+        let x = random::rng().gen::<f64>();
+        let y = random::rng().gen::<f64>();
         let u = ((i as f64) + x) / w;
         let v = ((j as f64) + y) / h;
-        let r = self.create_ray(u, v);
-        self.compute_ray_color(r, objects, max_depth)
+        // the rays through a pixel spread over roughly the viewport's
+        // angular height divided by its height in pixels
+        let spread = self.viewport_height / h;
+        let r = self.create_ray(u, v).with_spread(spread);
+        integrator.ray_color(r, objects)
+    }
+
+    /// Renders the scene with the integrator chosen in `render_config`.
+    /// Returns an image of linear, high dynamic range colors, without any
+    /// gamma correction.
+    pub fn render(&self, objects: &HittableObjects, render_config: RenderConfig) -> Image {
+        let integrator = render_config.integrator.build(render_config.path_tracer());
+        self.render_with(objects, render_config, integrator.as_ref())
     }
 
-    /// Renders the scene. Returns a Vec of pixels (bytes).
-    pub fn render(&self, objects: &HittableObjects, render_config: RenderConfig) -> Vec<u8> {
+    /// Same as `render`, with any integrator
+    pub fn render_with(
+        &self,
+        objects: &HittableObjects,
+        render_config: RenderConfig,
+        integrator: &dyn Integrator,
+    ) -> Image {
         let width = render_config.width;
         let height = render_config.height;
         let samples_per_pixel = render_config.samples_per_pixel;
-        let max_depth = render_config.max_depth;
-        let w = (width as f64) - 1.0;
-        let h = (height as f64) - 1.0;
+        let seed = render_config.seed;
+        // the jitter is in [0, 1), so dividing by the full size keeps every
+        // sample inside the viewport, even for one-pixel-wide images
+        let w = width as f64;
+        let h = height as f64;
 
-        let progress_bar = ProgressBar::new(height as u64);
+        let progress_bar = if render_config.show_progress {
+            ProgressBar::new(height as u64)
+        } else {
+            ProgressBar::hidden()
+        };
 
         // Note that the height coordinate is written backwards
-        // Should be able to parallelize the i and j loops. The sampling loop can't be though.
-        let rows: Vec<Vec<u8>> = (0..height)
+        // Rows are rendered in parallel. The sampling loop stays sequential
+        // so each pixel's random sequence is reproducible.
+        let rows: Vec<Vec<Color>> = (0..height)
             .rev()
             .collect::<Vec<_>>()
             .into_par_iter()
             .map(|j| {
-                let mut row_pixels: Vec<u8> = Vec::with_capacity(width * 3);
+                let mut row_pixels: Vec<Color> = Vec::with_capacity(width);
                 for i in 0..width {
-                    let samples: Vec<usize> = (0..samples_per_pixel).collect();
-                    let color = samples
-                        .par_iter()
-                        .map(|_| self.sample_pixel(i, j, objects, max_depth, w, h))
-                        .collect::<Vec<Color>>()
-                        .iter()
-                        .sum::<Color>();
-
-                    let pixel = color.sample_pixel(samples_per_pixel as u32);
-                    row_pixels.push(pixel.0);
-                    row_pixels.push(pixel.1);
-                    row_pixels.push(pixel.2);
+                    // Each pixel gets its own random sequence, so the image
+                    // doesn't depend on which thread renders which row
+                    random::reseed(random::mix_seed(seed, (j * width + i) as u64));
+                    let mut color = Color::BLACK;
+                    for _ in 0..samples_per_pixel {
+                        color += self.sample_pixel(i, j, objects, integrator, w, h);
+                    }
+
+                    row_pixels.push(color.average_samples(samples_per_pixel as u32));
                 }
                 progress_bar.inc(1);
                 row_pixels
-            }).collect();
+            })
+            .collect();
         progress_bar.finish_with_message("Done.");
-        let binary_pixels: Vec<u8> = rows.into_iter().flatten().collect();
-        binary_pixels
+        let pixels: Vec<Color> = rows.into_iter().flatten().collect();
+        Image::from_pixels(width, height, pixels)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CameraConfig, RenderConfig};
+    use crate::canvas::Resolution;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::shapes::{HittableObjects, Shape, Sphere};
+    use crate::texture::Texture;
+
+    #[test]
+    fn can_render_single_rows_and_columns() {
+        let mut objects = HittableObjects::new();
+        objects.add(Shape::Sphere(Sphere::new(
+            Point3::origin(),
+            1.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        )));
+        let config = CameraConfig {
+            look_from: Point3::new(0., 0., 5.),
+            look_at: Point3::origin(),
+            view_up: Vector3::new(0., 1., 0.),
+            vertical_fov: 40.,
+            aperture: 0.,
+            focus_dist: 5.,
+        };
+        for (width, height) in [(8, 1), (1, 8), (1, 1)] {
+            let mut render_config = RenderConfig::new(Resolution::Custom { width, height }, 2, 4);
+            render_config.show_progress = false;
+            let camera = config.build(render_config.aspect_ratio());
+            let image = camera.render(&objects, render_config);
+            for pixel in image.pixels() {
+                assert!(
+                    pixel.red.is_finite() && pixel.green.is_finite() && pixel.blue.is_finite(),
+                    "{}x{}: {:?}",
+                    width,
+                    height,
+                    pixel
+                );
+            }
+        }
     }
 }
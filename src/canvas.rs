@@ -1,6 +1,8 @@
+use std::str::FromStr;
+
 pub const ASPECT_RATIO: f64 = 16.0 / 9.0;
 
-#[derive(Copy, Clone, Debug)]
+#[derive(Copy, Clone, Debug, PartialEq)]
 pub enum Resolution {
     _240p,
     _360p,
@@ -10,6 +12,11 @@ pub enum Resolution {
     _2K,
     _4K,
     _5K,
+    /// Any other size, in pixels
+    Custom {
+        width: usize,
+        height: usize,
+    },
 }
 
 impl Resolution {
@@ -23,6 +30,49 @@ impl Resolution {
             Resolution::_2K => 1440,
             Resolution::_4K => 2160,
             Resolution::_5K => 2880,
+            Resolution::Custom { height, .. } => *height,
+        }
+    }
+
+    /// Width in pixels. Named resolutions have a 16:9 aspect ratio.
+    pub fn width(&self) -> usize {
+        match self {
+            Resolution::Custom { width, .. } => *width,
+            _ => ((self.height() as f64) * ASPECT_RATIO) as usize,
+        }
+    }
+}
+
+/// Parses names like "720p" or "4K", ignoring case, or a size in pixels
+/// like "1024x768"
+impl FromStr for Resolution {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let lowercase = s.to_ascii_lowercase();
+        if let Some((width, height)) = lowercase.split_once(['x', '×']) {
+            let parse = |n: &str| n.trim().parse::<usize>().ok().filter(|n| *n > 0);
+            return match (parse(width), parse(height)) {
+                (Some(width), Some(height)) => Ok(Resolution::Custom { width, height }),
+                _ => Err(format!(
+                    "invalid resolution '{}', width and height must be positive whole numbers",
+                    s
+                )),
+            };
+        }
+        match lowercase.as_str() {
+            "240p" => Ok(Resolution::_240p),
+            "360p" => Ok(Resolution::_360p),
+            "480p" => Ok(Resolution::_480p),
+            "720p" => Ok(Resolution::_720p),
+            "1080p" => Ok(Resolution::_1080p),
+            "2k" => Ok(Resolution::_2K),
+            "4k" => Ok(Resolution::_4K),
+            "5k" => Ok(Resolution::_5K),
+            _ => Err(format!(
+                "unknown resolution '{}', expected one of 240p, 360p, 480p, 720p, 1080p, 2K, 4K, 5K or WIDTHxHEIGHT",
+                s
+            )),
         }
     }
 }
@@ -42,6 +92,29 @@ impl CanvasConfig {
     }
 
     pub fn width(self) -> usize {
-        ((self.height() as f64) * ASPECT_RATIO) as usize
+        self.resolution.width()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Resolution;
+
+    #[test]
+    fn can_parse_resolutions() {
+        assert_eq!("720p".parse(), Ok(Resolution::_720p));
+        assert_eq!("4k".parse(), Ok(Resolution::_4K));
+        assert_eq!(
+            "1024x768".parse(),
+            Ok(Resolution::Custom {
+                width: 1024,
+                height: 768
+            })
+        );
+        assert!("1024x0".parse::<Resolution>().is_err());
+        assert!("huge".parse::<Resolution>().is_err());
+
+        let r: Resolution = "1080p".parse().unwrap();
+        assert_eq!((r.width(), r.height()), (1920, 1080));
     }
 }
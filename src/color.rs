@@ -1,3 +1,4 @@
+use crate::random;
 use rand::prelude::*;
 use std::cmp::PartialEq;
 use std::iter::Sum;
@@ -34,6 +35,49 @@ impl Color {
         )
     }
 
+    /// Like `to_pixel`, with 16 bits per channel
+    pub fn to_pixel16(&self) -> (u16, u16, u16) {
+        (
+            clamp_pixel16(self.red),
+            clamp_pixel16(self.green),
+            clamp_pixel16(self.blue),
+        )
+    }
+
+    /// Perceived brightness of a linear color, using the Rec. 709 weights
+    pub fn luminance(&self) -> f64 {
+        0.2126 * self.red + 0.7152 * self.green + 0.0722 * self.blue
+    }
+
+    pub fn max_component(&self) -> f64 {
+        self.red.max(self.green).max(self.blue)
+    }
+
+    /// Averages the sum of `samples_per_pixel` samples
+    pub fn average_samples(&self, samples_per_pixel: u32) -> Color {
+        *self * (1.0 / (samples_per_pixel as f64))
+    }
+
+    /// Encodes a linear color with the sRGB transfer function, as expected
+    /// by displays and 8-bit image files
+    pub fn encode_srgb(&self) -> Color {
+        Color::new(
+            linear_to_srgb(self.red),
+            linear_to_srgb(self.green),
+            linear_to_srgb(self.blue),
+        )
+    }
+
+    /// Decodes an sRGB-encoded color, such as one read from a PNG file, to
+    /// linear values
+    pub fn decode_srgb(&self) -> Color {
+        Color::new(
+            srgb_to_linear(self.red),
+            srgb_to_linear(self.green),
+            srgb_to_linear(self.blue),
+        )
+    }
+
     pub fn sample_pixel(&self, samples_per_pixel: u32) -> (u8, u8, u8) {
         // divide the color by the number of samples
         let scale = 1.0 / (samples_per_pixel as f64);
@@ -42,14 +86,14 @@ impl Color {
         let b = self.blue * scale;
 
         (
-            clamp_pixel(r.sqrt()),
-            clamp_pixel(g.sqrt()),
-            clamp_pixel(b.sqrt()),
+            clamp_pixel(linear_to_srgb(r)),
+            clamp_pixel(linear_to_srgb(g)),
+            clamp_pixel(linear_to_srgb(b)),
         )
     }
 
     pub fn random() -> Color {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         Color::new(rng.gen(), rng.gen(), rng.gen())
     }
 
@@ -98,6 +142,25 @@ impl Color {
     }
 }
 
+/// The piecewise sRGB transfer function: linear near black, and a 2.4 power
+/// curve elsewhere
+fn linear_to_srgb(c: f64) -> f64 {
+    if c <= 0.0031308 {
+        12.92 * c
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+/// Inverse of `linear_to_srgb`
+fn srgb_to_linear(c: f64) -> f64 {
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
 /// Clamps a color component to [0, 255]
 fn clamp_pixel(c: f64) -> u8 {
     match c {
@@ -107,11 +170,12 @@ fn clamp_pixel(c: f64) -> u8 {
     }
 }
 
-fn clamp_pixel2(x: f64, x_min: f64, x_max: f64) -> f64 {
-    match x {
-        x if x < x_min => x_min,
-        x if x > x_max => x_max,
-        _ => x,
+/// Clamps a color component to [0, 65535]
+fn clamp_pixel16(c: f64) -> u16 {
+    match c {
+        c if c > 1.0f64 => u16::MAX,
+        c if c < 0f64 => 0u16,
+        _ => (65535.0 * c).round() as u16,
     }
 }
 
@@ -128,7 +192,7 @@ impl Add for Color {
     }
 }
 
-impl<'a> Add<&'a Color> for Color {
+impl Add<&Color> for Color {
     type Output = Color;
 
     fn add(self, other: &Color) -> Color {
@@ -203,7 +267,7 @@ impl<'a> Sum<&'a Color> for Color {
 #[cfg(test)]
 mod tests {
     use super::Color;
-    use crate::color::clamp_pixel;
+    use crate::color::{clamp_pixel, clamp_pixel16, linear_to_srgb, srgb_to_linear};
 
     //    #[test]
     //    fn can_add_colors() {
@@ -219,5 +283,23 @@ mod tests {
         assert_eq!(clamp_pixel(c.red), 255u8);
         assert_eq!(clamp_pixel(c.green), 0u8);
         assert_eq!(clamp_pixel(c.blue), 128u8);
+        assert_eq!(clamp_pixel16(c.red), 65535u16);
+        assert_eq!(clamp_pixel16(c.green), 0u16);
+        assert_eq!(clamp_pixel16(c.blue), 32768u16);
+    }
+
+    #[test]
+    fn can_encode_and_decode_srgb() {
+        assert_eq!(linear_to_srgb(0.0), 0.0);
+        assert!((linear_to_srgb(1.0) - 1.0).abs() < 1e-12);
+        assert!((linear_to_srgb(0.5) - 0.735357).abs() < 1e-6);
+        assert!((linear_to_srgb(0.001) - 0.01292).abs() < 1e-12);
+        for i in 0..=100 {
+            let c = i as f64 / 100.0;
+            assert!((srgb_to_linear(linear_to_srgb(c)) - c).abs() < 1e-12);
+        }
+        let color = Color::new(0.2, 0.4, 0.8);
+        let round_trip = color.encode_srgb().decode_srgb();
+        assert!((round_trip.green - color.green).abs() < 1e-12);
     }
 }
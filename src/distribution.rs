@@ -0,0 +1,179 @@
+// distribution.rs
+//
+// Piecewise-constant distributions, for picking samples in proportion to a
+// tabulated function such as the brightness of an image's pixels. See
+// Physically Based Rendering, section 13.6.
+
+/// Picks points in [0, 1) with a density proportional to a step function,
+/// whose `n` steps each cover `1 / n` of the interval
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution1D {
+    func: Vec<f64>,
+    // cdf[i] is the probability of landing before step i
+    cdf: Vec<f64>,
+    integral: f64,
+}
+
+impl Distribution1D {
+    /// Panics if there are no steps or any of them are negative. If every
+    /// step is zero, all points are equally likely.
+    pub fn new(func: Vec<f64>) -> Self {
+        assert!(!func.is_empty(), "a distribution needs at least one step");
+        assert!(
+            func.iter().all(|f| *f >= 0.0),
+            "a distribution can't have negative steps"
+        );
+        let n = func.len() as f64;
+        let mut cdf = Vec::with_capacity(func.len() + 1);
+        cdf.push(0.0);
+        for f in func.iter() {
+            cdf.push(cdf.last().unwrap() + f / n);
+        }
+        let integral = *cdf.last().unwrap();
+        for (i, c) in cdf.iter_mut().enumerate() {
+            *c = if integral > 0.0 {
+                *c / integral
+            } else {
+                i as f64 / n
+            };
+        }
+        Distribution1D {
+            func,
+            cdf,
+            integral,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.func.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.func.is_empty()
+    }
+
+    /// The average value of the step function
+    pub fn integral(&self) -> f64 {
+        self.integral
+    }
+
+    /// Turns `u`, uniform in [0, 1), into a point in [0, 1). Returns the
+    /// point, its density and the step it's in.
+    pub fn sample(&self, u: f64) -> (f64, f64, usize) {
+        // the last step whose cdf is at most u, skipping empty steps
+        let index = (self.cdf.partition_point(|&c| c <= u) - 1).min(self.len() - 1);
+        let width = self.cdf[index + 1] - self.cdf[index];
+        let offset = if width > 0.0 {
+            (u - self.cdf[index]) / width
+        } else {
+            0.0
+        };
+        let x = (index as f64 + offset) / self.len() as f64;
+        (x.min(1.0 - f64::EPSILON), self.pdf(index), index)
+    }
+
+    /// The density of points in step `index`
+    pub fn pdf(&self, index: usize) -> f64 {
+        if self.integral > 0.0 {
+            self.func[index] / self.integral
+        } else {
+            1.0
+        }
+    }
+}
+
+/// Picks points in the unit square with a density proportional to a grid
+/// of values, such as an image's pixels. Rows are picked first, then a
+/// column within the row.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Distribution2D {
+    rows: Vec<Distribution1D>,
+    marginal: Distribution1D,
+}
+
+impl Distribution2D {
+    /// `values` holds `height` rows of `width` values each. Panics if the
+    /// grid is empty or has the wrong number of values.
+    pub fn new(values: &[f64], width: usize, height: usize) -> Self {
+        assert!(
+            width > 0 && height > 0 && values.len() == width * height,
+            "a {}x{} grid needs {} values",
+            width,
+            height,
+            width * height
+        );
+        let rows: Vec<Distribution1D> = values
+            .chunks(width)
+            .map(|row| Distribution1D::new(row.to_vec()))
+            .collect();
+        let marginal = Distribution1D::new(rows.iter().map(|row| row.integral()).collect());
+        Distribution2D { rows, marginal }
+    }
+
+    /// Turns two uniform numbers in [0, 1) into a point `(x, y)` in the
+    /// unit square, with `y` down the rows. Returns the point and its
+    /// density.
+    pub fn sample(&self, u1: f64, u2: f64) -> ((f64, f64), f64) {
+        let (y, row_pdf, row) = self.marginal.sample(u2);
+        let (x, column_pdf, _) = self.rows[row].sample(u1);
+        ((x, y), row_pdf * column_pdf)
+    }
+
+    /// The density of `sample` picking the point `(x, y)`
+    pub fn pdf(&self, x: f64, y: f64) -> f64 {
+        let row = ((y * self.rows.len() as f64) as usize).min(self.rows.len() - 1);
+        let width = self.rows[row].len();
+        let column = ((x * width as f64) as usize).min(width - 1);
+        self.marginal.pdf(row) * self.rows[row].pdf(column)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Distribution1D, Distribution2D};
+    use crate::random;
+    use rand::Rng;
+
+    #[test]
+    fn can_sample_steps_in_proportion() {
+        let distribution = Distribution1D::new(vec![1., 0., 3.]);
+        assert_eq!(distribution.integral(), 4. / 3.);
+        assert_eq!(distribution.pdf(0), 0.75);
+        assert_eq!(distribution.pdf(1), 0.);
+        assert_eq!(distribution.pdf(2), 2.25);
+
+        assert_eq!(distribution.sample(0.), (0., 0.75, 0));
+        let (x, pdf, index) = distribution.sample(0.125);
+        assert!((x - 1. / 6.).abs() < 1e-12);
+        assert_eq!((pdf, index), (0.75, 0));
+        // the empty step is skipped over
+        let (x, pdf, index) = distribution.sample(0.25);
+        assert!((x - 2. / 3.).abs() < 1e-12);
+        assert_eq!((pdf, index), (2.25, 2));
+        assert!(distribution.sample(0.999_999_999).0 < 1.);
+
+        let flat = Distribution1D::new(vec![0., 0.]);
+        assert_eq!(flat.sample(0.75), (0.75, 1., 1));
+    }
+
+    #[test]
+    fn can_sample_grid_in_proportion() {
+        random::reseed(1);
+        let values = [1., 2., 0., 0., 5., 0.];
+        let distribution = Distribution2D::new(&values, 3, 2);
+        let samples = 100_000;
+        let mut counts = [0; 6];
+        for _ in 0..samples {
+            let mut rng = random::rng();
+            let ((x, y), pdf) = distribution.sample(rng.gen(), rng.gen());
+            assert_eq!(pdf, distribution.pdf(x, y));
+            counts[(y * 2.) as usize * 3 + (x * 3.) as usize] += 1;
+        }
+        for (count, value) in counts.iter().zip(values.iter()) {
+            let expected = value / 8.;
+            assert!((*count as f64 / samples as f64 - expected).abs() < 0.01);
+        }
+        // the density is per unit area, so it averages to 1
+        assert_eq!(distribution.pdf(0.5, 0.75), 5. / 8. * 6.);
+    }
+}
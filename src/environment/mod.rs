@@ -0,0 +1,309 @@
+use crate::color::Color;
+use crate::distribution::Distribution2D;
+use crate::geom::Vector3;
+use crate::image::Image;
+use crate::random;
+use crate::ray::Ray;
+use rand::Rng;
+use std::f64::consts::PI;
+
+mod sky;
+
+pub use sky::{Sky, MAX_TURBIDITY, MIN_TURBIDITY, SUN_ANGULAR_RADIUS};
+
+// environment/mod.rs
+//
+// The environment is what rays see when they leave the scene without
+// hitting anything. It lights the scene too, like a sky infinitely far away.
+// Environment maps can be sampled like lights, picking directions in
+// proportion to the brightness of the map, so that a small bright sun in
+// an HDR photo lights the scene without fireflies. The analytic sky samples
+// its sun the same way.
+
+/// Color of the sky gradient at the bottom, looking straight down
+pub const SKY_BOTTOM: Color = Color {
+    red: 1.0,
+    green: 1.0,
+    blue: 1.0,
+};
+/// Color of the sky gradient at the top, looking straight up
+pub const SKY_TOP: Color = Color {
+    red: 0.5,
+    green: 0.7,
+    blue: 1.0,
+};
+
+/// What a ray sees when it doesn't hit anything
+#[derive(Debug, Clone, PartialEq)]
+pub enum Environment {
+    /// Nothing at all. Suits scenes that are lit only by emissive materials.
+    Black,
+    /// The same color in every direction
+    Constant(Color),
+    /// A vertical gradient, blended linearly between `bottom` when looking
+    /// straight down and `top` when looking straight up
+    Gradient { bottom: Color, top: Color },
+    /// A high dynamic range photo of the surroundings
+    Map(EnvironmentMap),
+    /// A physically based daylight sky, with the sun
+    Sky(Sky),
+}
+
+impl Default for Environment {
+    /// The sky from the book, which fades from white to light blue
+    fn default() -> Self {
+        Environment::Gradient {
+            bottom: SKY_BOTTOM,
+            top: SKY_TOP,
+        }
+    }
+}
+
+impl Environment {
+    /// The light arriving from the direction `r` is heading in
+    pub fn color(&self, r: &Ray) -> Color {
+        match self {
+            Environment::Black => Color::BLACK,
+            Environment::Constant(color) => *color,
+            Environment::Gradient { bottom, top } => {
+                let ray_direction = r.direction.to_unit_vector();
+                // y is [-1,1], so t is [0,1]
+                let t = 0.5 * (ray_direction.y + 1.0);
+                (1.0 - t) * *bottom + t * *top
+            }
+            Environment::Map(map) => map.color(&r.direction),
+            Environment::Sky(sky) => sky.color(&r.direction),
+        }
+    }
+
+    /// Picks a direction to look for light in. Only environment maps and
+    /// the sky's sun are sampled; the others are left to scattered rays.
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        match self {
+            Environment::Map(map) => map.sample(),
+            Environment::Sky(sky) => sky.sample(),
+            _ => None,
+        }
+    }
+
+    /// Probability density, per unit solid angle, of `sample` picking
+    /// `direction`
+    pub fn pdf(&self, direction: &Vector3) -> f64 {
+        match self {
+            Environment::Map(map) => map.pdf(direction),
+            Environment::Sky(sky) => sky.pdf(direction),
+            _ => 0.0,
+        }
+    }
+}
+
+/// A direction picked by `Environment::sample`
+#[derive(Debug, Copy, Clone)]
+pub struct EnvironmentSample {
+    /// Unit vector towards the environment
+    pub direction: Vector3,
+    /// The light arriving from `direction`
+    pub radiance: Color,
+    /// Probability density per unit solid angle of picking `direction`
+    pub pdf: f64,
+}
+
+/// An equirectangular (latitude-longitude) image wrapped around the scene.
+/// The top row is straight up and the bottom row straight down. The middle
+/// of the image is in the -z direction, and the image's left and right
+/// edges meet at +z, with +x a quarter of the way in from the right.
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnvironmentMap {
+    image: Image,
+    /// Turns the map about the y axis, in degrees. Positive angles turn it
+    /// anticlockwise, looking down from above.
+    rotation: f64,
+    /// Scales the image's colors
+    intensity: f64,
+    // for picking pixels in proportion to the light they give off
+    distribution: Distribution2D,
+}
+
+impl EnvironmentMap {
+    /// Panics if the image is empty
+    pub fn new(image: Image, rotation: f64, intensity: f64) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "an environment map can't be empty"
+        );
+        // pixels near the poles cover less of the sky, so they're less likely
+        // to be picked
+        let (width, height) = (image.width(), image.height());
+        let weights: Vec<f64> = (0..height)
+            .flat_map(|y| {
+                let sin_theta = (PI * (y as f64 + 0.5) / height as f64).sin();
+                let image = &image;
+                (0..width).map(move |x| image.get(x, y).luminance().max(0.0) * sin_theta)
+            })
+            .collect();
+        let distribution = Distribution2D::new(&weights, width, height);
+        EnvironmentMap {
+            image,
+            rotation,
+            intensity,
+            distribution,
+        }
+    }
+
+    pub fn image(&self) -> &Image {
+        &self.image
+    }
+
+    /// The position in the image, with both coordinates in [0, 1], that's
+    /// seen looking along `direction`
+    pub fn direction_to_uv(&self, direction: &Vector3) -> (f64, f64) {
+        let d = direction.to_unit_vector();
+        let phi = d.x.atan2(-d.z) + self.rotation.to_radians();
+        let u = (0.5 + phi / (2.0 * PI)).rem_euclid(1.0);
+        let v = d.y.clamp(-1.0, 1.0).acos() / PI;
+        (u, v)
+    }
+
+    /// The unit direction that's seen at `(u, v)` in the image
+    pub fn uv_to_direction(&self, u: f64, v: f64) -> Vector3 {
+        let phi = (u - 0.5) * 2.0 * PI - self.rotation.to_radians();
+        let theta = v * PI;
+        Vector3::new(
+            theta.sin() * phi.sin(),
+            theta.cos(),
+            -theta.sin() * phi.cos(),
+        )
+    }
+
+    /// The color of the pixel seen looking along `direction`. Pixels aren't
+    /// blended, so each one lights the scene from its own patch of sky.
+    pub fn color(&self, direction: &Vector3) -> Color {
+        let (u, v) = self.direction_to_uv(direction);
+        let (width, height) = (self.image.width(), self.image.height());
+        let x = ((u * width as f64) as usize).min(width - 1);
+        let y = ((v * height as f64) as usize).min(height - 1);
+        self.image.get(x, y) * self.intensity
+    }
+
+    /// Picks a direction in proportion to the brightness of the map
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        let mut rng = random::rng();
+        let ((u, v), uv_pdf) = self.distribution.sample(rng.gen(), rng.gen());
+        let sin_theta = (v * PI).sin();
+        if uv_pdf == 0.0 || sin_theta <= 0.0 {
+            return None;
+        }
+        let direction = self.uv_to_direction(u, v);
+        Some(EnvironmentSample {
+            direction,
+            radiance: self.color(&direction),
+            pdf: uv_pdf / (2.0 * PI * PI * sin_theta),
+        })
+    }
+
+    /// Probability density, per unit solid angle, of `sample` picking
+    /// `direction`
+    pub fn pdf(&self, direction: &Vector3) -> f64 {
+        let (u, v) = self.direction_to_uv(direction);
+        let sin_theta = (v * PI).sin();
+        if sin_theta <= 0.0 {
+            return 0.0;
+        }
+        // the image's (u, v) square is stretched over 2π by π radians, and
+        // rows shrink by sin θ towards the poles
+        self.distribution.pdf(u, v) / (2.0 * PI * PI * sin_theta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Environment, EnvironmentMap, SKY_BOTTOM, SKY_TOP};
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::image::Image;
+    use crate::random;
+    use crate::ray::Ray;
+
+    fn looking(x: f64, y: f64, z: f64) -> Ray {
+        Ray::new(Point3::origin(), Vector3::new(x, y, z))
+    }
+
+    #[test]
+    fn can_blend_gradient() {
+        let sky = Environment::default();
+        assert_eq!(sky.color(&looking(0., -2., 0.)), SKY_BOTTOM);
+        assert_eq!(sky.color(&looking(0., 3., 0.)), SKY_TOP);
+        assert_eq!(
+            sky.color(&looking(1., 0., 0.)),
+            0.5 * SKY_BOTTOM + 0.5 * SKY_TOP
+        );
+        let red = Color::new(1., 0., 0.);
+        assert_eq!(Environment::Constant(red).color(&looking(0., 1., 0.)), red);
+        assert_eq!(Environment::Black.color(&looking(0., 1., 0.)), Color::BLACK);
+    }
+
+    #[test]
+    fn can_look_up_environment_map() {
+        // 4 columns, for the quarters of the sky between +z, -x, -z and +x,
+        // above a dark bottom row
+        let columns = [
+            Color::new(1., 0., 0.),
+            Color::new(0., 1., 0.),
+            Color::new(0., 0., 1.),
+            Color::new(1., 1., 0.),
+        ];
+        let mut pixels = columns.to_vec();
+        pixels.extend(vec![Color::new(0.1, 0.1, 0.1); 4]);
+        let image = Image::from_pixels(4, 2, pixels);
+        let map = EnvironmentMap::new(image.clone(), 0., 2.);
+        let environment = Environment::Map(map.clone());
+        let up = 0.5;
+        let quarters = [(-1., 1.), (-1., -1.), (1., -1.), (1., 1.)];
+        for (&(x, z), column) in quarters.iter().zip(columns.iter()) {
+            assert_eq!(environment.color(&looking(x, up, z)), *column * 2.);
+        }
+        assert_eq!(
+            environment.color(&looking(0., -1., 0.)),
+            Color::new(0.2, 0.2, 0.2)
+        );
+
+        // a quarter turn anticlockwise brings what was towards -x and -z
+        // round to -x and +z
+        let turned = EnvironmentMap::new(image, 90., 1.);
+        assert_eq!(turned.color(&Vector3::new(-1., up, 1.)), columns[1]);
+
+        for &(u, v) in &[(0.1, 0.2), (0.6, 0.5), (0.9, 0.95)] {
+            for map in &[&map, &turned] {
+                let (u2, v2) = map.direction_to_uv(&map.uv_to_direction(u, v));
+                assert!((u - u2).abs() < 1e-9 && (v - v2).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn sampling_favors_bright_pixels() {
+        random::reseed(1);
+        let mut image = Image::from_pixels(8, 4, vec![Color::new(1., 1., 1.); 32]);
+        image.set(5, 2, Color::new(100., 100., 100.));
+        let map = EnvironmentMap::new(image, 0., 1.);
+        let samples = 10_000;
+        let mut bright = 0;
+        let mut inverse_pdf_sum = 0.;
+        for _ in 0..samples {
+            let sample = map.sample().unwrap();
+            let pdf = map.pdf(&sample.direction);
+            assert!((sample.pdf - pdf).abs() < 1e-9 * pdf);
+            assert_eq!(sample.radiance, map.color(&sample.direction));
+            if sample.radiance.red == 100. {
+                bright += 1;
+            }
+            inverse_pdf_sum += 1. / sample.pdf;
+        }
+        // the bright pixel gives off about four times as much light as all
+        // the others put together
+        assert!(bright as f64 / samples as f64 > 0.7);
+        // the density covers the whole sphere
+        let solid_angle = inverse_pdf_sum / samples as f64;
+        assert!((solid_angle - 4. * std::f64::consts::PI).abs() < 0.1);
+    }
+}
@@ -0,0 +1,306 @@
+use super::EnvironmentSample;
+use crate::color::Color;
+use crate::geom::{random_in_cone, Vector3};
+use std::f64::consts::PI;
+
+// Preetham, Shirley and Smits' analytic model of the daylight sky, from "A
+// Practical Analytic Model for Daylight" (SIGGRAPH 1999). The sky's
+// brightness and color come from Perez's formula, fitted to turbidity and
+// the height of the sun. The sun itself is a disk, dimmed and reddened by
+// the air it shines through.
+
+/// Half the angle the sun covers seen from the Earth, in radians
+pub const SUN_ANGULAR_RADIUS: f64 = 0.004_625;
+
+/// The clearest sky the model covers. Clean mountain air is about 2.
+pub const MIN_TURBIDITY: f64 = 1.7;
+/// The haziest sky the model covers
+pub const MAX_TURBIDITY: f64 = 10.0;
+
+/// The model's luminances are in kcd/m². Scaling them by this makes a clear
+/// sky about as bright as the book's gradient.
+const LUMINANCE_SCALE: f64 = 0.1;
+
+/// The sun's luminance before it enters the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f64 = 1.6e6;
+
+/// Wavelengths, in micrometres, that stand for red, green and blue when
+/// working out how much sunlight the atmosphere lets through
+const WAVELENGTHS: [f64; 3] = [0.680, 0.550, 0.440];
+
+/// Coefficients A to E of Perez's formula for how the sky's brightness
+/// varies with the angle from the zenith and from the sun
+#[derive(Copy, Clone, Debug, PartialEq)]
+struct Perez([f64; 5]);
+
+impl Perez {
+    /// Luminance (Y) and chromaticity (x and y) coefficients, from the
+    /// paper's appendix
+    fn coefficients(turbidity: f64) -> [Perez; 3] {
+        let t = turbidity;
+        [
+            Perez([
+                0.1787 * t - 1.4630,
+                -0.3554 * t + 0.4275,
+                -0.0227 * t + 5.3251,
+                0.1206 * t - 2.5771,
+                -0.0670 * t + 0.3703,
+            ]),
+            Perez([
+                -0.0193 * t - 0.2592,
+                -0.0665 * t + 0.0008,
+                -0.0004 * t + 0.2125,
+                -0.0641 * t - 0.8989,
+                -0.0033 * t + 0.0452,
+            ]),
+            Perez([
+                -0.0167 * t - 0.2608,
+                -0.0950 * t + 0.0092,
+                -0.0079 * t + 0.2102,
+                -0.0441 * t - 1.6537,
+                -0.0109 * t + 0.0529,
+            ]),
+        ]
+    }
+
+    /// `cos_theta` is for the angle from the zenith, and `gamma` is the
+    /// angle from the sun
+    fn value(&self, cos_theta: f64, gamma: f64) -> f64 {
+        let [a, b, c, d, e] = self.0;
+        let cos_gamma = gamma.cos();
+        (1.0 + a * (b / cos_theta).exp())
+            * (1.0 + c * (d * gamma).exp() + e * cos_gamma * cos_gamma)
+    }
+}
+
+/// Chromaticity at the zenith: `[T², T, 1] M [θ³, θ², θ, 1]` for turbidity
+/// `T` and the sun's angle from the zenith `θ`
+fn zenith_chromaticity(m: [[f64; 4]; 3], turbidity: f64, theta_sun: f64) -> f64 {
+    let t = [turbidity * turbidity, turbidity, 1.0];
+    let theta = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    (0..3)
+        .map(|i| t[i] * (0..4).map(|j| m[i][j] * theta[j]).sum::<f64>())
+        .sum()
+}
+
+/// Converts CIE xyY to linear sRGB, dropping colors outside its gamut
+fn xyy_to_rgb(x: f64, y: f64, luminance: f64) -> Color {
+    if y <= 0.0 {
+        return Color::BLACK;
+    }
+    let big_x = x / y * luminance;
+    let big_z = (1.0 - x - y) / y * luminance;
+    Color::new(
+        (3.2406 * big_x - 1.5372 * luminance - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * luminance + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * luminance + 1.0570 * big_z).max(0.0),
+    )
+}
+
+/// The fraction of sunlight at each of `WAVELENGTHS` that makes it through
+/// the atmosphere, from Rayleigh scattering by the air and Ångström's
+/// formula for haze
+fn sun_transmittance(turbidity: f64, theta_sun: f64) -> [f64; 3] {
+    // relative air mass (Kasten), which stays finite at the horizon
+    let air_mass = 1.0 / (theta_sun.cos() + 0.15 * (93.885 - theta_sun.to_degrees()).powf(-1.253));
+    let beta = 0.04608 * turbidity - 0.04586;
+    let mut transmittance = [0.0; 3];
+    for (t, lambda) in transmittance.iter_mut().zip(WAVELENGTHS.iter()) {
+        let rayleigh = 0.008735 * lambda.powf(-4.08);
+        let haze = beta * lambda.powf(-1.3);
+        *t = (-air_mass * (rayleigh + haze)).exp();
+    }
+    transmittance
+}
+
+/// Density, per unit solid angle, of picking each direction on the sun
+fn sun_pdf() -> f64 {
+    1.0 / (2.0 * PI * (1.0 - SUN_ANGULAR_RADIUS.cos()))
+}
+
+/// A clear daytime sky, lit by the sun
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sky {
+    sun_direction: Vector3,
+    turbidity: f64,
+    // luminance and chromaticity at the zenith, each divided by Perez's
+    // formula there, so the formula can be scaled to any direction
+    zenith: [f64; 3],
+    perez: [Perez; 3],
+    // scales the model's luminances into radiance
+    scale: f64,
+    sun_radiance: Color,
+}
+
+impl Sky {
+    /// A sky with the sun in `sun_direction`. `turbidity` is how hazy the
+    /// air is, and `intensity` scales both the sky and the sun. Panics if
+    /// the sun is below the horizon or the turbidity is out of range.
+    pub fn new(sun_direction: Vector3, turbidity: f64, intensity: f64) -> Self {
+        let sun_direction = sun_direction.to_unit_vector();
+        assert!(sun_direction.y >= 0.0, "the sun must be above the horizon");
+        assert!(
+            (MIN_TURBIDITY..=MAX_TURBIDITY).contains(&turbidity),
+            "turbidity must be between {} and {}",
+            MIN_TURBIDITY,
+            MAX_TURBIDITY
+        );
+        let theta_sun = sun_direction.y.min(1.0).acos();
+        let chi = (4.0 / 9.0 - turbidity / 120.0) * (PI - 2.0 * theta_sun);
+        let zenith_luminance =
+            (4.0453 * turbidity - 4.9710) * chi.tan() - 0.2155 * turbidity + 2.4192;
+        let zenith_x = zenith_chromaticity(
+            [
+                [0.00166, -0.00375, 0.00209, 0.0],
+                [-0.02903, 0.06377, -0.03202, 0.00394],
+                [0.11693, -0.21196, 0.06052, 0.25886],
+            ],
+            turbidity,
+            theta_sun,
+        );
+        let zenith_y = zenith_chromaticity(
+            [
+                [0.00275, -0.00610, 0.00317, 0.0],
+                [-0.04214, 0.08970, -0.04153, 0.00516],
+                [0.15346, -0.26756, 0.06670, 0.26688],
+            ],
+            turbidity,
+            theta_sun,
+        );
+        let perez = Perez::coefficients(turbidity);
+        let zenith = [zenith_luminance, zenith_x, zenith_y];
+        let mut normalized = [0.0; 3];
+        for i in 0..3 {
+            normalized[i] = zenith[i] / perez[i].value(1.0, theta_sun);
+        }
+
+        let scale = LUMINANCE_SCALE * intensity;
+        let [red, green, blue] = sun_transmittance(turbidity, theta_sun);
+        Sky {
+            sun_direction,
+            turbidity,
+            zenith: normalized,
+            perez,
+            scale,
+            sun_radiance: Color::new(red, green, blue) * (SUN_LUMINANCE * scale),
+        }
+    }
+
+    pub fn sun_direction(&self) -> Vector3 {
+        self.sun_direction
+    }
+
+    pub fn turbidity(&self) -> f64 {
+        self.turbidity
+    }
+
+    /// The light arriving from `direction`, from the sky and the sun.
+    /// Below the horizon, the sky looks the same as at the horizon.
+    pub fn color(&self, direction: &Vector3) -> Color {
+        let d = direction.to_unit_vector();
+        let cos_gamma = d.dot(&self.sun_direction).clamp(-1.0, 1.0);
+        // Perez's formula blows up at the horizon, where the cosine is 0
+        let cos_theta = d.y.max(1e-3);
+        let gamma = cos_gamma.acos();
+        let [luminance, x, y] =
+            [0, 1, 2].map(|i| self.zenith[i] * self.perez[i].value(cos_theta, gamma));
+        let sky = xyy_to_rgb(x, y, luminance) * self.scale;
+        if cos_gamma >= SUN_ANGULAR_RADIUS.cos() {
+            sky + self.sun_radiance
+        } else {
+            sky
+        }
+    }
+
+    /// Picks a direction on the sun's disk. The sky is left to scattered
+    /// rays, since it varies slowly.
+    pub fn sample(&self) -> Option<EnvironmentSample> {
+        if self.sun_radiance == Color::BLACK {
+            return None;
+        }
+        let direction = random_in_cone(&self.sun_direction, SUN_ANGULAR_RADIUS.cos());
+        Some(EnvironmentSample {
+            direction,
+            radiance: self.color(&direction),
+            pdf: sun_pdf(),
+        })
+    }
+
+    /// Probability density, per unit solid angle, of `sample` picking
+    /// `direction`
+    pub fn pdf(&self, direction: &Vector3) -> f64 {
+        let cosine = direction.to_unit_vector().dot(&self.sun_direction);
+        if self.sun_radiance == Color::BLACK || cosine < SUN_ANGULAR_RADIUS.cos() {
+            0.0
+        } else {
+            sun_pdf()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Sky, SUN_ANGULAR_RADIUS};
+    use crate::color::Color;
+    use crate::geom::Vector3;
+    use crate::random;
+
+    fn sun_at(elevation: f64) -> Vector3 {
+        let elevation = elevation.to_radians();
+        Vector3::new(0.0, elevation.sin(), -elevation.cos())
+    }
+
+    #[test]
+    fn sky_is_bright_near_sun_and_blue_overhead() {
+        let sky = Sky::new(sun_at(30.0), 3.0, 1.0);
+        let near_sun = sky.color(&sun_at(35.0));
+        let away_from_sun = sky.color(&Vector3::new(0.0, 0.5, 1.0));
+        assert!(near_sun.luminance() > 2.0 * away_from_sun.luminance());
+        let overhead = sky.color(&Vector3::new(0.0, 1.0, 0.0));
+        assert!(overhead.blue > overhead.red);
+        assert!(overhead.luminance() > 0.1 && overhead.luminance() < 5.0);
+        // a hazy sky is whiter
+        let hazy = Sky::new(sun_at(30.0), 9.0, 1.0).color(&Vector3::new(0.0, 1.0, 0.0));
+        assert!(hazy.red / hazy.blue > overhead.red / overhead.blue);
+    }
+
+    #[test]
+    fn sun_is_dimmer_and_redder_when_low() {
+        let noon = Sky::new(sun_at(80.0), 3.0, 1.0);
+        let evening = Sky::new(sun_at(3.0), 3.0, 1.0);
+        let (noon, evening) = (
+            noon.color(&noon.sun_direction()),
+            evening.color(&evening.sun_direction()),
+        );
+        assert!(noon.luminance() > 1e4);
+        assert!(evening.luminance() < noon.luminance());
+        assert!(evening.red / evening.blue > 2.0 * noon.red / noon.blue);
+        // just off the edge of the disk, there's only sky
+        let edge = (SUN_ANGULAR_RADIUS * 1.1).to_degrees();
+        let sky = Sky::new(sun_at(80.0), 3.0, 1.0);
+        assert!(sky.color(&sun_at(80.0 + edge)).luminance() < 100.0);
+    }
+
+    #[test]
+    fn samples_are_on_sun_disk() {
+        random::reseed(1);
+        let sky = Sky::new(Vector3::new(1.0, 1.0, 1.0), 3.0, 2.0);
+        for _ in 0..1000 {
+            let sample = sky.sample().unwrap();
+            let cosine = sample.direction.dot(&sky.sun_direction());
+            assert!(cosine >= SUN_ANGULAR_RADIUS.cos() - 1e-12);
+            assert_eq!(sample.pdf, sky.pdf(&sky.sun_direction()));
+            assert!(sample.radiance.luminance() > 1e4);
+        }
+        assert_eq!(sky.pdf(&Vector3::new(0.0, 1.0, 0.0)), 0.0);
+        // the disk covers about π r² steradians
+        let solid_angle = 1.0 / sky.pdf(&sky.sun_direction());
+        let expected = std::f64::consts::PI * SUN_ANGULAR_RADIUS * SUN_ANGULAR_RADIUS;
+        assert!((solid_angle - expected).abs() < 1e-4 * expected);
+        assert!(Sky::new(sun_at(0.0), 3.0, 0.0).sample().is_none());
+        assert_eq!(
+            Sky::new(sun_at(0.0), 3.0, 0.0).color(&sun_at(0.0)),
+            Color::BLACK
+        );
+    }
+}
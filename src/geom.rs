@@ -1,3 +1,4 @@
+use crate::random;
 use rand::prelude::*;
 use std::cmp::PartialEq;
 use std::ops::{Add, AddAssign, Div, DivAssign, Mul, MulAssign, Neg, Sub, SubAssign};
@@ -88,11 +89,11 @@ impl Vector3 {
 
 /// Returns a random point inside the unit sphere
 pub fn random_point_in_unit_sphere() -> Vector3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     loop {
-        let x: f64 = rng.gen();
-        let y: f64 = rng.gen();
-        let z: f64 = rng.gen();
+        let x: f64 = rng.gen_range(-1.0..1.0);
+        let y: f64 = rng.gen_range(-1.0..1.0);
+        let z: f64 = rng.gen_range(-1.0..1.0);
         let v = Vector3::new(x, y, z);
 
         if v.length_squared() >= 1.0 {
@@ -103,7 +104,7 @@ pub fn random_point_in_unit_sphere() -> Vector3 {
 }
 
 pub fn random_unit_vector() -> Vector3 {
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
     let phi: f64 = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
     let z: f64 = rng.gen_range(-1.0..1.0);
     let r = (1.0 - z * z).sqrt();
@@ -119,9 +120,86 @@ pub fn random_in_hemisphere(normal: &Vector3) -> Vector3 {
     -in_unit_sphere
 }
 
+/// Two unit vectors that, with the unit vector `n`, make a right-handed
+/// orthonormal basis. From Duff et al., "Building an Orthonormal Basis,
+/// Revisited".
+pub fn orthonormal_basis(n: &Vector3) -> (Vector3, Vector3) {
+    let sign = 1.0_f64.copysign(n.z);
+    let a = -1.0 / (sign + n.z);
+    let b = n.x * n.y * a;
+    (
+        Vector3::new(1.0 + sign * n.x * n.x * a, sign * b, -sign * n.x),
+        Vector3::new(b, sign + n.y * n.y * a, -n.y),
+    )
+}
+
+/// Directions relative to a surface: unit tangent and bitangent vectors
+/// along the surface, with its unit normal as the third axis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Frame {
+    pub tangent: Vector3,
+    pub bitangent: Vector3,
+    pub normal: Vector3,
+}
+
+impl Frame {
+    /// The frame at a point where the surface has unit normal `normal` and
+    /// moves along `dpdu` and `dpdv` as its texture coordinates increase.
+    /// The tangent follows `dpdu`, and the bitangent is on the same side of
+    /// it as `dpdv`, which makes the frame left-handed for mirrored
+    /// textures. Any frame will do where `dpdu` is zero or along the normal.
+    pub fn from_tangents(normal: Vector3, dpdu: Vector3, dpdv: Vector3) -> Self {
+        let tangent = dpdu - normal.dot(&dpdu) * normal;
+        if tangent.length_squared() < 1e-20 {
+            let (tangent, bitangent) = orthonormal_basis(&normal);
+            return Frame {
+                tangent,
+                bitangent,
+                normal,
+            };
+        }
+        let tangent = tangent.to_unit_vector();
+        let bitangent = normal.cross(&tangent);
+        Frame {
+            tangent,
+            bitangent: if bitangent.dot(&dpdv) < 0.0 {
+                -bitangent
+            } else {
+                bitangent
+            },
+            normal,
+        }
+    }
+
+    /// `v` in this frame's coordinates, with z along the normal
+    pub fn to_local(&self, v: &Vector3) -> Vector3 {
+        Vector3::new(
+            v.dot(&self.tangent),
+            v.dot(&self.bitangent),
+            v.dot(&self.normal),
+        )
+    }
+
+    /// Inverse of `to_local`
+    pub fn to_world(&self, v: &Vector3) -> Vector3 {
+        v.x * self.tangent + v.y * self.bitangent + v.z * self.normal
+    }
+}
+
+/// A random unit vector at most `acos(cos_max)` from the unit vector
+/// `axis`, with every direction in the cone equally likely
+pub fn random_in_cone(axis: &Vector3, cos_max: f64) -> Vector3 {
+    let mut rng = random::rng();
+    let cos_theta = 1.0 - rng.gen::<f64>() * (1.0 - cos_max);
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let phi = rng.gen_range(0.0..2.0 * std::f64::consts::PI);
+    let (s, t) = orthonormal_basis(axis);
+    (sin_theta * phi.cos()) * s + (sin_theta * phi.sin()) * t + cos_theta * *axis
+}
+
 pub fn random_in_unit_disk() -> Vector3 {
     loop {
-        let mut rng = rand::thread_rng();
+        let mut rng = random::rng();
         let x: f64 = rng.gen_range(-1.0..1.0);
         let y: f64 = rng.gen_range(-1.0..1.0);
         let p = Vector3::new(x, y, 0.0);
@@ -314,7 +392,80 @@ impl DivAssign<f64> for Vector3 {
 
 #[cfg(test)]
 mod tests {
-    use super::{Point3, Vector3};
+    use super::{
+        orthonormal_basis, random_in_cone, random_point_in_unit_sphere, Frame, Point3, Vector3,
+    };
+    use crate::random;
+
+    #[test]
+    fn random_points_fill_unit_sphere() {
+        random::reseed(1);
+        let samples = 10_000;
+        let mut sum = Vector3::new(0.0, 0.0, 0.0);
+        for _ in 0..samples {
+            let p = random_point_in_unit_sphere();
+            assert!(p.length_squared() < 1.0);
+            sum += p;
+        }
+        // points are spread evenly around the center
+        assert!((sum / samples as f64).norm() < 0.05);
+    }
+
+    #[test]
+    fn can_build_orthonormal_basis() {
+        let normals = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(0.0, 0.0, -1.0),
+            Vector3::new(1.0, 2.0, -3.0).to_unit_vector(),
+        ];
+        for n in normals.iter() {
+            let (s, t) = orthonormal_basis(n);
+            for (a, b) in [(s, t), (t, *n), (*n, s)] {
+                assert!(a.dot(&b).abs() < 1e-12);
+                assert!((a.norm() - 1.0).abs() < 1e-12);
+            }
+            assert!((s.cross(&t) - *n).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn frames_follow_tangents() {
+        let normal = Vector3::new(0.0, 0.0, 1.0);
+        let frame = Frame::from_tangents(
+            normal,
+            Vector3::new(2.0, 0.0, 1.0),
+            Vector3::new(0.0, -3.0, 0.0),
+        );
+        assert_eq!(frame.tangent, Vector3::new(1.0, 0.0, 0.0));
+        // mirrored, since v turns the other way round the normal
+        assert_eq!(frame.bitangent, Vector3::new(0.0, -1.0, 0.0));
+        let v = Vector3::new(0.3, -0.4, 0.5);
+        assert_eq!(frame.to_local(&v), Vector3::new(0.3, 0.4, 0.5));
+        assert!((frame.to_world(&frame.to_local(&v)) - v).norm() < 1e-12);
+
+        // without tangents, any frame around the normal
+        let zero = Vector3::new(0.0, 0.0, 0.0);
+        let frame = Frame::from_tangents(normal, zero, zero);
+        assert!(frame.tangent.dot(&normal).abs() < 1e-12);
+        assert!((frame.tangent.cross(&frame.bitangent) - normal).norm() < 1e-12);
+    }
+
+    #[test]
+    fn random_directions_stay_in_cone() {
+        random::reseed(1);
+        let axis = Vector3::new(1.0, 1.0, 0.0).to_unit_vector();
+        let cos_max = 0.9;
+        let samples = 10_000;
+        let mut sum = 0.0;
+        for _ in 0..samples {
+            let d = random_in_cone(&axis, cos_max);
+            assert!((d.norm() - 1.0).abs() < 1e-9);
+            assert!(d.dot(&axis) >= cos_max - 1e-12);
+            sum += d.dot(&axis);
+        }
+        // the cosine is uniform over [cos_max, 1]
+        assert!((sum / samples as f64 - 0.95).abs() < 0.002);
+    }
 
     #[test]
     fn can_add_tuples() {
@@ -0,0 +1,38 @@
+use super::{Image, ImageError};
+use crate::color::Color;
+use std::path::Path;
+
+// OpenEXR files, through the exr crate. `::exr` is the crate, not this module.
+
+fn exr_error(path: &Path) -> impl FnOnce(::exr::error::Error) -> ImageError + '_ {
+    move |error| ImageError::Exr {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+/// Writes 32-bit float RGB channels
+pub(super) fn write(image: &Image, path: &Path) -> Result<(), ImageError> {
+    ::exr::prelude::write_rgb_file(path, image.width, image.height, |x, y| {
+        let color = image.get(x, y);
+        (color.red as f32, color.green as f32, color.blue as f32)
+    })
+    .map_err(exr_error(path))
+}
+
+/// Reads the first layer with RGB channels, ignoring alpha
+pub(super) fn read(path: &Path) -> Result<Image, ImageError> {
+    let file = ::exr::prelude::read_first_rgba_layer_from_file(
+        path,
+        |resolution, _| Image::new(resolution.width(), resolution.height()),
+        |image: &mut Image, position, (r, g, b, _): (f32, f32, f32, f32)| {
+            image.set(
+                position.x(),
+                position.y(),
+                Color::new(r as f64, g as f64, b as f64),
+            )
+        },
+    )
+    .map_err(exr_error(path))?;
+    Ok(file.layer_data.channel_data.pixels)
+}
@@ -0,0 +1,423 @@
+use crate::color::Color;
+use std::fmt;
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+mod exr;
+mod pfm;
+mod png;
+mod ppm;
+mod radiance;
+
+// image/mod.rs
+
+/// A rectangular grid of pixels, stored row by row starting at the top left.
+/// Rendered images hold linear radiance, which can go well above 1.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    /// Creates a black image
+    pub fn new(width: usize, height: usize) -> Self {
+        Image::from_pixels(width, height, vec![Color::BLACK; width * height])
+    }
+
+    /// Panics unless there are `width * height` pixels
+    pub fn from_pixels(width: usize, height: usize, pixels: Vec<Color>) -> Self {
+        assert_eq!(
+            pixels.len(),
+            width * height,
+            "a {}x{} image needs {} pixels",
+            width,
+            height,
+            width * height
+        );
+        Image {
+            width,
+            height,
+            pixels,
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    /// The pixel in column `x` and row `y`, where row 0 is the top
+    pub fn get(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    /// Creates a new image by applying `f` to every pixel
+    pub fn map(&self, f: impl Fn(Color) -> Color) -> Image {
+        Image::from_pixels(
+            self.width,
+            self.height,
+            self.pixels.iter().map(|&color| f(color)).collect(),
+        )
+    }
+
+    /// Pixels as 8-bit RGB triples
+    fn to_bytes(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| {
+                let (r, g, b) = color.to_pixel();
+                [r, g, b]
+            })
+            .collect()
+    }
+
+    /// Pixels as 16-bit RGB triples, with each value stored big-endian
+    fn to_big_endian_words(&self) -> Vec<u8> {
+        self.pixels
+            .iter()
+            .flat_map(|color| {
+                let (r, g, b) = color.to_pixel16();
+                [r, g, b]
+            })
+            .flat_map(u16::to_be_bytes)
+            .collect()
+    }
+}
+
+/// File formats that images can be written in
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ImageFormat {
+    /// Plain text PPM (P3), with 8 bits per channel
+    PpmText,
+    /// Binary PPM (P6), with 8 bits per channel
+    PpmBinary,
+    /// PNG with 8 bits per channel
+    Png8,
+    /// PNG with 16 bits per channel
+    Png16,
+    /// Portable float map, with a 32-bit float per channel
+    Pfm,
+    /// Radiance RGBE, with an 8-bit mantissa per channel and a shared exponent
+    Radiance,
+    /// OpenEXR, with a 32-bit float per channel
+    OpenExr,
+}
+
+impl ImageFormat {
+    /// Picks the format from the file extension: binary PPM for `.ppm`,
+    /// 8-bit PNG for `.png`, and `.pfm`, `.hdr` and `.exr` for the high
+    /// dynamic range formats
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        match extension.as_str() {
+            "ppm" => Some(ImageFormat::PpmBinary),
+            "png" => Some(ImageFormat::Png8),
+            "pfm" => Some(ImageFormat::Pfm),
+            "hdr" => Some(ImageFormat::Radiance),
+            "exr" => Some(ImageFormat::OpenExr),
+            _ => None,
+        }
+    }
+
+    /// Whether the format stores values above 1, so linear images can be
+    /// written without being converted for display first
+    pub fn is_high_dynamic_range(&self) -> bool {
+        matches!(
+            self,
+            ImageFormat::Pfm | ImageFormat::Radiance | ImageFormat::OpenExr
+        )
+    }
+}
+
+/// Errors from reading and writing image files
+#[derive(Debug)]
+pub enum ImageError {
+    /// A file could not be read or written
+    Io { path: PathBuf, error: io::Error },
+    /// The file's extension doesn't match a supported format
+    UnknownFormat { path: PathBuf },
+    /// A file is not a valid image, or uses features that aren't supported
+    Invalid { path: PathBuf, message: String },
+    /// The PNG library could not decode a file
+    PngDecoding {
+        path: PathBuf,
+        error: ::png::DecodingError,
+    },
+    /// The PNG library could not encode an image
+    PngEncoding {
+        path: PathBuf,
+        error: ::png::EncodingError,
+    },
+    /// The OpenEXR library could not read or write a file
+    Exr {
+        path: PathBuf,
+        error: ::exr::error::Error,
+    },
+}
+
+impl fmt::Display for ImageError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImageError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::UnknownFormat { path } => write!(
+                f,
+                "{}: unknown image format, expected a .ppm, .png, .pfm, .hdr or .exr file",
+                path.display()
+            ),
+            ImageError::Invalid { path, message } => write!(f, "{}: {}", path.display(), message),
+            ImageError::PngDecoding { path, error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::PngEncoding { path, error } => write!(f, "{}: {}", path.display(), error),
+            ImageError::Exr { path, error } => write!(f, "{}: {}", path.display(), error),
+        }
+    }
+}
+
+impl std::error::Error for ImageError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ImageError::Io { error, .. } => Some(error),
+            ImageError::PngDecoding { error, .. } => Some(error),
+            ImageError::PngEncoding { error, .. } => Some(error),
+            ImageError::Exr { error, .. } => Some(error),
+            ImageError::UnknownFormat { .. } | ImageError::Invalid { .. } => None,
+        }
+    }
+}
+
+fn io_error(path: &Path) -> impl FnOnce(io::Error) -> ImageError + '_ {
+    move |error| ImageError::Io {
+        path: path.to_path_buf(),
+        error,
+    }
+}
+
+fn invalid(path: &Path, message: impl Into<String>) -> ImageError {
+    ImageError::Invalid {
+        path: path.to_path_buf(),
+        message: message.into(),
+    }
+}
+
+/// Most pixels a file may claim to have, far more than any real image, so a
+/// corrupt header can't ask for more memory than there is
+const MAX_PIXELS: usize = 1 << 28;
+
+/// Number of pixels in an image of the size given by a file's header
+fn pixel_count(width: usize, height: usize, path: &Path) -> Result<usize, ImageError> {
+    width
+        .checked_mul(height)
+        .filter(|&count| count <= MAX_PIXELS)
+        .ok_or_else(|| {
+            invalid(
+                path,
+                format!("image size {}x{} is too large", width, height),
+            )
+        })
+}
+
+/// Writes the image to `path`, in the format given by its extension
+pub fn save_image(image: &Image, path: &Path) -> Result<(), ImageError> {
+    let format = ImageFormat::from_path(path).ok_or_else(|| ImageError::UnknownFormat {
+        path: path.to_path_buf(),
+    })?;
+    write_image(image, path, format)
+}
+
+/// Writes the image to `path` in the given format. High dynamic range
+/// formats store the colors as they are. Other formats clamp them to
+/// [0, 1], so they should already be converted for display.
+pub fn write_image(image: &Image, path: &Path, format: ImageFormat) -> Result<(), ImageError> {
+    if format == ImageFormat::OpenExr {
+        return exr::write(image, path);
+    }
+    let file = File::create(path).map_err(io_error(path))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        ImageFormat::PpmText => ppm::write_text(image, &mut writer).map_err(io_error(path))?,
+        ImageFormat::PpmBinary => ppm::write_binary(image, &mut writer).map_err(io_error(path))?,
+        ImageFormat::Png8 => png::write(image, &mut writer, ::png::BitDepth::Eight, path)?,
+        ImageFormat::Png16 => png::write(image, &mut writer, ::png::BitDepth::Sixteen, path)?,
+        ImageFormat::Pfm => pfm::write(image, &mut writer).map_err(io_error(path))?,
+        ImageFormat::Radiance => radiance::write(image, &mut writer).map_err(io_error(path))?,
+        ImageFormat::OpenExr => unreachable!("OpenEXR files are written above"),
+    }
+    writer.flush().map_err(io_error(path))
+}
+
+/// Reads an image in any of the formats that can be written, whatever the
+/// file's extension. PPM and PNG values are scaled to [0, 1], and no format
+/// gets any gamma correction.
+pub fn read_image(path: &Path) -> Result<Image, ImageError> {
+    let data = fs::read(path).map_err(io_error(path))?;
+    if data.starts_with(b"P3") || data.starts_with(b"P6") {
+        ppm::read(&data, path)
+    } else if data.starts_with(b"\x89PNG") {
+        png::read(&data, path)
+    } else if data.starts_with(b"PF") || data.starts_with(b"Pf") {
+        pfm::read(&data, path)
+    } else if data.starts_with(b"#?") {
+        radiance::read(&data, path)
+    } else if data.starts_with(&[0x76, 0x2f, 0x31, 0x01]) {
+        exr::read(path)
+    } else {
+        Err(invalid(
+            path,
+            "not a PPM, PNG, PFM, Radiance or OpenEXR file",
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_image, save_image, write_image, Image, ImageError, ImageFormat};
+    use crate::color::Color;
+    use std::fs;
+    use std::path::{Path, PathBuf};
+
+    /// A small image covering the full range of each channel
+    fn gradient() -> Image {
+        let (width, height) = (9, 5);
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let u = x as f64 / (width - 1) as f64;
+                let v = y as f64 / (height - 1) as f64;
+                image.set(x, y, Color::new(u, v, 1.0 - u * v));
+            }
+        }
+        image
+    }
+
+    fn test_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("image_test_{}_{}", name, std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    /// Checks that each channel comes back within `tolerance`, relative to
+    /// the pixel's brightest channel if that's above 1
+    fn assert_round_trip(path: &Path, format: ImageFormat, tolerance: f64) {
+        let image = if format.is_high_dynamic_range() {
+            // colors way outside [0, 1], which the PPM and PNG files would clamp
+            gradient().map(|color| Color::new(100. * color.red, color.green, 1e-3 * color.blue))
+        } else {
+            gradient()
+        };
+        write_image(&image, path, format).unwrap();
+        let read = read_image(path).unwrap();
+        assert_eq!(
+            (read.width(), read.height()),
+            (image.width(), image.height())
+        );
+        for (expected, actual) in image.pixels().iter().zip(read.pixels()) {
+            let scale = expected.red.max(expected.green).max(expected.blue).max(1.);
+            let error = (expected.red - actual.red)
+                .abs()
+                .max((expected.green - actual.green).abs())
+                .max((expected.blue - actual.blue).abs());
+            assert!(error <= tolerance * scale, "{:?} vs {:?}", expected, actual);
+        }
+    }
+
+    #[test]
+    fn can_round_trip_every_format() {
+        let dir = test_dir("round_trip");
+        let eight_bit = 0.5 / 255.0 + 1e-9;
+        let sixteen_bit = 0.5 / 65535.0 + 1e-9;
+        assert_round_trip(&dir.join("p3.ppm"), ImageFormat::PpmText, eight_bit);
+        assert_round_trip(&dir.join("p6.ppm"), ImageFormat::PpmBinary, eight_bit);
+        assert_round_trip(&dir.join("8.png"), ImageFormat::Png8, eight_bit);
+        assert_round_trip(&dir.join("16.png"), ImageFormat::Png16, sixteen_bit);
+        assert_round_trip(&dir.join("float.pfm"), ImageFormat::Pfm, 1e-6);
+        assert_round_trip(&dir.join("rgbe.hdr"), ImageFormat::Radiance, 1. / 128.);
+        assert_round_trip(&dir.join("float.exr"), ImageFormat::OpenExr, 1e-6);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clamps_out_of_range_colors() {
+        let dir = test_dir("clamp");
+        let path = dir.join("clamped.png");
+        let image = Image::from_pixels(2, 1, vec![Color::new(2., -1., 0.5), Color::WHITE]);
+        write_image(&image, &path, ImageFormat::Png16).unwrap();
+        let read = read_image(&path).unwrap();
+        assert_eq!(read.get(0, 0).red, 1.);
+        assert_eq!(read.get(0, 0).green, 0.);
+        assert_eq!(read.get(1, 0), Color::WHITE);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn can_choose_format_from_extension() {
+        assert_eq!(
+            ImageFormat::from_path(Path::new("a/b.ppm")),
+            Some(ImageFormat::PpmBinary)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("b.PNG")),
+            Some(ImageFormat::Png8)
+        );
+        assert_eq!(
+            ImageFormat::from_path(Path::new("b.exr")),
+            Some(ImageFormat::OpenExr)
+        );
+        assert_eq!(ImageFormat::from_path(Path::new("b.jpg")), None);
+        assert_eq!(ImageFormat::from_path(Path::new("png")), None);
+
+        let dir = test_dir("extension");
+        save_image(&gradient(), &dir.join("saved.png")).unwrap();
+        assert_eq!(&fs::read(dir.join("saved.png")).unwrap()[1..4], b"PNG");
+        let unknown = save_image(&gradient(), &dir.join("saved.jpg"));
+        assert!(matches!(unknown, Err(ImageError::UnknownFormat { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn can_read_ppm_with_comments() {
+        let dir = test_dir("comments");
+        let path = dir.join("comments.ppm");
+        fs::write(&path, "P3\n# a comment\n2 1 # size\n15\n15 0 0\n0 15 3\n").unwrap();
+        let image = read_image(&path).unwrap();
+        assert_eq!(image.get(0, 0), Color::RED);
+        assert_eq!(image.get(1, 0), Color::new(0., 1., 0.2));
+
+        fs::write(&path, "P3\n2 1\n255\n1 2 3\n").unwrap();
+        assert!(matches!(read_image(&path), Err(ImageError::Invalid { .. })));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn rejects_ppm_with_huge_size() {
+        let dir = test_dir("huge");
+        let path = dir.join("huge.ppm");
+        for header in [
+            "P6\n2000000000 2000000000\n255\n",
+            "P6\n10000 10000\n65535\n",
+            "P3\n100000 100000\n255\n",
+            "P3\n1000 1000\n255\n",
+        ] {
+            fs::write(&path, header).unwrap();
+            let result = read_image(&path);
+            assert!(
+                matches!(result, Err(ImageError::Invalid { .. })),
+                "{}",
+                header
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
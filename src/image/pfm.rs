@@ -0,0 +1,118 @@
+use super::{invalid, pixel_count, Image, ImageError};
+use crate::color::Color;
+use std::convert::TryInto;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Portable float maps (PFM): a short text header followed by raw 32-bit
+// floats, with rows stored from the bottom of the image to the top
+
+pub(super) fn write(image: &Image, writer: &mut impl Write) -> io::Result<()> {
+    // a negative scale means the floats are little-endian
+    write!(writer, "PF\n{} {}\n-1.0\n", image.width, image.height)?;
+    let mut row_data = Vec::with_capacity(image.width * 12);
+    for row in image.pixels.chunks(image.width.max(1)).rev() {
+        row_data.clear();
+        for color in row {
+            for value in [color.red, color.green, color.blue] {
+                row_data.extend_from_slice(&(value as f32).to_le_bytes());
+            }
+        }
+        writer.write_all(&row_data)?;
+    }
+    Ok(())
+}
+
+/// Splits off the first `count` whitespace-separated words of `data`, and
+/// returns them with the position just past the whitespace after the last one
+fn header_words(data: &[u8], count: usize) -> Option<(Vec<&str>, usize)> {
+    let mut words = Vec::with_capacity(count);
+    let mut position = 0;
+    while words.len() < count {
+        while data.get(position)?.is_ascii_whitespace() {
+            position += 1;
+        }
+        let start = position;
+        while !data.get(position)?.is_ascii_whitespace() {
+            position += 1;
+        }
+        words.push(std::str::from_utf8(&data[start..position]).ok()?);
+    }
+    Some((words, position + 1))
+}
+
+/// Reads a color (PF) or grayscale (Pf) file of either byte order
+pub(super) fn read(data: &[u8], path: &Path) -> Result<Image, ImageError> {
+    let header_error = || invalid(path, "invalid PFM header");
+    let (words, start) = header_words(data, 4).ok_or_else(header_error)?;
+    let channels = match words[0] {
+        "PF" => 3,
+        "Pf" => 1,
+        _ => return Err(header_error()),
+    };
+    let width: usize = words[1].parse().map_err(|_| header_error())?;
+    let height: usize = words[2].parse().map_err(|_| header_error())?;
+    let scale: f32 = words[3].parse().map_err(|_| header_error())?;
+    let little_endian = scale < 0.0;
+
+    let count = pixel_count(width, height, path)? * channels;
+    let values: Vec<f64> = data
+        .get(start..start + count * 4)
+        .ok_or_else(|| invalid(path, "PFM file has too few pixels"))?
+        .chunks(4)
+        .map(|bytes| {
+            let bytes: [u8; 4] = bytes.try_into().unwrap();
+            let value = if little_endian {
+                f32::from_le_bytes(bytes)
+            } else {
+                f32::from_be_bytes(bytes)
+            };
+            value as f64
+        })
+        .collect();
+
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in values.chunks(width.max(1) * channels).rev() {
+        pixels.extend(row.chunks(channels).map(|pixel| match pixel {
+            [r, g, b] => Color::new(*r, *g, *b),
+            _ => Color::new(pixel[0], pixel[0], pixel[0]),
+        }));
+    }
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::read;
+    use crate::color::Color;
+    use crate::image::ImageError;
+    use std::path::Path;
+
+    #[test]
+    fn can_read_big_endian_grayscale() {
+        let mut data = b"Pf\n1 2\n1.0\n".to_vec();
+        // bottom row first
+        data.extend_from_slice(&0.25f32.to_be_bytes());
+        data.extend_from_slice(&4.0f32.to_be_bytes());
+        let image = read(&data, Path::new("gray.pfm")).unwrap();
+        assert_eq!(image.get(0, 0), Color::new(4., 4., 4.));
+        assert_eq!(image.get(0, 1), Color::new(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn rejects_huge_sizes() {
+        for header in [
+            "PF\n2000000000 2000000000\n-1.0\n",
+            "PF\n10000000000000000000 1\n-1.0\n",
+            "Pf\n100000 100000\n-1.0\n",
+            "PF\n4000 4000\n-1.0\n",
+        ] {
+            let result = read(header.as_bytes(), Path::new("huge.pfm"));
+            assert!(
+                matches!(result, Err(ImageError::Invalid { .. })),
+                "{}",
+                header
+            );
+        }
+    }
+}
@@ -0,0 +1,79 @@
+use super::{invalid, Image, ImageError};
+use crate::color::Color;
+use std::convert::TryFrom;
+use std::io::{self, Write};
+use std::path::Path;
+
+// PNG files, through the png crate. `::png` is the crate, not this module.
+
+pub(super) fn write(
+    image: &Image,
+    writer: &mut impl Write,
+    bit_depth: ::png::BitDepth,
+    path: &Path,
+) -> Result<(), ImageError> {
+    let png_error = |error| ImageError::PngEncoding {
+        path: path.to_path_buf(),
+        error,
+    };
+    let too_large = || invalid(path, "image is too large for a PNG file");
+    let width = u32::try_from(image.width).map_err(|_| too_large())?;
+    let height = u32::try_from(image.height).map_err(|_| too_large())?;
+
+    let mut encoder = ::png::Encoder::new(writer, width, height);
+    encoder.set_color(::png::ColorType::Rgb);
+    encoder.set_depth(bit_depth);
+    let data = match bit_depth {
+        ::png::BitDepth::Sixteen => image.to_big_endian_words(),
+        _ => image.to_bytes(),
+    };
+    let mut png_writer = encoder.write_header().map_err(png_error)?;
+    png_writer.write_image_data(&data).map_err(png_error)?;
+    png_writer.finish().map_err(png_error)
+}
+
+/// Reads a PNG file of any color type, scaling values to [0, 1]
+pub(super) fn read(data: &[u8], path: &Path) -> Result<Image, ImageError> {
+    let png_error = |error| ImageError::PngDecoding {
+        path: path.to_path_buf(),
+        error,
+    };
+    let mut decoder = ::png::Decoder::new(io::Cursor::new(data));
+    // palettes become RGB and small gray values become bytes
+    decoder.set_transformations(::png::Transformations::EXPAND);
+    let mut reader = decoder.read_info().map_err(png_error)?;
+    let size = reader
+        .output_buffer_size()
+        .ok_or_else(|| invalid(path, "PNG image is too large"))?;
+    let mut buffer = vec![0; size];
+    let info = reader.next_frame(&mut buffer).map_err(png_error)?;
+
+    let channels = info.color_type.samples();
+    let (bytes_per_value, max_value) = match info.bit_depth {
+        ::png::BitDepth::Sixteen => (2, u16::MAX as f64),
+        _ => (1, u8::MAX as f64),
+    };
+    let pixels = buffer[..info.line_size * info.height as usize]
+        .chunks(info.line_size)
+        .flat_map(|row| row.chunks(channels * bytes_per_value))
+        .take(info.width as usize * info.height as usize)
+        .map(|pixel| {
+            let value = |channel: usize| {
+                let bytes = &pixel[channel * bytes_per_value..(channel + 1) * bytes_per_value];
+                bytes.iter().fold(0, |n, &byte| (n << 8) | byte as u32) as f64 / max_value
+            };
+            // alpha is ignored
+            if channels < 3 {
+                let gray = value(0);
+                Color::new(gray, gray, gray)
+            } else {
+                Color::new(value(0), value(1), value(2))
+            }
+        })
+        .collect();
+    Ok(Image::from_pixels(
+        info.width as usize,
+        info.height as usize,
+        pixels,
+    ))
+}
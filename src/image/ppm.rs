@@ -0,0 +1,107 @@
+use super::{invalid, pixel_count, Image, ImageError};
+use crate::color::Color;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Portable pixmaps (PPM), in plain text (P3) and binary (P6) form
+
+/// P3 is human-readable, with one pixel per line
+pub(super) fn write_text(image: &Image, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "P3\n{} {}\n255\n", image.width, image.height)?;
+    for color in &image.pixels {
+        let (r, g, b) = color.to_pixel();
+        writeln!(writer, "{} {} {}", r, g, b)?;
+    }
+    Ok(())
+}
+
+pub(super) fn write_binary(image: &Image, writer: &mut impl Write) -> io::Result<()> {
+    write!(writer, "P6\n{} {}\n255\n", image.width, image.height)?;
+    writer.write_all(&image.to_bytes())
+}
+
+/// Reads whitespace-separated numbers from a PPM file, skipping comments
+struct Tokens<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl Tokens<'_> {
+    fn next_number(&mut self) -> Option<u32> {
+        loop {
+            match self.data.get(self.position)? {
+                b'#' => {
+                    while !matches!(self.data.get(self.position), None | Some(b'\n')) {
+                        self.position += 1;
+                    }
+                }
+                c if c.is_ascii_whitespace() => self.position += 1,
+                _ => break,
+            }
+        }
+        let start = self.position;
+        while matches!(self.data.get(self.position), Some(c) if c.is_ascii_digit()) {
+            self.position += 1;
+        }
+        std::str::from_utf8(&self.data[start..self.position])
+            .ok()?
+            .parse()
+            .ok()
+    }
+}
+
+/// Reads a P3 or P6 file, scaling values to [0, 1]
+pub(super) fn read(data: &[u8], path: &Path) -> Result<Image, ImageError> {
+    let mut tokens = Tokens { data, position: 2 };
+    let mut header = || {
+        tokens
+            .next_number()
+            .ok_or_else(|| invalid(path, "invalid PPM header"))
+    };
+    let width = header()? as usize;
+    let height = header()? as usize;
+    let max_value = header()?;
+    if max_value == 0 || max_value > u16::MAX as u32 {
+        return Err(invalid(
+            path,
+            format!("invalid PPM maximum value {}", max_value),
+        ));
+    }
+    let scale = 1.0 / max_value as f64;
+    let count = pixel_count(width, height, path)? * 3;
+    let too_few_pixels = || invalid(path, "PPM file has too few pixels");
+
+    let values: Vec<u32> = if data.starts_with(b"P3") {
+        // every value takes at least one digit
+        if count > data.len() - tokens.position {
+            return Err(too_few_pixels());
+        }
+        (0..count)
+            .map(|_| tokens.next_number())
+            .collect::<Option<_>>()
+            .ok_or_else(too_few_pixels)?
+    } else {
+        // a single whitespace character separates the header from the pixels
+        let start = tokens.position + 1;
+        let bytes_per_value = if max_value < 256 { 1 } else { 2 };
+        let pixels = data
+            .get(start..start + count * bytes_per_value)
+            .ok_or_else(too_few_pixels)?;
+        pixels
+            .chunks(bytes_per_value)
+            .map(|value| value.iter().fold(0, |n, &byte| (n << 8) | byte as u32))
+            .collect()
+    };
+
+    let pixels = values
+        .chunks(3)
+        .map(|rgb| {
+            Color::new(
+                rgb[0] as f64 * scale,
+                rgb[1] as f64 * scale,
+                rgb[2] as f64 * scale,
+            )
+        })
+        .collect();
+    Ok(Image::from_pixels(width, height, pixels))
+}
@@ -0,0 +1,325 @@
+use super::{invalid, pixel_count, Image, ImageError};
+use crate::color::Color;
+use std::io::{self, Write};
+use std::path::Path;
+
+// Radiance RGBE (.hdr) files. Each pixel is stored as three 8-bit mantissas
+// sharing an 8-bit exponent, and scanlines are usually run-length encoded.
+
+/// Scanlines outside this range of widths can't be run-length encoded
+const MIN_ENCODED_WIDTH: usize = 8;
+const MAX_ENCODED_WIDTH: usize = 0x7fff;
+
+/// Longest run, and longest stretch of literal bytes, in an encoded scanline
+const MAX_RUN: usize = 127;
+const MAX_LITERALS: usize = 128;
+
+/// Runs shorter than this are cheaper to store as literals
+const MIN_RUN: usize = 4;
+
+fn to_rgbe(color: &Color) -> [u8; 4] {
+    let max = color.red.max(color.green).max(color.blue);
+    if max < 1e-32 || !max.is_finite() {
+        // negative, tiny, infinite and NaN values all become black
+        return [0; 4];
+    }
+    // max = mantissa * 2^exponent, with mantissa in [0.5, 1)
+    let mut exponent = max.log2().floor() as i32 + 1;
+    if max / 2f64.powi(exponent) >= 1.0 {
+        exponent += 1;
+    }
+    let scale = 256.0 / 2f64.powi(exponent);
+    let mantissa = |value: f64| (value.max(0.0) * scale).min(255.0) as u8;
+    [
+        mantissa(color.red),
+        mantissa(color.green),
+        mantissa(color.blue),
+        (exponent + 128).clamp(0, 255) as u8,
+    ]
+}
+
+fn from_rgbe(rgbe: [u8; 4]) -> Color {
+    if rgbe[3] == 0 {
+        return Color::BLACK;
+    }
+    // the + 0.5 puts each value in the middle of its range
+    let scale = 2f64.powi(rgbe[3] as i32 - (128 + 8));
+    Color::new(
+        (rgbe[0] as f64 + 0.5) * scale,
+        (rgbe[1] as f64 + 0.5) * scale,
+        (rgbe[2] as f64 + 0.5) * scale,
+    )
+}
+
+pub(super) fn write(image: &Image, writer: &mut impl Write) -> io::Result<()> {
+    write!(
+        writer,
+        "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+        image.height, image.width
+    )?;
+    let width = image.width;
+    let mut encoded = Vec::new();
+    for row in image.pixels.chunks(width.max(1)) {
+        let rgbe: Vec<[u8; 4]> = row.iter().map(to_rgbe).collect();
+        if !(MIN_ENCODED_WIDTH..=MAX_ENCODED_WIDTH).contains(&width) {
+            writer.write_all(&rgbe.concat())?;
+            continue;
+        }
+        // each channel is encoded separately, after a marker giving the width
+        encoded.clear();
+        encoded.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+        for channel in 0..4 {
+            let values: Vec<u8> = rgbe.iter().map(|pixel| pixel[channel]).collect();
+            encode_channel(&values, &mut encoded);
+        }
+        writer.write_all(&encoded)?;
+    }
+    Ok(())
+}
+
+/// Appends runs of a repeated byte as (128 + length, byte), and everything
+/// else as (length, bytes...)
+fn encode_channel(values: &[u8], encoded: &mut Vec<u8>) {
+    let mut start = 0;
+    while start < values.len() {
+        // find the next run that's long enough to be worth encoding
+        let mut run_start = start;
+        let mut run_length = 0;
+        while run_start < values.len() {
+            run_length = values[run_start..]
+                .iter()
+                .take(MAX_RUN)
+                .take_while(|&&value| value == values[run_start])
+                .count();
+            if run_length >= MIN_RUN {
+                break;
+            }
+            run_start += run_length;
+        }
+        if run_start >= values.len() {
+            run_length = 0;
+        }
+
+        for literals in values[start..run_start].chunks(MAX_LITERALS) {
+            encoded.push(literals.len() as u8);
+            encoded.extend_from_slice(literals);
+        }
+        if run_length > 0 {
+            encoded.push(128 + run_length as u8);
+            encoded.push(values[run_start]);
+        }
+        start = run_start + run_length;
+    }
+}
+
+/// Reads a file with the standard `-Y height +X width` orientation, with
+/// scanlines that are flat, run-length encoded, or use the old style of runs
+pub(super) fn read(data: &[u8], path: &Path) -> Result<Image, ImageError> {
+    let header_error =
+        |message: &str| invalid(path, format!("invalid Radiance header, {}", message));
+
+    // the header is a list of lines ending with an empty one
+    let mut position = 0;
+    let mut next_line = || -> Option<&[u8]> {
+        let length = data[position..].iter().position(|&c| c == b'\n')?;
+        let line = &data[position..position + length];
+        position += length + 1;
+        Some(line)
+    };
+    loop {
+        let line = next_line().ok_or_else(|| header_error("no end of header"))?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix(b"FORMAT=") {
+            if format != b"32-bit_rle_rgbe" {
+                return Err(header_error("only RGBE pixels are supported"));
+            }
+        }
+    }
+    let size_line = next_line().ok_or_else(|| header_error("no image size"))?;
+    let size = std::str::from_utf8(size_line).unwrap_or_default();
+    let (height, width) = match size.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => (height.parse().ok(), width.parse().ok()),
+        _ => (None, None),
+    };
+    let (height, width): (usize, usize) = height
+        .zip(width)
+        .ok_or_else(|| header_error("only the -Y height +X width orientation is supported"))?;
+
+    let count = pixel_count(width, height, path)?;
+    // every scanline takes at least one whole pixel
+    if height > (data.len() - position) / 4 {
+        return Err(invalid(path, "Radiance file has too few pixels"));
+    }
+
+    let mut reader = ScanlineReader {
+        data,
+        position,
+        path,
+    };
+    let mut pixels = Vec::with_capacity(count);
+    let mut scanline = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        reader.read_scanline(&mut scanline)?;
+        pixels.extend(scanline.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(Image::from_pixels(width, height, pixels))
+}
+
+struct ScanlineReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    path: &'a Path,
+}
+
+impl ScanlineReader<'_> {
+    fn truncated(&self) -> ImageError {
+        invalid(self.path, "Radiance file has too few pixels")
+    }
+
+    fn byte(&mut self) -> Result<u8, ImageError> {
+        let byte = *self
+            .data
+            .get(self.position)
+            .ok_or_else(|| self.truncated())?;
+        self.position += 1;
+        Ok(byte)
+    }
+
+    fn pixel(&mut self) -> Result<[u8; 4], ImageError> {
+        Ok([self.byte()?, self.byte()?, self.byte()?, self.byte()?])
+    }
+
+    fn read_scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+        let width = scanline.len();
+        let start = self.position;
+        let marker = self.pixel()?;
+        let is_encoded = (MIN_ENCODED_WIDTH..=MAX_ENCODED_WIDTH).contains(&width)
+            && marker[0] == 2
+            && marker[1] == 2
+            && marker[2] < 128;
+        if !is_encoded {
+            self.position = start;
+            return self.read_flat_scanline(scanline);
+        }
+        if ((marker[2] as usize) << 8 | marker[3] as usize) != width {
+            return Err(invalid(self.path, "Radiance scanline has the wrong width"));
+        }
+
+        for channel in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = self.byte()? as usize;
+                let (length, is_run) = if count > 128 {
+                    (count - 128, true)
+                } else {
+                    (count, false)
+                };
+                if length == 0 || x + length > width {
+                    return Err(invalid(self.path, "invalid run in Radiance scanline"));
+                }
+                if is_run {
+                    let value = self.byte()?;
+                    for pixel in &mut scanline[x..x + length] {
+                        pixel[channel] = value;
+                    }
+                } else {
+                    for pixel in &mut scanline[x..x + length] {
+                        pixel[channel] = self.byte()?;
+                    }
+                }
+                x += length;
+            }
+        }
+        Ok(())
+    }
+
+    /// Pixels one after another, where (1, 1, 1, n) repeats the previous
+    /// pixel n times, shifted left by 8 bits for each run in a row
+    fn read_flat_scanline(&mut self, scanline: &mut [[u8; 4]]) -> Result<(), ImageError> {
+        let mut x = 0;
+        let mut shift = 0;
+        while x < scanline.len() {
+            let pixel = self.pixel()?;
+            if pixel[..3] == [1, 1, 1] && x > 0 {
+                let count = (pixel[3] as usize).checked_shl(shift).unwrap_or(usize::MAX);
+                if count > scanline.len() - x {
+                    return Err(invalid(self.path, "invalid run in Radiance scanline"));
+                }
+                let previous = scanline[x - 1];
+                scanline[x..x + count].fill(previous);
+                x += count;
+                shift += 8;
+            } else {
+                scanline[x] = pixel;
+                x += 1;
+                shift = 0;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_channel, from_rgbe, read, to_rgbe};
+    use crate::color::Color;
+    use crate::image::ImageError;
+    use std::path::Path;
+
+    #[test]
+    fn can_convert_rgbe() {
+        assert_eq!(to_rgbe(&Color::BLACK), [0; 4]);
+        assert_eq!(to_rgbe(&Color::new(1., 0.5, 0.)), [128, 64, 0, 129]);
+        for color in [Color::new(1e-3, 2e-3, 3e-3), Color::new(1e4, 1., 0.1)] {
+            let converted = from_rgbe(to_rgbe(&color));
+            assert!((converted.red - color.red).abs() <= color.red / 128.);
+        }
+    }
+
+    #[test]
+    fn can_encode_runs_and_literals() {
+        let mut values = vec![7; 200];
+        values.extend_from_slice(&[1, 2, 3, 3, 4]);
+        let mut encoded = Vec::new();
+        encode_channel(&values, &mut encoded);
+        assert_eq!(encoded, [255, 7, 128 + 73, 7, 5, 1, 2, 3, 3, 4]);
+    }
+
+    #[test]
+    fn can_read_flat_scanlines_with_old_runs() {
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 4\n".to_vec();
+        data.extend_from_slice(&[128, 0, 0, 129, 1, 1, 1, 2, 0, 0, 0, 0]);
+        let image = read(&data, Path::new("old.hdr")).unwrap();
+        assert_eq!(image.get(2, 0), image.get(0, 0));
+        assert!((image.get(0, 0).red - 1.).abs() < 0.01);
+        assert_eq!(image.get(3, 0), Color::BLACK);
+    }
+
+    #[test]
+    fn rejects_huge_sizes() {
+        for size in [
+            "-Y 2000000000 +X 2000000000",
+            "-Y 100000 +X 100000",
+            "-Y 1000000 +X 1",
+        ] {
+            let mut data = format!("#?RADIANCE\n\n{}\n", size).into_bytes();
+            data.extend_from_slice(&[128, 0, 0, 129]);
+            let result = read(&data, Path::new("huge.hdr"));
+            assert!(
+                matches!(result, Err(ImageError::Invalid { .. })),
+                "{}",
+                size
+            );
+        }
+
+        // runs that keep growing can't overflow
+        let mut data = b"#?RADIANCE\n\n-Y 1 +X 4\n".to_vec();
+        data.extend_from_slice(&[128, 0, 0, 129]);
+        for _ in 0..10 {
+            data.extend_from_slice(&[1, 1, 1, 0]);
+        }
+        assert!(read(&data, Path::new("runs.hdr")).is_err());
+    }
+}
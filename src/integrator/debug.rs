@@ -0,0 +1,170 @@
+use super::Integrator;
+use crate::color::Color;
+use crate::geom::{random_unit_vector, Vector3};
+use crate::ray::Ray;
+use crate::shapes::{HittableObjects, Intersection, Interval, INFINITY, SURFACE_EPSILON};
+
+// Integrators that show the geometry rather than the lighting. Rays that
+// miss everything are black.
+
+fn first_hit<'a>(r: &Ray, objects: &'a HittableObjects) -> Option<Intersection<'a>> {
+    objects.hit(r, Interval::new(SURFACE_EPSILON, INFINITY))
+}
+
+/// Colors surfaces by the direction they face: each component of the unit
+/// outward normal is mapped from [-1, 1] to [0, 1]
+#[derive(Copy, Clone, Debug)]
+pub struct NormalsIntegrator;
+
+impl Integrator for NormalsIntegrator {
+    fn ray_color(&self, r: Ray, objects: &HittableObjects) -> Color {
+        match first_hit(&r, objects) {
+            Some(intersect) => {
+                let normal = if intersect.ray_hit_outer_surface {
+                    intersect.normal
+                } else {
+                    -intersect.normal
+                };
+                Color::new(
+                    0.5 * (normal.x + 1.0),
+                    0.5 * (normal.y + 1.0),
+                    0.5 * (normal.z + 1.0),
+                )
+            }
+            None => Color::BLACK,
+        }
+    }
+}
+
+/// Colors surfaces by their distance from the camera, unscaled. Write the
+/// image to a PFM or EXR file to keep the distances, or use the
+/// reinhard-extended tone map, which scales the farthest point to white.
+#[derive(Copy, Clone, Debug)]
+pub struct DepthIntegrator;
+
+impl Integrator for DepthIntegrator {
+    fn ray_color(&self, r: Ray, objects: &HittableObjects) -> Color {
+        match first_hit(&r, objects) {
+            Some(intersect) => {
+                // camera rays aren't unit length
+                let distance = intersect.t * r.direction.norm();
+                Color::new(distance, distance, distance)
+            }
+            None => Color::BLACK,
+        }
+    }
+}
+
+/// Colors surfaces by how little of the hemisphere above them is blocked
+/// by nearby objects: white where nothing is within `distance`, darker in
+/// creases and corners. Directions are weighted by their cosine, as for a
+/// diffuse surface under a uniform sky.
+#[derive(Copy, Clone, Debug)]
+pub struct AmbientOcclusion {
+    pub distance: f64,
+}
+
+impl Integrator for AmbientOcclusion {
+    fn ray_color(&self, r: Ray, objects: &HittableObjects) -> Color {
+        let intersect = match first_hit(&r, objects) {
+            Some(intersect) => intersect,
+            None => return Color::BLACK,
+        };
+        let direction: Vector3 = intersect.normal + random_unit_vector();
+        if direction.length_squared() < 1e-12 {
+            // the random vector cancelled the normal out
+            return Color::WHITE;
+        }
+        let probe = Ray::new(intersect.p, direction.to_unit_vector());
+        let nearby = Interval::new(SURFACE_EPSILON, self.distance);
+        if objects.hit(&probe, nearby).is_some() {
+            Color::BLACK
+        } else {
+            Color::WHITE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{AmbientOcclusion, DepthIntegrator, NormalsIntegrator};
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::integrator::Integrator;
+    use crate::material::Material;
+    use crate::random;
+    use crate::ray::Ray;
+    use crate::shapes::{HittableObjects, Quad, Shape, Sphere};
+    use crate::texture::Texture;
+
+    const WHITE: Material = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+
+    fn unit_sphere() -> HittableObjects {
+        let mut objects = HittableObjects::new();
+        objects.add(Shape::Sphere(Sphere::new(Point3::origin(), 1., WHITE)));
+        objects
+    }
+
+    #[test]
+    fn can_show_normals_and_depth() {
+        let objects = unit_sphere();
+        // a ray of length 2, which hits the sphere 2 units away
+        let r = Ray::new(Point3::new(0., 0., 3.), Vector3::new(0., 0., -2.));
+        assert_eq!(
+            NormalsIntegrator.ray_color(r, &objects),
+            Color::new(0.5, 0.5, 1.)
+        );
+        assert_eq!(
+            DepthIntegrator.ray_color(r, &objects),
+            Color::new(2., 2., 2.)
+        );
+
+        // from inside, the outward normal points away from the viewer
+        let r = Ray::new(Point3::origin(), Vector3::new(1., 0., 0.));
+        assert_eq!(
+            NormalsIntegrator.ray_color(r, &objects),
+            Color::new(1., 0.5, 0.5)
+        );
+
+        let miss = Ray::new(Point3::new(0., 5., 3.), Vector3::new(0., 0., -1.));
+        assert_eq!(NormalsIntegrator.ray_color(miss, &objects), Color::BLACK);
+        assert_eq!(DepthIntegrator.ray_color(miss, &objects), Color::BLACK);
+    }
+
+    #[test]
+    fn ambient_occlusion_darkens_corners() {
+        random::reseed(1);
+        let mut objects = unit_sphere();
+        // a floor, with a wall along one side
+        objects.add(Shape::Quad(Quad::new(
+            Point3::new(-5., -1., -5.),
+            Vector3::new(0., 0., 10.),
+            Vector3::new(10., 0., 0.),
+            WHITE,
+        )));
+        objects.add(Shape::Quad(Quad::new(
+            Point3::new(2.5, -1., -5.),
+            Vector3::new(0., 5., 0.),
+            Vector3::new(0., 0., 10.),
+            WHITE,
+        )));
+        let average = |integrator: &AmbientOcclusion, target: Point3| {
+            let origin = target + Vector3::new(0., 3., 0.);
+            let r = Ray::new(origin, target - origin);
+            let samples = 4000;
+            (0..samples)
+                .map(|_| integrator.ray_color(r, &objects).red)
+                .sum::<f64>()
+                / samples as f64
+        };
+
+        let integrator = AmbientOcclusion { distance: 1. };
+        let open_floor = Point3::new(-4., -1., 4.);
+        let corner = Point3::new(2.4, -1., 4.);
+        assert!(average(&integrator, open_floor) > 0.99);
+        assert!(average(&integrator, corner) < 0.7);
+        // with a short enough distance, even the wall is too far away
+        let integrator = AmbientOcclusion { distance: 0.05 };
+        assert!(average(&integrator, corner) > 0.99);
+    }
+}
@@ -0,0 +1,52 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::shapes::HittableObjects;
+
+mod debug;
+mod path;
+
+pub use debug::{AmbientOcclusion, DepthIntegrator, NormalsIntegrator};
+pub use path::{MisHeuristic, PathTracer, DEFAULT_RUSSIAN_ROULETTE_DEPTH};
+
+// integrator/mod.rs
+//
+// An integrator decides what color a camera ray sees. The path tracer
+// estimates the light arriving along the ray; the others show some
+// property of the geometry instead, which is much quicker for checking
+// that a scene is put together correctly.
+
+/// Computes the color seen along a ray
+pub trait Integrator: Sync {
+    /// Estimates the color arriving at the origin of `r` along it. Each
+    /// call gives one random sample, and a pixel's samples are averaged.
+    fn ray_color(&self, r: Ray, objects: &HittableObjects) -> Color;
+}
+
+/// The integrators that can be picked when rendering
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum IntegratorKind {
+    /// Light transport, with the render settings' depth and sampling options
+    #[default]
+    Path,
+    /// Outward surface normals, mapped from [-1, 1] to [0, 1]
+    Normals,
+    /// Distance from the camera, as a linear value
+    Depth,
+    /// How much of the sky above each point is unblocked within `distance`,
+    /// or at all if there is no distance
+    AmbientOcclusion { distance: Option<f64> },
+}
+
+impl IntegratorKind {
+    /// Creates the integrator. `path_tracer` holds the settings for `Path`.
+    pub fn build(&self, path_tracer: PathTracer) -> Box<dyn Integrator> {
+        match self {
+            IntegratorKind::Path => Box::new(path_tracer),
+            IntegratorKind::Normals => Box::new(NormalsIntegrator),
+            IntegratorKind::Depth => Box::new(DepthIntegrator),
+            IntegratorKind::AmbientOcclusion { distance } => Box::new(AmbientOcclusion {
+                distance: distance.unwrap_or(f64::INFINITY),
+            }),
+        }
+    }
+}
@@ -0,0 +1,396 @@
+use super::Integrator;
+use crate::color::Color;
+use crate::random;
+use crate::ray::Ray;
+use crate::shapes::{HittableObjects, Intersection, Interval, INFINITY, SURFACE_EPSILON};
+use rand::prelude::*;
+
+// The path tracer. Light reaches a surface either straight from a light or after bouncing
+// around the scene. At every non-specular hit the path tracer estimates the
+// first part twice: once by picking a point on a light, and once by seeing
+// whether the scattered ray happens to hit a light. Multiple importance
+// sampling weights each estimate by how likely it was to find that light,
+// so small lights don't make diffuse surfaces noisy, and big lights don't
+// give glossy surfaces fireflies. Environment maps are treated the same
+// way, as a light surrounding the scene.
+
+/// How the light-sampled and scattered estimates of direct light are
+/// weighted, from Veach's thesis. Both weightings are unbiased.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum MisHeuristic {
+    /// Weights in proportion to each strategy's density
+    Balance,
+    /// Weights in proportion to the square of each strategy's density,
+    /// which favors the better strategy more strongly
+    #[default]
+    Power,
+}
+
+impl MisHeuristic {
+    /// Weight of a sample taken with density `pdf`, when the other strategy
+    /// would have taken it with density `other_pdf`
+    pub fn weight(&self, pdf: f64, other_pdf: f64) -> f64 {
+        let (a, b) = match self {
+            MisHeuristic::Balance => (pdf, other_pdf),
+            MisHeuristic::Power => (pdf * pdf, other_pdf * other_pdf),
+        };
+        if a + b > 0.0 {
+            a / (a + b)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Paths start Russian roulette after this many bounces by default
+pub const DEFAULT_RUSSIAN_ROULETTE_DEPTH: i32 = 5;
+
+/// Paths surviving Russian roulette are never more likely than this to be
+/// ended, so their weight can't grow without bound
+const MIN_SURVIVAL_PROBABILITY: f64 = 0.05;
+
+/// A path tracer combining scattered rays and light samples with multiple
+/// importance sampling.
+///
+/// Rather than bouncing until `max_depth`, paths that carry little light
+/// are ended at random (Russian roulette) once they've bounced
+/// `russian_roulette_depth` times. The paths that survive are brightened to
+/// make up for the ones that don't, so the result stays unbiased.
+#[derive(Copy, Clone, Debug)]
+pub struct PathTracer {
+    /// Maximum number of times a ray can bounce in the scene
+    pub max_depth: i32,
+    pub heuristic: MisHeuristic,
+    /// Number of bounces before Russian roulette starts
+    pub russian_roulette_depth: i32,
+}
+
+impl PathTracer {
+    pub fn new(max_depth: i32) -> Self {
+        PathTracer {
+            max_depth,
+            heuristic: MisHeuristic::default(),
+            russian_roulette_depth: DEFAULT_RUSSIAN_ROULETTE_DEPTH,
+        }
+    }
+
+    /// Estimates the light scattered back along `r` that comes straight
+    /// from a light or the environment, by picking a point on a light and a
+    /// direction in the environment and casting shadow rays towards them.
+    /// The result is weighted for combining with the scattered ray's
+    /// estimate.
+    pub fn direct_light(
+        &self,
+        r: &Ray,
+        intersect: &Intersection,
+        objects: &HittableObjects,
+    ) -> Color {
+        self.light_sample(r, intersect, objects) + self.environment_sample(r, intersect, objects)
+    }
+
+    fn light_sample(&self, r: &Ray, intersect: &Intersection, objects: &HittableObjects) -> Color {
+        let sample = match objects.lights().sample() {
+            Some(sample) => sample,
+            None => return Color::BLACK,
+        };
+        let to_light = sample.point - intersect.p;
+        let distance_squared = to_light.length_squared();
+        let direction = to_light / distance_squared.sqrt();
+        let cos_light = -sample.normal.dot(&direction);
+        if cos_light <= 0.0 {
+            // we're behind the light
+            return Color::BLACK;
+        }
+
+        let material = intersect.material;
+        let scattered = material.eval(r, intersect, &direction);
+        if scattered == Color::BLACK || objects.is_occluded(intersect.p, sample.point) {
+            return Color::BLACK;
+        }
+
+        // the density of the point on the light, per unit solid angle
+        let light_pdf = sample.pdf * distance_squared / cos_light;
+        let weight = self
+            .heuristic
+            .weight(light_pdf, material.pdf(r, intersect, &direction));
+        scattered.mult(sample.radiance) * (weight / light_pdf)
+    }
+
+    fn environment_sample(
+        &self,
+        r: &Ray,
+        intersect: &Intersection,
+        objects: &HittableObjects,
+    ) -> Color {
+        let sample = match objects.environment.sample() {
+            Some(sample) => sample,
+            None => return Color::BLACK,
+        };
+        let material = intersect.material;
+        let scattered = material.eval(r, intersect, &sample.direction);
+        if scattered == Color::BLACK || objects.is_occluded_towards(intersect.p, sample.direction) {
+            return Color::BLACK;
+        }
+        let weight = self
+            .heuristic
+            .weight(sample.pdf, material.pdf(r, intersect, &sample.direction));
+        scattered.mult(sample.radiance) * (weight / sample.pdf)
+    }
+}
+
+impl Integrator for PathTracer {
+    fn ray_color(&self, r: Ray, objects: &HittableObjects) -> Color {
+        let mut color = Color::BLACK;
+        // fraction of the light found at the current hit that reaches the
+        // start of the path
+        let mut throughput = Color::WHITE;
+        let mut r = r;
+        // the density with which the previous hit scattered `r`, or `None`
+        // for camera rays and specular bounces, whose light couldn't have
+        // been found by sampling the lights
+        let mut scatter_pdf: Option<f64> = None;
+
+        for depth in 0..self.max_depth {
+            let intersect = match objects.hit(&r, Interval::new(SURFACE_EPSILON, INFINITY)) {
+                Some(intersect) => intersect,
+                None => {
+                    let mut background = objects.environment.color(&r);
+                    if let Some(pdf) = scatter_pdf {
+                        let environment_pdf = objects.environment.pdf(&r.direction);
+                        background = background * self.heuristic.weight(pdf, environment_pdf);
+                    }
+                    color += throughput.mult(background);
+                    break;
+                }
+            };
+            let material = intersect.material;
+
+            let mut emitted = material.emitted(&intersect);
+            if let Some(pdf) = scatter_pdf {
+                let light_pdf = objects.lights().pdf(r.origin, &intersect);
+                emitted = emitted * self.heuristic.weight(pdf, light_pdf);
+            }
+            color += throughput.mult(emitted);
+
+            if !material.is_specular() {
+                color += throughput.mult(self.direct_light(&r, &intersect, objects));
+            }
+
+            let (scattered_ray, attenuation) = match material.scatter(r, &intersect) {
+                Some(scattered) => scattered,
+                None => break,
+            };
+            scatter_pdf = if material.is_specular() {
+                None
+            } else {
+                Some(material.pdf(&r, &intersect, &scattered_ray.direction))
+            };
+            throughput = throughput.mult(attenuation);
+            r = scattered_ray;
+
+            if depth + 1 >= self.russian_roulette_depth {
+                // dim paths are the most likely to end
+                let survival = throughput
+                    .max_component()
+                    .clamp(MIN_SURVIVAL_PROBABILITY, 1.0);
+                if random::rng().gen::<f64>() >= survival {
+                    break;
+                }
+                throughput = throughput * (1.0 / survival);
+            }
+        }
+        color
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{MisHeuristic, PathTracer};
+    use crate::color::Color;
+    use crate::environment::{Environment, EnvironmentMap};
+    use crate::geom::{Point3, Vector3};
+    use crate::image::Image;
+    use crate::integrator::Integrator;
+    use crate::material::Material;
+    use crate::random;
+    use crate::ray::Ray;
+    use crate::shapes::{HittableObjects, Quad, Shape, Sphere};
+    use crate::texture::Texture;
+
+    /// A floor of the given material, lit only by a sphere of radiance 1
+    /// hanging above it
+    fn lit_floor(floor: Material, radius: f64, height: f64) -> HittableObjects {
+        let mut objects = HittableObjects::new();
+        objects.environment = Environment::Black;
+        objects.add(Shape::Sphere(Sphere::new(
+            Point3::new(0., height, 0.),
+            radius,
+            Material::DiffuseLight(Color::WHITE, 1.),
+        )));
+        objects.add(Shape::Quad(Quad::new(
+            Point3::new(-10., 0., 10.),
+            Vector3::new(20., 0., 0.),
+            Vector3::new(0., 0., -20.),
+            floor,
+        )));
+        objects
+    }
+
+    fn average_red(samples: usize, mut sample: impl FnMut() -> Color) -> f64 {
+        (0..samples).map(|_| sample().red).sum::<f64>() / samples as f64
+    }
+
+    #[test]
+    fn mis_weights_sum_to_one() {
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let sum = heuristic.weight(0.3, 2.0) + heuristic.weight(2.0, 0.3);
+            assert!((sum - 1.).abs() < 1e-12);
+            assert_eq!(heuristic.weight(1.0, 0.0), 1.0);
+        }
+        assert!(MisHeuristic::Power.weight(2.0, 1.0) > MisHeuristic::Balance.weight(2.0, 1.0));
+    }
+
+    #[test]
+    fn can_light_diffuse_floor_from_sphere() {
+        random::reseed(1);
+        let (radius, height) = (1.0, 4.0);
+        let objects = lit_floor(
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+            radius,
+            height,
+        );
+        // the irradiance from a sphere is π (r / d)², and a white diffuse
+        // surface reflects 1 / π of it
+        let expected = (radius / height).powi(2);
+        let down = Ray::new(Point3::new(0., 1., 0.), Vector3::new(0., -1., 0.));
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let integrator = PathTracer {
+                heuristic,
+                ..PathTracer::new(2)
+            };
+            let estimate = average_red(40_000, || integrator.ray_color(down, &objects));
+            assert!(
+                (estimate - expected).abs() < 0.03 * expected,
+                "{:?}: {} vs {}",
+                heuristic,
+                estimate,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn can_light_diffuse_floor_from_environment_map() {
+        random::reseed(1);
+        // a dim sky with one bright pixel, above the horizon
+        let mut image = Image::from_pixels(8, 4, vec![Color::new(0.1, 0.1, 0.1); 32]);
+        image.set(3, 1, Color::new(200., 200., 200.));
+        let mut objects = HittableObjects::new();
+        objects.environment = Environment::Map(EnvironmentMap::new(image, 30., 1.));
+        objects.add(Shape::Quad(Quad::new(
+            Point3::new(-10., 0., 10.),
+            Vector3::new(20., 0., 0.),
+            Vector3::new(0., 0., -20.),
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        )));
+        // each pixel in the upper two rows covers a quarter of π / 4 of the
+        // cosine-weighted hemisphere, and a white diffuse surface reflects
+        // 1 / π of the light
+        let expected = (15. * 0.1 + 200.) / 16.;
+        let down = Ray::new(Point3::new(0., 1., 0.), Vector3::new(0., -1., 0.));
+        for heuristic in [MisHeuristic::Balance, MisHeuristic::Power] {
+            let integrator = PathTracer {
+                heuristic,
+                ..PathTracer::new(2)
+            };
+            let estimate = average_red(40_000, || integrator.ray_color(down, &objects));
+            assert!(
+                (estimate - expected).abs() < 0.02 * expected,
+                "{:?}: {} vs {}",
+                heuristic,
+                estimate,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn light_sampling_and_scattering_agree_on_glossy_floor() {
+        random::reseed(1);
+        let objects = lit_floor(
+            Material::Metal(Texture::Constant(Color::WHITE), 0.5.into()),
+            1.0,
+            3.0,
+        );
+        // the same scene, with the shapes pushed directly onto `objects` so
+        // the light can only be found by scattered rays
+        let mut unsampled = HittableObjects::new();
+        unsampled.environment = Environment::Black;
+        unsampled.objects = objects.objects.clone();
+        assert!(unsampled.lights().is_empty());
+
+        // the reflection off the floor passes just beside the light
+        let r = Ray::new(Point3::new(-3., 6., 0.), Vector3::new(3., -6., 0.));
+        let integrator = PathTracer::new(2);
+        let sampled = average_red(40_000, || integrator.ray_color(r, &objects));
+        let expected = average_red(40_000, || integrator.ray_color(r, &unsampled));
+        assert!(
+            (sampled - expected).abs() < 0.03 * expected,
+            "{} vs {}",
+            sampled,
+            expected
+        );
+    }
+
+    /// A light sphere of radius 1 inside a diffuse sphere of radius 2, seen
+    /// from between them
+    fn furnace(albedo: f64) -> (HittableObjects, Ray) {
+        let mut objects = HittableObjects::new();
+        objects.environment = Environment::Black;
+        objects.add(Shape::Sphere(Sphere::new(
+            Point3::origin(),
+            1.,
+            Material::DiffuseLight(Color::WHITE, 1.),
+        )));
+        objects.add(Shape::Sphere(Sphere::new(
+            Point3::origin(),
+            2.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::new(albedo, albedo, albedo))),
+        )));
+        let r = Ray::new(Point3::new(0., 1.5, 0.), Vector3::new(0., 1., 0.));
+        (objects, r)
+    }
+
+    #[test]
+    fn russian_roulette_is_unbiased_in_furnace() {
+        random::reseed(1);
+        let albedo = 0.8;
+        let (objects, r) = furnace(albedo);
+        // Every point on the wall gets the same light, directly from the
+        // light and from the rest of the wall, which hides all but k of its
+        // view. Its radiance L solves L = albedo (k + (1 - k) L).
+        let k: f64 = 0.25;
+        let expected = albedo * k / (1. - albedo * (1. - k));
+
+        // paths bounce 5 times on average before the wall absorbs them
+        let integrator = PathTracer {
+            russian_roulette_depth: 1,
+            ..PathTracer::new(1000)
+        };
+        let estimate = average_red(40_000, || integrator.ray_color(r, &objects));
+        assert!(
+            (estimate - expected).abs() < 0.02 * expected,
+            "{} vs {}",
+            estimate,
+            expected
+        );
+
+        // cutting paths off without Russian roulette loses light
+        let integrator = PathTracer {
+            russian_roulette_depth: 1000,
+            ..PathTracer::new(3)
+        };
+        let truncated = average_red(40_000, || integrator.ray_color(r, &objects));
+        assert!(truncated < 0.9 * expected, "{} vs {}", truncated, expected);
+    }
+}
@@ -0,0 +1,17 @@
+pub mod bump;
+pub mod camera;
+pub mod canvas;
+pub mod color;
+pub mod distribution;
+pub mod environment;
+pub mod geom;
+pub mod image;
+pub mod integrator;
+pub mod material;
+pub mod obj;
+pub mod random;
+pub mod ray;
+pub mod scene;
+pub mod shapes;
+pub mod texture;
+pub mod tonemap;
@@ -1,28 +1,181 @@
-use std::fs::File;
-use std::io::Write;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-mod camera;
-mod canvas;
-mod color;
-mod geom;
-mod material;
-mod ray;
-mod shapes;
+use clap::{Parser, ValueEnum};
 
-use camera::{Camera, RenderConfig};
-use canvas::{Resolution, ASPECT_RATIO};
-use geom::*;
+use ray_tracing_weekend::camera::{CameraConfig, RenderConfig};
+use ray_tracing_weekend::canvas::Resolution;
+use ray_tracing_weekend::environment::{Environment, EnvironmentMap};
+use ray_tracing_weekend::geom::*;
+use ray_tracing_weekend::image::{read_image, write_image, ImageFormat};
+use ray_tracing_weekend::integrator::{IntegratorKind, MisHeuristic};
+use ray_tracing_weekend::random;
+use ray_tracing_weekend::scene::{load_scene, Scene};
 
-use shapes::make_random_scene;
+use ray_tracing_weekend::shapes::bvh::BvhConfig;
+use ray_tracing_weekend::shapes::{make_cornell_box, make_random_scene};
+use ray_tracing_weekend::tonemap::ToneMapOperator;
 
-/// The viewer's eye (the camera) will be at `(0,0,0)`. The screen will
-/// basically be an xy-plane, where the origin is in the lower left corner,
-/// the x-axis goes to the right, and the y-axis goes up. The z-axis points
-/// out of the screen. The endpoint of the ray on the screen (in the xy-plane)
-/// can be denoted with two offset vectors `u` and `v`.
+/// Scenes that can be rendered without a scene file
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum BuiltinScene {
+    /// The random spheres from the cover of "Ray Tracing in One Weekend"
+    Cover,
+    /// The Cornell box, lit by an area light in the ceiling
+    Cornell,
+}
 
-fn main() {
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum OutputFormat {
+    /// Plain text PPM
+    P3,
+    /// Binary PPM
+    P6,
+    /// PNG with 8 bits per channel
+    Png8,
+    /// PNG with 16 bits per channel
+    Png16,
+    /// Portable float map (high dynamic range)
+    Pfm,
+    /// Radiance RGBE (high dynamic range)
+    Hdr,
+    /// OpenEXR (high dynamic range)
+    Exr,
+}
+
+impl From<OutputFormat> for ImageFormat {
+    fn from(format: OutputFormat) -> Self {
+        match format {
+            OutputFormat::P3 => ImageFormat::PpmText,
+            OutputFormat::P6 => ImageFormat::PpmBinary,
+            OutputFormat::Png8 => ImageFormat::Png8,
+            OutputFormat::Png16 => ImageFormat::Png16,
+            OutputFormat::Pfm => ImageFormat::Pfm,
+            OutputFormat::Hdr => ImageFormat::Radiance,
+            OutputFormat::Exr => ImageFormat::OpenExr,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum ToneMap {
+    /// Clip values above 1
+    Clamp,
+    /// Reinhard's x / (1 + x)
+    Reinhard,
+    /// Reinhard's curve, reaching white at --white (default: the brightest pixel)
+    ReinhardExtended,
+    /// Hable's filmic curve
+    Filmic,
+    /// Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum IntegratorName {
+    /// Path tracing
+    Path,
+    /// Surface normals, for checking geometry
+    Normals,
+    /// Distance from the camera (write a .pfm or .exr file to keep the values)
+    Depth,
+    /// Ambient occlusion, limited to --ao-distance
+    Ao,
+}
+
+#[derive(Copy, Clone, Debug, ValueEnum)]
+enum Mis {
+    /// Weight light samples and scattered rays by their densities
+    Balance,
+    /// Weight them by their squared densities
+    Power,
+}
+
+/// Renders a scene file or a built-in scene to an image.
+///
+/// Options given here override the settings in the scene file.
+#[derive(Debug, Parser)]
+#[command(version, about)]
+struct Args {
+    /// Scene file to render (see the scenes directory for examples)
+    scene: Option<PathBuf>,
+
+    /// Built-in scene to render when no scene file is given
+    #[arg(long, value_enum, default_value_t = BuiltinScene::Cover, conflicts_with = "scene")]
+    builtin: BuiltinScene,
+
+    /// Image size: 240p, 360p, 480p, 720p, 1080p, 2K, 4K, 5K or WIDTHxHEIGHT
+    #[arg(short, long)]
+    resolution: Option<Resolution>,
+
+    /// Number of random samples for each pixel
+    #[arg(short, long, value_parser = clap::value_parser!(u64).range(1..))]
+    samples: Option<u64>,
+
+    /// Maximum number of times a ray can bounce in the scene
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    max_depth: Option<i32>,
+
+    /// Equirectangular HDR image to light the scene with, replacing its
+    /// environment
+    #[arg(long)]
+    environment: Option<PathBuf>,
+
+    /// What to compute for each pixel [default: the scene's, or path]
+    #[arg(long, value_enum)]
+    integrator: Option<IntegratorName>,
+
+    /// How far away objects can block ambient occlusion [default: the
+    /// scene's, or unlimited]
+    #[arg(long)]
+    ao_distance: Option<f64>,
+
+    /// Number of bounces before paths may be ended at random (Russian
+    /// roulette). Paths still never bounce more than --max-depth times.
+    #[arg(long, value_parser = clap::value_parser!(i32).range(0..))]
+    rr_depth: Option<i32>,
+
+    /// How light samples and scattered rays are combined [default: the
+    /// scene's, or power]
+    #[arg(long, value_enum)]
+    mis: Option<Mis>,
+
+    /// Number of rendering threads [default: one per CPU]
+    #[arg(short = 'j', long, value_parser = clap::value_parser!(u64).range(1..))]
+    threads: Option<u64>,
+
+    /// Seed for the random numbers, to make renders reproducible [default: random]
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Where to write the image. The extension (.ppm, .png, .pfm, .hdr or
+    /// .exr) picks the format. [default: scene_<height>p.ppm]
+    #[arg(short, long)]
+    output: Option<PathBuf>,
+
+    /// Image file format, overriding the one given by the extension
+    #[arg(long, value_enum)]
+    format: Option<OutputFormat>,
+
+    /// How to compress bright colors for PPM and PNG files [default: the
+    /// scene's, or clamp]
+    #[arg(long, value_enum)]
+    tone_map: Option<ToneMap>,
+
+    /// Exposure adjustment in stops, for PPM and PNG files
+    #[arg(long, allow_negative_numbers = true)]
+    exposure: Option<f64>,
+
+    /// Brightness that becomes white with the reinhard-extended tone map
+    #[arg(long)]
+    white: Option<f64>,
+
+    /// Don't print anything except errors
+    #[arg(short, long)]
+    quiet: bool,
+}
+
+/// The random spheres scene from the cover of "Ray Tracing in One Weekend"
+fn make_cover_scene() -> Scene {
     let resolution = Resolution::_4K;
     let samples_per_pixel: usize = 500;
     let max_depth: i32 = 50;
@@ -31,38 +184,189 @@ fn main() {
     let objects = make_random_scene();
 
     // Camera
-    let look_from = Point3::new(13., 2., 3.);
-    let look_at = Point3::origin();
-    let view_up = Vector3::new(0., 1., 0.);
-    let dist_to_focus = 10_f64;
-    let aperture = 0.1_f64;
-
-    let camera = Camera::new(
-        look_from,
-        look_at,
-        view_up,
-        20.0,
-        ASPECT_RATIO,
-        aperture,
-        dist_to_focus,
-    );
+    let camera_config = CameraConfig {
+        look_from: Point3::new(13., 2., 3.),
+        look_at: Point3::origin(),
+        view_up: Vector3::new(0., 1., 0.),
+        vertical_fov: 20.0,
+        aperture: 0.1,
+        focus_dist: 10.0,
+    };
 
-    // Render
+    Scene {
+        objects,
+        camera_config,
+        render_config,
+    }
+}
+
+/// The Cornell box, viewed through its open front
+fn make_cornell_scene() -> Scene {
+    let resolution = Resolution::Custom {
+        width: 600,
+        height: 600,
+    };
+    let render_config = RenderConfig::new(resolution, 200, 50);
+
+    let camera_config = CameraConfig {
+        look_from: Point3::new(278., 278., -800.),
+        look_at: Point3::new(278., 278., 0.),
+        view_up: Vector3::new(0., 1., 0.),
+        vertical_fov: 40.0,
+        aperture: 0.0,
+        focus_dist: 800.0,
+    };
+
+    Scene {
+        objects: make_cornell_box(),
+        camera_config,
+        render_config,
+    }
+}
+
+fn exit_with_error(message: impl std::fmt::Display) -> ! {
+    eprintln!("{}", message);
+    std::process::exit(1);
+}
+
+/// The viewer's eye (the camera) will be at `(0,0,0)`. The screen will
+/// basically be an xy-plane, where the origin is in the lower left corner,
+/// the x-axis goes to the right, and the y-axis goes up. The z-axis points
+/// out of the screen. The endpoint of the ray on the screen (in the xy-plane)
+/// can be denoted with two offset vectors `u` and `v`.
+fn main() {
+    let args = Args::parse();
+
+    if let Some(threads) = args.threads {
+        rayon::ThreadPoolBuilder::new()
+            .num_threads(threads as usize)
+            .build_global()
+            .unwrap_or_else(|error| exit_with_error(error));
+    }
+
+    let seed = args.seed.unwrap_or_else(rand::random);
+    if !args.quiet {
+        eprintln!("Seed: {}", seed);
+    }
+    // Seed before building the scene, so random scenes are reproducible too
+    random::reseed(seed);
+
+    let mut scene = match &args.scene {
+        Some(path) => load_scene(path).unwrap_or_else(|error| exit_with_error(error)),
+        None => match args.builtin {
+            BuiltinScene::Cover => make_cover_scene(),
+            BuiltinScene::Cornell => make_cornell_scene(),
+        },
+    };
 
-    let binary_pixels = camera.render(&objects, render_config);
+    if let Some(resolution) = args.resolution {
+        scene.render_config.set_resolution(resolution);
+    }
+    if let Some(samples) = args.samples {
+        scene.render_config.samples_per_pixel = samples as usize;
+    }
+    if let Some(max_depth) = args.max_depth {
+        scene.render_config.max_depth = max_depth;
+    }
+    if let Some(path) = &args.environment {
+        let image = read_image(path).unwrap_or_else(|error| exit_with_error(error));
+        if image.width() == 0 || image.height() == 0 {
+            exit_with_error(format!("{}: the image is empty", path.display()));
+        }
+        scene.objects.environment = Environment::Map(EnvironmentMap::new(image, 0.0, 1.0));
+    }
+    if let Some(integrator) = args.integrator {
+        scene.render_config.integrator = match integrator {
+            IntegratorName::Path => IntegratorKind::Path,
+            IntegratorName::Normals => IntegratorKind::Normals,
+            IntegratorName::Depth => IntegratorKind::Depth,
+            IntegratorName::Ao => IntegratorKind::AmbientOcclusion { distance: None },
+        };
+    }
+    if let Some(ao_distance) = args.ao_distance {
+        match &mut scene.render_config.integrator {
+            IntegratorKind::AmbientOcclusion { distance } if ao_distance > 0.0 => {
+                *distance = Some(ao_distance)
+            }
+            IntegratorKind::AmbientOcclusion { .. } => {
+                exit_with_error("--ao-distance must be positive")
+            }
+            _ => exit_with_error("--ao-distance is only used by the ao integrator"),
+        }
+    }
+    if let Some(depth) = args.rr_depth {
+        scene.render_config.russian_roulette_depth = depth;
+    }
+    if let Some(mis) = args.mis {
+        scene.render_config.mis_heuristic = match mis {
+            Mis::Balance => MisHeuristic::Balance,
+            Mis::Power => MisHeuristic::Power,
+        };
+    }
+    if let Some(tone_map) = args.tone_map {
+        scene.render_config.tone_mapping.operator = match tone_map {
+            ToneMap::Clamp => ToneMapOperator::Clamp,
+            ToneMap::Reinhard => ToneMapOperator::Reinhard,
+            ToneMap::ReinhardExtended => ToneMapOperator::ReinhardExtended { white: None },
+            ToneMap::Filmic => ToneMapOperator::Filmic,
+            ToneMap::Aces => ToneMapOperator::Aces,
+        };
+    }
+    if let Some(white) = args.white {
+        match &mut scene.render_config.tone_mapping.operator {
+            ToneMapOperator::ReinhardExtended { white: scene_white } if white > 0.0 => {
+                *scene_white = Some(white)
+            }
+            ToneMapOperator::ReinhardExtended { .. } => exit_with_error("--white must be positive"),
+            _ => exit_with_error("--white is only used by the reinhard-extended tone map"),
+        }
+    }
+    if let Some(exposure) = args.exposure {
+        scene.render_config.tone_mapping.exposure = exposure;
+    }
+    scene.render_config.seed = seed;
+    scene.render_config.show_progress = !args.quiet;
 
-    // Write pixels to PPM file in P6 format, P6 format is a little simpler than P3 format
-    let filename = format!("scene_{}p.ppm", render_config.height);
-    let path = Path::new(&filename);
-    let mut file = File::create(path).expect("Failed to create file.");
+    // Check where the image goes before spending time rendering it
+    let output = args
+        .output
+        .unwrap_or_else(|| PathBuf::from(format!("scene_{}p.ppm", scene.render_config.height)));
+    let format = match args.format {
+        Some(format) => format.into(),
+        None => ImageFormat::from_path(&output).unwrap_or_else(|| {
+            exit_with_error(format!(
+                "{}: unknown image format, use a .ppm, .png, .pfm, .hdr or .exr extension or --format",
+                output.display()
+            ))
+        }),
+    };
+    let output_dir = match output.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    if !output_dir.is_dir() {
+        exit_with_error(format!(
+            "{}: no such directory to save the image in",
+            output_dir.display()
+        ));
+    }
+
+    let bvh_stats = scene.objects.build_bvh(BvhConfig::default());
+    if !args.quiet {
+        eprintln!("{}", bvh_stats);
+    }
+
+    // Render
 
-    let header = format!(
-        "P6\n{} {}\n255\n",
-        render_config.width, render_config.height
-    );
-    file.write_all(header.as_bytes())
-        .expect("Failed to write PPM header.");
+    let render_config = scene.render_config;
+    let camera = scene.camera();
+    let image = camera.render(&scene.objects, render_config);
 
-    file.write_all(&binary_pixels)
-        .expect("Failed to write color map to PPM.");
+    // high dynamic range files keep the linear colors
+    let image = if format.is_high_dynamic_range() {
+        image
+    } else {
+        render_config.tone_mapping.to_display(&image)
+    };
+    write_image(&image, &output, format).unwrap_or_else(|error| exit_with_error(error));
 }
@@ -0,0 +1,198 @@
+use crate::color::Color;
+use crate::geom::Vector3;
+use crate::material::fresnel::conductor_reflectance_rgb;
+use crate::material::microfacet::Ggx;
+
+// material/conductor.rs
+//
+// A metal whose roughness comes from GGX microfacets, reflecting light by
+// the Fresnel equations for its complex index of refraction. Unlike
+// `Material::Metal`'s fuzz, the reflected light never adds up to more than
+// arrived, and it brightens and whitens towards grazing angles as real
+// metals do. Directions are in the local frame of `microfacet.rs`.
+
+/// Metals with measured indices of refraction, sampled at red, green and
+/// blue wavelengths (650, 550 and 450 nm)
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MetalPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl MetalPreset {
+    /// The real and imaginary parts of the index of refraction, `eta` and
+    /// `k`
+    pub fn index_of_refraction(&self) -> (Color, Color) {
+        match self {
+            MetalPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            MetalPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            MetalPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            MetalPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    roughness: f64,
+    distribution: Ggx,
+}
+
+impl Conductor {
+    /// A metal with index of refraction `eta + ik` for each color, and a
+    /// `roughness` and `anisotropy` from 0 to 1. Roughness 0 is a perfect
+    /// mirror.
+    pub fn new(eta: Color, k: Color, roughness: f64, anisotropy: f64) -> Self {
+        Conductor {
+            eta,
+            k,
+            roughness,
+            distribution: Ggx::from_roughness(roughness, anisotropy),
+        }
+    }
+
+    pub fn from_preset(preset: MetalPreset, roughness: f64, anisotropy: f64) -> Self {
+        let (eta, k) = preset.index_of_refraction();
+        Conductor::new(eta, k, roughness, anisotropy)
+    }
+
+    /// Whether the metal is a perfect mirror
+    pub fn is_specular(&self) -> bool {
+        self.roughness <= 0.0
+    }
+
+    /// Fraction of light reflected by a facet seen at an angle with cosine
+    /// `cos_theta`
+    pub fn reflectance(&self, cos_theta: f64) -> Color {
+        conductor_reflectance_rgb(cos_theta, self.eta, self.k)
+    }
+
+    /// The BRDF times the cosine at `wi`, for light arriving from `wi` and
+    /// leaving along `wo`. Zero for perfect mirrors.
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3) -> Color {
+        if self.is_specular() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return Color::BLACK;
+        }
+        let m = (*wo + *wi).to_unit_vector();
+        let ggx = &self.distribution;
+        self.reflectance(wo.dot(&m)) * (ggx.d(&m) * ggx.g(wo, wi) / (4.0 * wo.z))
+    }
+
+    /// Density, per unit solid angle, of `sample` picking `wi`
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3) -> f64 {
+        if self.is_specular() || wo.z <= 0.0 || wi.z <= 0.0 {
+            return 0.0;
+        }
+        let m = (*wo + *wi).to_unit_vector();
+        // reflecting about m doubles the angles, which spreads the density
+        // over four times the solid angle at normal incidence
+        self.distribution.visible_normal_pdf(wo, &m) / (4.0 * wo.dot(&m))
+    }
+
+    /// Picks the direction light leaving along `wo` arrives from, using
+    /// two random numbers in [0, 1), and returns it with the weight
+    /// `eval / pdf`. `None` if the light would come from below the surface.
+    pub fn sample(&self, wo: &Vector3, u1: f64, u2: f64) -> Option<(Vector3, Color)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        if self.is_specular() {
+            let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+            return Some((wi, self.reflectance(wo.z)));
+        }
+        let m = self.distribution.sample_visible_normal(wo, u1, u2);
+        let wi = 2.0 * wo.dot(&m) * m - *wo;
+        if wi.z <= 0.0 {
+            return None;
+        }
+        let ggx = &self.distribution;
+        let weight = ggx.g(wo, &wi) / ggx.g1(wo);
+        Some((wi, self.reflectance(wo.dot(&m)) * weight))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Conductor, MetalPreset};
+    use crate::color::Color;
+    use crate::geom::Vector3;
+    use crate::material::check_sampling;
+    use crate::random;
+    use rand::Rng;
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        random::reseed(1);
+        let gold = Conductor::from_preset(MetalPreset::Gold, 0.4, 0.6);
+        let wo = Vector3::new(0.3, 0.2, 0.8).to_unit_vector();
+        let mut rng = random::rng();
+        check_sampling(
+            || gold.sample(&wo, rng.gen(), rng.gen()),
+            |wi| gold.eval(&wo, wi),
+            |wi| gold.pdf(&wo, wi),
+            100_000,
+            0.02,
+        );
+    }
+
+    #[test]
+    fn rough_metal_reflects_no_more_than_arrives() {
+        random::reseed(1);
+        // the brightest a metal can be, with total reflection at every angle
+        let perfect = Conductor::new(Color::BLACK, Color::new(1e4, 1e4, 1e4), 0.5, 0.);
+        let mut rng = random::rng();
+        let wo = Vector3::new(0.6, 0., 0.8);
+        let samples = 100_000;
+        let albedo: f64 = (0..samples)
+            .filter_map(|_| perfect.sample(&wo, rng.gen(), rng.gen()))
+            .map(|(_, weight)| weight.green)
+            .sum::<f64>()
+            / samples as f64;
+        // single scattering loses a little energy, but never gains any
+        assert!(albedo < 1. && albedo > 0.85, "{}", albedo);
+    }
+
+    #[test]
+    fn can_reflect_like_mirror() {
+        let copper = Conductor::from_preset(MetalPreset::Copper, 0., 0.);
+        assert!(copper.is_specular());
+        let wo = Vector3::new(0.6, 0., 0.8);
+        let (wi, weight) = copper.sample(&wo, 0.5, 0.5).unwrap();
+        assert_eq!(wi, Vector3::new(-0.6, 0., 0.8));
+        // copper is red
+        assert!(weight.red > weight.green && weight.green > weight.blue);
+        assert_eq!(copper.pdf(&wo, &wi), 0.);
+    }
+
+    #[test]
+    fn anisotropy_stretches_highlights_along_tangent() {
+        random::reseed(1);
+        let brushed = Conductor::from_preset(MetalPreset::Aluminium, 0.4, 0.9);
+        let mut rng = random::rng();
+        let wo = Vector3::new(0., 0., 1.);
+        let (mut spread_x, mut spread_y) = (0., 0.);
+        for _ in 0..10_000 {
+            if let Some((wi, _)) = brushed.sample(&wo, rng.gen(), rng.gen()) {
+                spread_x += wi.x * wi.x;
+                spread_y += wi.y * wi.y;
+            }
+        }
+        assert!(spread_x > 4. * spread_y);
+    }
+}
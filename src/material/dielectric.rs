@@ -0,0 +1,201 @@
+use crate::geom::Vector3;
+use crate::material::fresnel::dielectric_reflectance;
+use crate::material::microfacet::Ggx;
+
+// material/dielectric.rs
+//
+// Frosted glass: a dielectric whose surface is made of GGX microfacets, each
+// of which reflects or refracts light like smooth glass. From Walter et al.,
+// "Microfacet Models for Refraction through Rough Surfaces", 2007.
+// Directions are in the local frame of `microfacet.rs`, with `wo` on the
+// side of the surface the normal points to, and `outside` says whether
+// that side is outside the medium.
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RoughDielectric {
+    index_of_refraction: f64,
+    distribution: Ggx,
+}
+
+/// The facet normal that refracts `wo` into `wi`, where `eta` is the index
+/// of refraction on `wi`'s side relative to `wo`'s. `None` if no facet
+/// facing up could.
+fn refracting_normal(wo: &Vector3, wi: &Vector3, eta: f64) -> Option<Vector3> {
+    let m = *wo + eta * *wi;
+    if m.length_squared() < 1e-20 {
+        return None;
+    }
+    let m = m.to_unit_vector();
+    let m = if m.z < 0.0 { -m } else { m };
+    if wo.dot(&m) <= 0.0 || wi.dot(&m) >= 0.0 {
+        return None;
+    }
+    Some(m)
+}
+
+impl RoughDielectric {
+    /// Glass with a `roughness` from 0 to 1, which must be above 0; smooth
+    /// glass is `Material::Dielectric`
+    pub fn new(index_of_refraction: f64, roughness: f64) -> Self {
+        RoughDielectric {
+            index_of_refraction,
+            distribution: Ggx::from_roughness(roughness, 0.0),
+        }
+    }
+
+    /// The index of refraction below the surface relative to above it
+    fn eta(&self, outside: bool) -> f64 {
+        if outside {
+            self.index_of_refraction
+        } else {
+            1.0 / self.index_of_refraction
+        }
+    }
+
+    /// The BSDF times the cosine at `wi`, for light arriving from `wi` and
+    /// leaving along `wo`. Light refracted into a denser medium is squeezed
+    /// into a narrower cone, so its radiance is scaled by 1 / eta².
+    pub fn eval(&self, wo: &Vector3, wi: &Vector3, outside: bool) -> f64 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let eta = self.eta(outside);
+        let ggx = &self.distribution;
+        if wi.z > 0.0 {
+            let m = (*wo + *wi).to_unit_vector();
+            let reflectance = dielectric_reflectance(wo.dot(&m), eta);
+            return reflectance * ggx.d(&m) * ggx.g(wo, wi) / (4.0 * wo.z);
+        }
+        let m = match refracting_normal(wo, wi, eta) {
+            Some(m) => m,
+            None => return 0.0,
+        };
+        let transmittance = 1.0 - dielectric_reflectance(wo.dot(&m), eta);
+        let denominator = (wo.dot(&m) + eta * wi.dot(&m)).powi(2);
+        transmittance * ggx.d(&m) * ggx.g(wo, wi) * (wo.dot(&m) * wi.dot(&m)).abs()
+            / (wo.z * denominator)
+    }
+
+    /// Density, per unit solid angle, of `sample` picking `wi`
+    pub fn pdf(&self, wo: &Vector3, wi: &Vector3, outside: bool) -> f64 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let eta = self.eta(outside);
+        let ggx = &self.distribution;
+        if wi.z > 0.0 {
+            let m = (*wo + *wi).to_unit_vector();
+            let reflectance = dielectric_reflectance(wo.dot(&m), eta);
+            return reflectance * ggx.visible_normal_pdf(wo, &m) / (4.0 * wo.dot(&m));
+        }
+        let m = match refracting_normal(wo, wi, eta) {
+            Some(m) => m,
+            None => return 0.0,
+        };
+        let transmittance = 1.0 - dielectric_reflectance(wo.dot(&m), eta);
+        // how much refraction about m squeezes or spreads the density
+        let denominator = (wo.dot(&m) + eta * wi.dot(&m)).powi(2);
+        let jacobian = eta * eta * wi.dot(&m).abs() / denominator;
+        transmittance * ggx.visible_normal_pdf(wo, &m) * jacobian
+    }
+
+    /// Picks the direction light leaving along `wo` arrives from, using
+    /// three random numbers in [0, 1), and returns it with the weight
+    /// `eval / pdf`. Facets reflect or refract in proportion to their
+    /// reflectance. `None` if the light is lost between the facets.
+    pub fn sample(
+        &self,
+        wo: &Vector3,
+        u1: f64,
+        u2: f64,
+        u3: f64,
+        outside: bool,
+    ) -> Option<(Vector3, f64)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let eta = self.eta(outside);
+        let ggx = &self.distribution;
+        let m = ggx.sample_visible_normal(wo, u1, u2);
+        let cos_i = wo.dot(&m);
+        if u3 < dielectric_reflectance(cos_i, eta) {
+            let wi = 2.0 * cos_i * m - *wo;
+            if wi.z <= 0.0 {
+                return None;
+            }
+            return Some((wi, ggx.g(wo, &wi) / ggx.g1(wo)));
+        }
+        // Snell's law, about the facet; total internal reflection has
+        // reflectance 1, so it never gets here
+        let sin_squared_t = (1.0 - cos_i * cos_i) / (eta * eta);
+        let cos_t = (1.0 - sin_squared_t).max(0.0).sqrt();
+        let wi = (cos_i / eta - cos_t) * m - *wo / eta;
+        if wi.z >= 0.0 {
+            return None;
+        }
+        Some((wi, ggx.g(wo, &wi) / (ggx.g1(wo) * eta * eta)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::RoughDielectric;
+    use crate::color::Color;
+    use crate::geom::Vector3;
+    use crate::material::check_sampling;
+    use crate::random;
+    use rand::Rng;
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        random::reseed(1);
+        let glass = RoughDielectric::new(1.5, 0.6);
+        let wo = Vector3::new(0.4, -0.2, 0.7).to_unit_vector();
+        let mut rng = random::rng();
+        let gray = |value: f64| Color::new(value, value, value);
+        for outside in [true, false] {
+            let samples = 200_000;
+            let directions = check_sampling(
+                || {
+                    glass
+                        .sample(&wo, rng.gen(), rng.gen(), rng.gen(), outside)
+                        .map(|(wi, weight)| (wi, gray(weight)))
+                },
+                |wi| gray(glass.eval(&wo, wi, outside)),
+                |wi| glass.pdf(&wo, wi, outside),
+                samples,
+                0.02,
+            );
+            let refracted = directions.iter().filter(|wi| wi.z < 0.).count();
+            assert!(refracted > samples / 2);
+        }
+    }
+
+    #[test]
+    fn frosted_glass_refracts_like_smooth_glass_on_average() {
+        random::reseed(1);
+        let glass = RoughDielectric::new(1.5, 0.2);
+        let mut rng = random::rng();
+        // head on, light goes straight through
+        let wo = Vector3::new(0., 0., 1.);
+        let mut mean = Vector3::new(0., 0., 0.);
+        for _ in 0..10_000 {
+            if let Some((wi, _)) = glass.sample(&wo, rng.gen(), rng.gen(), rng.gen(), true) {
+                if wi.z < 0. {
+                    mean += wi;
+                }
+            }
+        }
+        let mean = mean.to_unit_vector();
+        assert!(mean.z < -0.99, "{:?}", mean);
+
+        // and light leaving the glass at a grazing angle is mostly
+        // reflected back inside
+        let wo = Vector3::new(0.9, 0., 0.3).to_unit_vector();
+        let reflected = (0..10_000)
+            .filter_map(|_| glass.sample(&wo, rng.gen(), rng.gen(), rng.gen(), false))
+            .filter(|(wi, _)| wi.z > 0.)
+            .count();
+        assert!(reflected > 8_000, "{}", reflected);
+    }
+}
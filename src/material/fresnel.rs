@@ -0,0 +1,95 @@
+use crate::color::Color;
+
+// material/fresnel.rs
+//
+// How much light a smooth interface reflects, from the Fresnel equations,
+// averaged over the two polarizations.
+
+/// Reflectance of a conductor with complex index of refraction `eta + ik`,
+/// relative to the medium the light arrives through, for light arriving
+/// at an angle with cosine `cos_theta` to the normal
+pub fn conductor_reflectance(cos_theta: f64, eta: f64, k: f64) -> f64 {
+    let cos_squared = cos_theta.clamp(0.0, 1.0).powi(2);
+    let sin_squared = 1.0 - cos_squared;
+    let eta_squared = eta * eta;
+    let k_squared = k * k;
+
+    let t0 = eta_squared - k_squared - sin_squared;
+    let a_squared_plus_b_squared = (t0 * t0 + 4.0 * eta_squared * k_squared).sqrt();
+    let a = (0.5 * (a_squared_plus_b_squared + t0)).max(0.0).sqrt();
+    let cos_theta = cos_squared.sqrt();
+
+    let t1 = a_squared_plus_b_squared + cos_squared;
+    let t2 = 2.0 * a * cos_theta;
+    let rs = (t1 - t2) / (t1 + t2);
+
+    let t3 = cos_squared * a_squared_plus_b_squared + sin_squared * sin_squared;
+    let t4 = t2 * sin_squared;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+
+    0.5 * (rs + rp)
+}
+
+/// Reflectance of a dielectric such as glass, for light arriving at an
+/// angle with cosine `cos_theta` to the normal from a medium where the
+/// other side's index of refraction, relative to this one, is `eta`. 1
+/// past the critical angle, where all the light is reflected.
+pub fn dielectric_reflectance(cos_theta: f64, eta: f64) -> f64 {
+    let cos_i = cos_theta.clamp(0.0, 1.0);
+    let sin_squared_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin_squared_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin_squared_t).sqrt();
+    let rs = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    let rp = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    0.5 * (rs * rs + rp * rp)
+}
+
+/// `conductor_reflectance` for red, green and blue light
+pub fn conductor_reflectance_rgb(cos_theta: f64, eta: Color, k: Color) -> Color {
+    Color::new(
+        conductor_reflectance(cos_theta, eta.red, k.red),
+        conductor_reflectance(cos_theta, eta.green, k.green),
+        conductor_reflectance(cos_theta, eta.blue, k.blue),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{conductor_reflectance, dielectric_reflectance};
+
+    #[test]
+    fn can_find_conductor_reflectance() {
+        // at normal incidence, ((n - 1)² + k²) / ((n + 1)² + k²)
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1f64).powi(2) + k * k) / ((eta + 1f64).powi(2) + k * k);
+        assert!((conductor_reflectance(1., eta, k) - expected).abs() < 1e-12);
+        // everything is reflected at grazing angles
+        assert!((conductor_reflectance(0., eta, k) - 1.).abs() < 1e-12);
+        // with no absorption, glass reflects 4% head on
+        assert!((conductor_reflectance(1., 1.5, 0.) - 0.04).abs() < 1e-12);
+        // and at Brewster's angle only the s-polarized half is reflected
+        let (cos_i, cos_t) = (1.5f64.atan().cos(), 1.5f64.atan().sin());
+        let rs = ((cos_i - 1.5 * cos_t) / (cos_i + 1.5 * cos_t)).powi(2);
+        assert!((conductor_reflectance(cos_i, 1.5, 0.) - 0.5 * rs).abs() < 1e-12);
+    }
+
+    #[test]
+    fn can_find_dielectric_reflectance() {
+        // a conductor that absorbs nothing is a dielectric
+        for i in 0..=10 {
+            let cos_theta = i as f64 / 10.;
+            let expected = conductor_reflectance(cos_theta, 1.5, 0.);
+            assert!((dielectric_reflectance(cos_theta, 1.5) - expected).abs() < 1e-12);
+        }
+        // leaving glass reflects the same 4% head on, and everything past
+        // the critical angle
+        assert!((dielectric_reflectance(1., 1. / 1.5) - 0.04).abs() < 1e-12);
+        let critical = (1f64 / 1.5).asin().cos();
+        assert_eq!(dielectric_reflectance(critical - 1e-6, 1. / 1.5), 1.);
+        assert!(dielectric_reflectance(critical + 1e-3, 1. / 1.5) < 1.);
+        // and nothing is reflected where the index doesn't change
+        assert!(dielectric_reflectance(0.3, 1.) < 1e-12);
+    }
+}
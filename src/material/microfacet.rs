@@ -0,0 +1,171 @@
+use crate::geom::Vector3;
+use std::f64::consts::PI;
+
+// material/microfacet.rs
+//
+// Rough surfaces are modelled as a great many tiny mirror facets, whose
+// normals follow the Trowbridge-Reitz (GGX) distribution. Everything here
+// works in a local frame with the surface normal along z, and the x and y
+// axes along the surface's tangent and bitangent.
+
+/// Smallest width used, so the distribution stays finite. Materials with
+/// no roughness at all are treated as perfect mirrors instead.
+const MIN_ALPHA: f64 = 1e-4;
+
+/// The GGX distribution of facet normals, with separate widths along the
+/// tangent (x) and bitangent (y) for anisotropic surfaces
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Ggx {
+    alpha_x: f64,
+    alpha_y: f64,
+}
+
+impl Ggx {
+    pub fn new(alpha_x: f64, alpha_y: f64) -> Self {
+        Ggx {
+            alpha_x: alpha_x.max(MIN_ALPHA),
+            alpha_y: alpha_y.max(MIN_ALPHA),
+        }
+    }
+
+    /// The distribution for a perceptual `roughness` from 0 to 1, which is
+    /// squared to give the width, stretched along the tangent by an
+    /// `anisotropy` from 0 to 1. This is Burley's mapping, from the Disney
+    /// BRDF.
+    pub fn from_roughness(roughness: f64, anisotropy: f64) -> Self {
+        let alpha = roughness * roughness;
+        let aspect = (1.0 - 0.9 * anisotropy).sqrt();
+        Ggx::new(alpha / aspect, alpha * aspect)
+    }
+
+    /// Density of facets with normal `m`, per unit solid angle and unit
+    /// area of the surface's projection
+    pub fn d(&self, m: &Vector3) -> f64 {
+        if m.z <= 0.0 {
+            return 0.0;
+        }
+        let x = m.x / self.alpha_x;
+        let y = m.y / self.alpha_y;
+        let t = x * x + y * y + m.z * m.z;
+        1.0 / (PI * self.alpha_x * self.alpha_y * t * t)
+    }
+
+    /// Smith's Λ, which measures how much of the surface is hidden from
+    /// direction `w` by other facets
+    fn lambda(&self, w: &Vector3) -> f64 {
+        let cos_squared = w.z * w.z;
+        if cos_squared <= 0.0 {
+            return f64::INFINITY;
+        }
+        let x = self.alpha_x * w.x;
+        let y = self.alpha_y * w.y;
+        let alpha_tan_squared = (x * x + y * y) / cos_squared;
+        0.5 * ((1.0 + alpha_tan_squared).sqrt() - 1.0)
+    }
+
+    /// Fraction of the facets facing `w` that can be seen from `w`
+    pub fn g1(&self, w: &Vector3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    /// Fraction of facets visible from both `wo` and `wi`, allowing for
+    /// facets that are high up being likelier to be visible from both
+    pub fn g(&self, wo: &Vector3, wi: &Vector3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    /// Picks a facet normal in proportion to how much of it can be seen
+    /// from `wo`, which must be above the surface. From Heitz, "Sampling
+    /// the GGX Distribution of Visible Normals", 2018.
+    pub fn sample_visible_normal(&self, wo: &Vector3, u1: f64, u2: f64) -> Vector3 {
+        // stretch the view direction so the distribution becomes a
+        // hemisphere
+        let v = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).to_unit_vector();
+        let length_squared = v.x * v.x + v.y * v.y;
+        let t1 = if length_squared > 0.0 {
+            Vector3::new(-v.y, v.x, 0.0) / length_squared.sqrt()
+        } else {
+            Vector3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = v.cross(&t1);
+
+        // a point on the disk the hemisphere projects to, seen from `v`
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + v.z);
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let normal = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * v;
+
+        // and back again
+        Vector3::new(
+            self.alpha_x * normal.x,
+            self.alpha_y * normal.y,
+            normal.z.max(1e-9),
+        )
+        .to_unit_vector()
+    }
+
+    /// Density, per unit solid angle, of `sample_visible_normal` picking
+    /// `m` when seen from `wo`
+    pub fn visible_normal_pdf(&self, wo: &Vector3, m: &Vector3) -> f64 {
+        if wo.z <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Ggx;
+    use crate::geom::{random_unit_vector, Vector3};
+    use crate::random;
+    use rand::Rng;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn facets_cover_surface() {
+        random::reseed(1);
+        // the facets' projected areas add up to the surface's
+        for ggx in [Ggx::from_roughness(0.5, 0.), Ggx::from_roughness(0.7, 0.8)] {
+            let samples = 200_000;
+            let total: f64 = (0..samples)
+                .map(|_| {
+                    let m = random_unit_vector();
+                    ggx.d(&m) * m.z.max(0.)
+                })
+                .sum();
+            let integral = 4. * PI * total / samples as f64;
+            assert!((integral - 1.).abs() < 0.02, "{:?}: {}", ggx, integral);
+        }
+    }
+
+    #[test]
+    fn visible_normals_match_their_pdf() {
+        random::reseed(1);
+        let ggx = Ggx::from_roughness(0.6, 0.5);
+        let wo = Vector3::new(0.5, -0.3, 0.6).to_unit_vector();
+        // the density integrates to 1 over the facets seen from `wo`
+        let samples = 200_000;
+        let total: f64 = (0..samples)
+            .map(|_| ggx.visible_normal_pdf(&wo, &random_unit_vector()))
+            .sum();
+        assert!((4. * PI * total / samples as f64 - 1.).abs() < 0.02);
+
+        // and the samples have the same mean as the density
+        let mut rng = random::rng();
+        let mut mean = Vector3::new(0., 0., 0.);
+        for _ in 0..samples {
+            let m = ggx.sample_visible_normal(&wo, rng.gen(), rng.gen());
+            assert!(m.z > 0. && wo.dot(&m) >= -1e-9);
+            mean += m / samples as f64;
+        }
+        let mut expected = Vector3::new(0., 0., 0.);
+        for _ in 0..samples {
+            let m = random_unit_vector();
+            expected += m * (4. * PI * ggx.visible_normal_pdf(&wo, &m) / samples as f64);
+        }
+        assert!((mean - expected).norm() < 0.02, "{:?} {:?}", mean, expected);
+    }
+}
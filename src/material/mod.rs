@@ -0,0 +1,555 @@
+use crate::bump::Bump;
+use crate::color::*;
+use crate::geom::*;
+use crate::random;
+use crate::ray::Ray;
+use crate::shapes::Intersection;
+use crate::texture::Texture;
+use rand::prelude::*;
+use std::f64::consts::PI;
+
+mod conductor;
+mod dielectric;
+mod fresnel;
+mod microfacet;
+mod principled;
+
+pub use conductor::{Conductor, MetalPreset};
+pub use dielectric::RoughDielectric;
+use fresnel::dielectric_reflectance;
+pub use principled::{Principled, PrincipledParameters};
+
+/// Different types of material
+///
+/// A material will produce a scattered ray (or say it absorbed the incident ray).
+/// If scattered, say how much the ray should be attenuated.
+
+#[derive(Debug, Clone)]
+pub enum Material {
+    DiffuseNonMetal(Texture),
+    /// A metal with an albedo and a fuzz, from 0 for a mirror to 1. The fuzz
+    /// is the luminance of a texture, so it can vary over the surface.
+    Metal(Texture, Texture),
+    // get known refractive indices from https://en.wikipedia.org/wiki/List_of_refractive_indices
+    /// Smooth glass with an index of refraction, and the fraction of each
+    /// color that survives a unit distance inside it
+    Dielectric(f64, Color),
+    /// Emits light of the given color, scaled by an intensity, from the
+    /// front of the surface, and absorbs all light that hits it
+    DiffuseLight(Color, f64),
+    /// Another material with its shading normal tilted by a normal or bump
+    /// map
+    Bumped(Box<Material>, Bump),
+    /// A metal with GGX microfacet roughness and a complex index of
+    /// refraction
+    Conductor(Conductor),
+    /// Frosted glass, with GGX microfacet roughness, absorbing like
+    /// `Dielectric`
+    RoughDielectric(RoughDielectric, Color),
+    /// Disney's principled BSDF, which blends diffuse, metal, clearcoat and
+    /// glass with a few parameters
+    Principled(Box<Principled>),
+}
+
+/// Fraction of the light that survives the incident ray's trip to the
+/// intersection, by the Beer-Lambert law: all of it, unless the ray
+/// travelled through the medium, which lets through `attenuation` per
+/// unit distance.
+fn transmittance(attenuation: &Color, incident_ray: &Ray, intersect: &Intersection) -> Color {
+    if intersect.ray_hit_outer_surface {
+        return Color::WHITE;
+    }
+    let distance = intersect.t * incident_ray.direction.norm();
+    Color::new(
+        attenuation.red.powf(distance),
+        attenuation.green.powf(distance),
+        attenuation.blue.powf(distance),
+    )
+}
+
+/// The frame that microfacet materials work in at the intersection, with
+/// the tangent along u so anisotropic highlights follow the texture
+fn shading_frame(intersect: &Intersection) -> Frame {
+    Frame::from_tangents(intersect.normal, intersect.dpdu, intersect.dpdv)
+}
+
+/// Fuzz where textured fuzz goes down to 0, since a mirror-like spot on a
+/// fuzzy metal still needs a finite density
+const MIN_FUZZ: f64 = 1e-3;
+
+/// The metal's fuzz at the intersection
+fn fuzz_at(fuzz: &Texture, intersect: &Intersection) -> f64 {
+    fuzz.value_at(intersect).luminance().clamp(MIN_FUZZ, 1.0)
+}
+
+/// Density, per unit solid angle, of the fuzzy reflection directions picked
+/// by `Metal::scatter`: the mirror direction plus a random point in a ball
+/// of radius `fuzz`. `cos_theta` is the cosine of the angle between the
+/// direction and the mirror direction.
+fn fuzzy_reflection_pdf(cos_theta: f64, fuzz: f64) -> f64 {
+    let sin_squared = 1.0 - cos_theta * cos_theta;
+    let discriminant = fuzz * fuzz - sin_squared;
+    if discriminant < 0.0 || cos_theta <= 0.0 {
+        return 0.0;
+    }
+    // the direction's line enters the ball at `near` and leaves at `far`,
+    // and the density is the volume of the ball it passes through
+    let root = discriminant.sqrt();
+    let near = (cos_theta - root).max(0.0);
+    let far = cos_theta + root;
+    (far.powi(3) - near.powi(3)) / (4.0 * PI * fuzz.powi(3))
+}
+
+impl Material {
+    /// Whether `scatter` only ever picks one direction, like a mirror or
+    /// glass. Lights can't be sampled for such materials, since a random
+    /// direction to a light would never be the one that's scattered.
+    pub fn is_specular(&self) -> bool {
+        match self {
+            Material::Metal(_, Texture::Constant(fuzz)) => fuzz.luminance() <= 0.0,
+            Material::Metal(_, _) => false,
+            Material::Dielectric(_, _) => true,
+            Material::DiffuseNonMetal(_) | Material::DiffuseLight(_, _) => false,
+            Material::Bumped(material, _) => material.is_specular(),
+            Material::Conductor(conductor) => conductor.is_specular(),
+            Material::RoughDielectric(_, _) | Material::Principled(_) => false,
+        }
+    }
+
+    /// Tilts the intersection's normal by the material's normal or bump
+    /// map, if it has one. This has to happen before the material scatters
+    /// or evaluates light there.
+    pub fn perturb_normal(&self, intersect: &mut Intersection) {
+        if let Material::Bumped(material, bump) = self {
+            material.perturb_normal(intersect);
+            bump.apply(intersect);
+        }
+    }
+
+    /// Probability density, per unit solid angle, of `scatter` picking
+    /// `direction` for the incident ray. Zero for specular materials.
+    pub fn pdf(&self, incident_ray: &Ray, intersect: &Intersection, direction: &Vector3) -> f64 {
+        let direction = direction.to_unit_vector();
+        match self {
+            Material::DiffuseNonMetal(_) => intersect.normal.dot(&direction).max(0.0) / PI,
+            Material::Metal(_, fuzz) if !self.is_specular() => {
+                if intersect.normal.dot(&direction) <= 0.0 {
+                    // scattered rays below the surface are absorbed
+                    return 0.0;
+                }
+                let reflection = incident_ray
+                    .direction
+                    .to_unit_vector()
+                    .reflect(&intersect.normal);
+                fuzzy_reflection_pdf(reflection.dot(&direction), fuzz_at(fuzz, intersect))
+            }
+            Material::Bumped(material, _) => material.pdf(incident_ray, intersect, &direction),
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                conductor.pdf(&wo, &frame.to_local(&direction))
+            }
+            Material::RoughDielectric(glass, _) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                glass.pdf(
+                    &wo,
+                    &frame.to_local(&direction),
+                    intersect.ray_hit_outer_surface,
+                )
+            }
+            Material::Principled(principled) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                principled.pdf(
+                    principled.base_color().value_at(intersect),
+                    &wo,
+                    &frame.to_local(&direction),
+                    intersect.ray_hit_outer_surface,
+                )
+            }
+            _ => 0.0,
+        }
+    }
+
+    /// The BSDF times the cosine of the angle between `direction` and the
+    /// normal: how much of the light arriving from `direction` is scattered
+    /// back along the incident ray. Zero for specular materials.
+    pub fn eval(&self, incident_ray: &Ray, intersect: &Intersection, direction: &Vector3) -> Color {
+        match self {
+            Material::DiffuseNonMetal(albedo) => {
+                let cosine = intersect.normal.dot(&direction.to_unit_vector());
+                albedo.value_at(intersect) * (cosine.max(0.0) / PI)
+            }
+            // fuzzy metal is defined by how it scatters, so it reflects
+            // `albedo` times the density of each direction
+            Material::Metal(albedo, _) => {
+                albedo.value_at(intersect) * self.pdf(incident_ray, intersect, direction)
+            }
+            Material::Bumped(material, _) => material.eval(incident_ray, intersect, direction),
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                conductor.eval(&wo, &frame.to_local(&direction.to_unit_vector()))
+            }
+            Material::RoughDielectric(glass, attenuation) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                let wi = frame.to_local(&direction.to_unit_vector());
+                transmittance(attenuation, incident_ray, intersect)
+                    * glass.eval(&wo, &wi, intersect.ray_hit_outer_surface)
+            }
+            Material::Principled(principled) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                principled.eval(
+                    principled.base_color().value_at(intersect),
+                    &wo,
+                    &frame.to_local(&direction.to_unit_vector()),
+                    intersect.ray_hit_outer_surface,
+                )
+            }
+            _ => Color::BLACK,
+        }
+    }
+
+    /// Radiance given off at the intersection. Only lights emit anything,
+    /// and only from their front (outer) side.
+    pub fn emitted(&self, intersect: &Intersection) -> Color {
+        match self {
+            Material::DiffuseLight(color, intensity) if intersect.ray_hit_outer_surface => {
+                *color * *intensity
+            }
+            Material::Bumped(material, _) => material.emitted(intersect),
+            _ => Color::BLACK,
+        }
+    }
+
+    /// Picks the direction light arriving along `incident_ray` leaves in,
+    /// and how much of it. `None` if the light is absorbed.
+    pub fn scatter(&self, incident_ray: Ray, intersect: &Intersection) -> Option<(Ray, Color)> {
+        match self {
+            Material::DiffuseNonMetal(albedo) => {
+                // Lambertian Reflection
+                let scatter_direction = intersect.normal + random_unit_vector();
+                let scattered_ray = Ray::new(intersect.p, scatter_direction);
+                Some((scattered_ray, albedo.value_at(intersect)))
+            }
+
+            Material::Metal(albedo, fuzz) => {
+                let reflection = incident_ray
+                    .direction
+                    .to_unit_vector()
+                    .reflect(&intersect.normal);
+
+                // Fuzzy reflection
+                let fuzz = if self.is_specular() {
+                    0.0
+                } else {
+                    fuzz_at(fuzz, intersect)
+                };
+                let direction = reflection + fuzz * random_point_in_unit_sphere();
+                let scattered_ray = Ray::new(intersect.p, direction);
+
+                if scattered_ray.direction.dot(&intersect.normal) > 0.0 {
+                    Some((scattered_ray, albedo.value_at(intersect)))
+                } else {
+                    None
+                }
+            }
+
+            Material::Dielectric(index_of_refraction, attenuation) => {
+                // Snell's law
+                let refraction_ratio = if intersect.ray_hit_outer_surface {
+                    1.0 / index_of_refraction
+                } else {
+                    *index_of_refraction
+                };
+
+                let incident_direction = incident_ray.direction.to_unit_vector();
+                // reflectance is 1 past the critical angle, for total
+                // internal reflection
+                let cos_theta = intersect.normal.dot(&-incident_direction).min(1.0);
+                let mut rng = random::rng();
+                let reflect = dielectric_reflectance(cos_theta, 1.0 / refraction_ratio) > rng.gen();
+
+                let (refracted_direction, scale) = if reflect {
+                    (incident_direction.reflect(&intersect.normal), 1.0)
+                } else {
+                    // refracted light is squeezed into a narrower cone, or
+                    // spread over a wider one
+                    (
+                        incident_direction.refract(&intersect.normal, refraction_ratio),
+                        refraction_ratio * refraction_ratio,
+                    )
+                };
+
+                let scattered_ray = Ray::new(intersect.p, refracted_direction);
+                let survived = transmittance(attenuation, &incident_ray, intersect);
+                Some((scattered_ray, survived * scale))
+            }
+
+            Material::DiffuseLight(_, _) => None,
+            Material::Bumped(material, _) => material.scatter(incident_ray, intersect),
+
+            Material::Conductor(conductor) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                let mut rng = random::rng();
+                let (wi, weight) = conductor.sample(&wo, rng.gen(), rng.gen())?;
+                Some((Ray::new(intersect.p, frame.to_world(&wi)), weight))
+            }
+
+            Material::RoughDielectric(glass, attenuation) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                let mut rng = random::rng();
+                let (wi, weight) = glass.sample(
+                    &wo,
+                    rng.gen(),
+                    rng.gen(),
+                    rng.gen(),
+                    intersect.ray_hit_outer_surface,
+                )?;
+                let survived = transmittance(attenuation, &incident_ray, intersect);
+                Some((
+                    Ray::new(intersect.p, frame.to_world(&wi)),
+                    survived * weight,
+                ))
+            }
+
+            Material::Principled(principled) => {
+                let frame = shading_frame(intersect);
+                let wo = frame.to_local(&-incident_ray.direction.to_unit_vector());
+                let (wi, weight) = principled.sample(
+                    principled.base_color().value_at(intersect),
+                    &wo,
+                    intersect.ray_hit_outer_surface,
+                    &mut random::rng(),
+                )?;
+                Some((Ray::new(intersect.p, frame.to_world(&wi)), weight))
+            }
+        }
+    }
+}
+
+/// Selects a material based on the provided probability and random number generator.
+///
+/// # Arguments
+///
+/// * `p_material` - A float representing the probability of selecting a particular material.
+/// * `rng` - A mutable reference to a random number generator.
+///
+/// # Returns
+///
+/// * `Material` - The selected material.
+///
+/// # Example
+///
+/// ```
+/// use rand::prelude::*;
+/// use ray_tracing_weekend::material::select_material;
+/// use ray_tracing_weekend::random;
+///
+/// let mut rng = random::rng();
+/// let p_material: f64 = rng.gen();
+/// let material = select_material(p_material, &mut rng);
+/// ```
+pub fn select_material<R: Rng>(p_material: f64, rng: &mut R) -> Material {
+    if p_material < 0.1 {
+        // dielectric => cinnabar
+        Material::Dielectric(3.02, Color::CINNABAR)
+    } else if p_material < 0.2 {
+        // dielectric => diamond
+        Material::Dielectric(3.02, Color::DIAMOND)
+    } else if p_material < 0.8 {
+        // diffuse non-metal
+        Material::DiffuseNonMetal(Texture::Constant(Color::diffuse_albedo()))
+    } else if p_material < 0.95 {
+        // metal
+        let fuzz: f64 = rng.gen_range(0. ..0.5);
+        Material::Metal(Texture::Constant(Color::metal_albedo()), fuzz.into())
+    } else {
+        // dielectric
+        Material::Dielectric(1.5, Color::WHITE)
+    }
+}
+
+/// Checks a BSDF's sampling against its `eval` and `pdf`, for directions
+/// `wi`. Each of `samples` calls to `sample` may return a direction and its
+/// weight, which must be `eval / pdf`, and the density must integrate to
+/// the fraction of samples returned, within `tolerance`. Returns the
+/// directions sampled.
+#[cfg(test)]
+pub(crate) fn check_sampling(
+    mut sample: impl FnMut() -> Option<(Vector3, Color)>,
+    eval: impl Fn(&Vector3) -> Color,
+    pdf: impl Fn(&Vector3) -> f64,
+    samples: usize,
+    tolerance: f64,
+) -> Vec<Vector3> {
+    let directions: Vec<Vector3> = (0..samples)
+        .filter_map(|_| sample())
+        .map(|(wi, weight)| {
+            let expected = eval(&wi) * (1. / pdf(&wi));
+            for (actual, expected) in [
+                (weight.red, expected.red),
+                (weight.green, expected.green),
+                (weight.blue, expected.blue),
+            ] {
+                assert!(
+                    (actual - expected).abs() < 1e-9 * (1. + expected),
+                    "{} {}",
+                    actual,
+                    expected
+                );
+            }
+            wi
+        })
+        .collect();
+    // the density covers the directions that are returned, and no others
+    let total: f64 = (0..samples).map(|_| pdf(&random_unit_vector())).sum();
+    let integral = 4. * PI * total / samples as f64;
+    let fraction = directions.len() as f64 / samples as f64;
+    assert!(
+        (integral - fraction).abs() < tolerance,
+        "{} {}",
+        integral,
+        fraction
+    );
+    directions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        Conductor, Material, MetalPreset, Principled, PrincipledParameters, RoughDielectric,
+    };
+    use crate::color::Color;
+    use crate::geom::{random_unit_vector, Point3, Vector3};
+    use crate::random;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, Sphere, INFINITY};
+    use crate::texture::Texture;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn scattering_pdfs_integrate_to_one() {
+        random::reseed(1);
+        let sphere = Sphere::new(
+            Point3::origin(),
+            1.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        );
+        let r = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+        let hit = sphere.hit(&r, Interval::new(1e-3, INFINITY)).unwrap();
+        for material in [
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+            Material::Metal(Texture::Constant(Color::WHITE), 0.3.into()),
+        ] {
+            // integrate over the sphere of directions by sampling it uniformly
+            let samples = 200_000;
+            let total: f64 = (0..samples)
+                .map(|_| material.pdf(&r, &hit, &random_unit_vector()))
+                .sum();
+            let integral = 4. * PI * total / samples as f64;
+            assert!((integral - 1.).abs() < 0.03, "{:?}: {}", material, integral);
+        }
+    }
+
+    #[test]
+    fn scattered_rays_match_eval_and_pdf() {
+        let sphere = Sphere::new(
+            Point3::origin(),
+            1.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        );
+        let r = Ray::new(Point3::new(0., 0.5, 5.), Vector3::new(0., 0., -1.));
+        let hit = sphere.hit(&r, Interval::new(1e-3, INFINITY)).unwrap();
+        let albedo = Texture::Constant(Color::new(0.9, 0.5, 0.1));
+        for material in [
+            Material::DiffuseNonMetal(albedo.clone()),
+            Material::Metal(albedo.clone(), 0.2.into()),
+            Material::Conductor(Conductor::from_preset(MetalPreset::Gold, 0.3, 0.5)),
+            Material::RoughDielectric(RoughDielectric::new(1.5, 0.3), Color::WHITE),
+            Material::Principled(Box::new(Principled::new(PrincipledParameters {
+                base_color: albedo.clone(),
+                metallic: 0.2,
+                clearcoat: 0.5,
+                transmission: 0.3,
+                ..Default::default()
+            }))),
+        ] {
+            for _ in 0..100 {
+                if let Some((scattered, attenuation)) = material.scatter(r, &hit) {
+                    // the attenuation is the usual Monte Carlo weight
+                    let pdf = material.pdf(&r, &hit, &scattered.direction);
+                    let weight = material.eval(&r, &hit, &scattered.direction) * (1. / pdf);
+                    assert!((weight.red - attenuation.red).abs() < 1e-6 * weight.red);
+                    assert!((weight.blue - attenuation.blue).abs() < 1e-6 * weight.blue);
+                }
+            }
+        }
+        assert!(Material::Dielectric(1.5, Color::WHITE).is_specular());
+        assert!(Material::Metal(albedo.clone(), 0.0.into()).is_specular());
+        assert!(!Material::Metal(albedo, 0.2.into()).is_specular());
+        assert!(
+            Material::Conductor(Conductor::from_preset(MetalPreset::Silver, 0., 0.)).is_specular()
+        );
+    }
+
+    #[test]
+    fn glass_absorbs_by_distance_travelled() {
+        random::reseed(1);
+        let attenuation = Color::new(0.5, 0.8, 1.);
+        for glass in [
+            Material::Dielectric(1.5, attenuation),
+            Material::RoughDielectric(RoughDielectric::new(1.5, 0.2), attenuation),
+        ] {
+            let sphere = Sphere::new(Point3::origin(), 2., glass.clone());
+            let interval = Interval::new(1e-3, INFINITY);
+            // nothing is absorbed on the way in
+            let from_outside = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+            let hit = sphere.hit(&from_outside, interval).unwrap();
+            for _ in 0..100 {
+                if let Some((scattered, weight)) = glass.scatter(from_outside, &hit) {
+                    if scattered.direction.z > 0. {
+                        assert_eq!(weight.red, weight.blue);
+                    }
+                }
+            }
+            // but a ray that crossed 2 units of glass keeps 0.5² of its red
+            let from_inside = Ray::new(Point3::origin(), Vector3::new(0., 0., -1.));
+            let hit = sphere.hit(&from_inside, interval).unwrap();
+            for _ in 0..100 {
+                if let Some((scattered, weight)) = glass.scatter(from_inside, &hit) {
+                    if scattered.direction.z < 0. {
+                        continue;
+                    }
+                    // light reflected back inside keeps its radiance
+                    assert!((weight.red / weight.blue - 0.25).abs() < 1e-9);
+                    assert!((weight.green / weight.blue - 0.64).abs() < 1e-9);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn lights_emit_from_outside_only() {
+        let light = Material::DiffuseLight(Color::new(1., 0.5, 0.25), 4.);
+        let sphere = Sphere::new(Point3::origin(), 1., light.clone());
+        let interval = Interval::new(1e-3, INFINITY);
+
+        let from_outside = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+        let hit = sphere.hit(&from_outside, interval).unwrap();
+        assert_eq!(light.emitted(&hit), Color::new(4., 2., 1.));
+        assert!(light.scatter(from_outside, &hit).is_none());
+
+        let from_inside = Ray::new(Point3::origin(), Vector3::new(0., 0., -1.));
+        let hit = sphere.hit(&from_inside, interval).unwrap();
+        assert_eq!(light.emitted(&hit), Color::BLACK);
+
+        let diffuse = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+        assert_eq!(diffuse.emitted(&hit), Color::BLACK);
+    }
+}
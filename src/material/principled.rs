@@ -0,0 +1,411 @@
+use crate::color::Color;
+use crate::geom::Vector3;
+use crate::material::dielectric::RoughDielectric;
+use crate::material::microfacet::Ggx;
+use crate::texture::Texture;
+use rand::Rng;
+use std::f64::consts::PI;
+
+// material/principled.rs
+//
+// One material for everything from plastic to metal to glass, after
+// Burley's "Physically Based Shading at Disney", 2012, and its 2015
+// follow-up that added transmission. A handful of parameters from 0 to 1
+// blend five lobes: a diffuse base with a sheen on top, GGX reflection,
+// a clearcoat layer, and frosted glass. Directions are in the local frame
+// of `microfacet.rs`, with `wo` on the side the normal points to, and
+// `outside` says whether that side is outside the material.
+
+/// How strongly each lobe is picked when sampling: diffuse (with sheen),
+/// specular reflection, clearcoat and glass
+type LobeWeights = [f64; 4];
+
+/// The settings an artist picks, all from 0 to 1 except the index of
+/// refraction
+#[derive(Debug, Clone)]
+pub struct PrincipledParameters {
+    pub base_color: Texture,
+    /// 0 for dielectrics such as plastic, 1 for metals, which reflect
+    /// light tinted by their base color and don't diffuse it
+    pub metallic: f64,
+    pub roughness: f64,
+    /// Strength of a dielectric's reflection; 0.5 reflects 4% head on
+    pub specular: f64,
+    /// How far a dielectric's reflection is tinted by its base color
+    pub specular_tint: f64,
+    /// A soft glow at grazing angles, as on cloth
+    pub sheen: f64,
+    pub sheen_tint: f64,
+    /// A second, colorless layer of reflection, as on car paint
+    pub clearcoat: f64,
+    /// 0 for a satin clearcoat, 1 for a glossy one
+    pub clearcoat_gloss: f64,
+    /// How much of a dielectric is glass rather than diffuse, letting
+    /// light through tinted by the base color
+    pub transmission: f64,
+    pub index_of_refraction: f64,
+}
+
+impl Default for PrincipledParameters {
+    fn default() -> Self {
+        PrincipledParameters {
+            base_color: Texture::Constant(Color::new(0.8, 0.8, 0.8)),
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            transmission: 0.0,
+            index_of_refraction: 1.5,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Principled {
+    parameters: PrincipledParameters,
+    specular_lobe: Ggx,
+    clearcoat_lobe: Ggx,
+    glass: RoughDielectric,
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + t * (b - a)
+}
+
+fn lerp_color(a: Color, b: Color, t: f64) -> Color {
+    a * (1.0 - t) + b * t
+}
+
+/// Schlick's approximation to how reflectance rises from `r0` head on to 1
+/// at grazing angles
+fn schlick_weight(cos_theta: f64) -> f64 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+/// The hue of `color`, with a luminance of 1
+fn tint(color: Color) -> Color {
+    let luminance = color.luminance();
+    if luminance > 0.0 {
+        color * (1.0 / luminance)
+    } else {
+        Color::WHITE
+    }
+}
+
+/// A direction above the surface with density cos θ / π, from two random
+/// numbers in [0, 1)
+fn cosine_direction(u1: f64, u2: f64) -> Vector3 {
+    let r = u1.sqrt();
+    let phi = 2.0 * PI * u2;
+    Vector3::new(r * phi.cos(), r * phi.sin(), (1.0 - u1).sqrt())
+}
+
+/// Density, per unit solid angle, of reflecting `wo` into `wi` off facets
+/// picked from `lobe`
+fn reflection_pdf(lobe: &Ggx, wo: &Vector3, wi: &Vector3) -> f64 {
+    let m = (*wo + *wi).to_unit_vector();
+    let cos_d = wo.dot(&m);
+    if cos_d <= 0.0 {
+        return 0.0;
+    }
+    lobe.visible_normal_pdf(wo, &m) / (4.0 * cos_d)
+}
+
+impl Principled {
+    pub fn new(parameters: PrincipledParameters) -> Self {
+        let specular_lobe = Ggx::from_roughness(parameters.roughness, 0.0);
+        // Burley maps gloss to a width this way, though his clearcoat
+        // uses a longer-tailed distribution than GGX
+        let clearcoat_lobe = Ggx::new(
+            lerp(0.1, 0.001, parameters.clearcoat_gloss),
+            lerp(0.1, 0.001, parameters.clearcoat_gloss),
+        );
+        let glass = RoughDielectric::new(parameters.index_of_refraction, parameters.roughness);
+        Principled {
+            parameters,
+            specular_lobe,
+            clearcoat_lobe,
+            glass,
+        }
+    }
+
+    pub fn base_color(&self) -> &Texture {
+        &self.parameters.base_color
+    }
+
+    /// The reflectance of the specular lobe head on: a few percent for
+    /// dielectrics, and the base color for metals
+    fn specular_color(&self, base_color: Color) -> Color {
+        let p = &self.parameters;
+        let dielectric =
+            lerp_color(Color::WHITE, tint(base_color), p.specular_tint) * (0.08 * p.specular);
+        lerp_color(dielectric, base_color, p.metallic)
+    }
+
+    /// How much light each lobe reflects, roughly, so that sampling
+    /// favors the brightest. A lobe only gets no weight when it reflects
+    /// nothing at all.
+    fn lobe_weights(&self, base_color: Color, outside: bool) -> LobeWeights {
+        let p = &self.parameters;
+        if !outside && p.transmission > 0.0 {
+            // the other lobes are on the outside, so light inside glass
+            // only meets the glass
+            return [0.0, 0.0, 0.0, 1.0];
+        }
+        let transmission = (1.0 - p.metallic) * p.transmission;
+        let diffuse = (1.0 - p.metallic) * (1.0 - p.transmission);
+        [
+            diffuse * (base_color.luminance() + p.sheen),
+            // Fresnel reflection reaches 1 at grazing angles, however
+            // dark it is head on
+            (1.0 - transmission) * self.specular_color(base_color).luminance().max(0.1),
+            0.25 * p.clearcoat,
+            transmission,
+        ]
+    }
+
+    /// The BSDF times the cosine at `wi`, for light arriving from `wi` and
+    /// leaving along `wo`, with the base color at the hit
+    pub fn eval(&self, base_color: Color, wo: &Vector3, wi: &Vector3, outside: bool) -> Color {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return Color::BLACK;
+        }
+        let p = &self.parameters;
+        let [_, _, _, glass_weight] = self.lobe_weights(base_color, outside);
+        let glass = self.glass.eval(wo, wi, outside) * glass_weight;
+        if wi.z < 0.0 {
+            // tinted on the way in and out, so light that passes through
+            // takes on the base color
+            let filter = Color::new(
+                base_color.red.sqrt(),
+                base_color.green.sqrt(),
+                base_color.blue.sqrt(),
+            );
+            return filter * glass;
+        }
+        if !outside && p.transmission > 0.0 {
+            return Color::WHITE * glass;
+        }
+
+        let m = (*wo + *wi).to_unit_vector();
+        let cos_d = wi.dot(&m);
+        let fresnel = schlick_weight(cos_d);
+
+        // Burley's diffuse, which darkens smooth surfaces and brightens
+        // rough ones at grazing angles
+        let diffuse_weight = (1.0 - p.metallic) * (1.0 - p.transmission);
+        let fd90 = 0.5 + 2.0 * p.roughness * cos_d * cos_d;
+        let retro = (1.0 + (fd90 - 1.0) * schlick_weight(wo.z))
+            * (1.0 + (fd90 - 1.0) * schlick_weight(wi.z));
+        let diffuse = base_color * (retro / PI);
+        let sheen_color = lerp_color(Color::WHITE, tint(base_color), p.sheen_tint);
+        let sheen = sheen_color * (p.sheen * fresnel);
+
+        let specular_weight = 1.0 - (1.0 - p.metallic) * p.transmission;
+        let f0 = self.specular_color(base_color);
+        let specular = lerp_color(f0, Color::WHITE, fresnel)
+            * (self.specular_lobe.d(&m) * self.specular_lobe.g(wo, wi) / (4.0 * wo.z));
+
+        let clearcoat_fresnel = lerp(0.04, 1.0, fresnel);
+        let clearcoat = 0.25
+            * p.clearcoat
+            * clearcoat_fresnel
+            * self.clearcoat_lobe.d(&m)
+            * self.clearcoat_lobe.g(wo, wi)
+            / (4.0 * wo.z);
+
+        (diffuse + sheen) * (diffuse_weight * wi.z)
+            + specular * specular_weight
+            + Color::WHITE * (clearcoat + glass)
+    }
+
+    /// Density, per unit solid angle, of `sample` picking `wi`
+    pub fn pdf(&self, base_color: Color, wo: &Vector3, wi: &Vector3, outside: bool) -> f64 {
+        if wo.z <= 0.0 || wi.z == 0.0 {
+            return 0.0;
+        }
+        let weights = self.lobe_weights(base_color, outside);
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return 0.0;
+        }
+        let [diffuse, specular, clearcoat, glass] = weights;
+        let mut pdf = glass * self.glass.pdf(wo, wi, outside);
+        if wi.z > 0.0 {
+            pdf += diffuse * wi.z / PI
+                + specular * reflection_pdf(&self.specular_lobe, wo, wi)
+                + clearcoat * reflection_pdf(&self.clearcoat_lobe, wo, wi);
+        }
+        pdf / total
+    }
+
+    /// Picks the direction light leaving along `wo` arrives from, by first
+    /// picking a lobe, and returns it with the weight `eval / pdf`. `None`
+    /// if the light is absorbed.
+    pub fn sample<R: Rng>(
+        &self,
+        base_color: Color,
+        wo: &Vector3,
+        outside: bool,
+        rng: &mut R,
+    ) -> Option<(Vector3, Color)> {
+        if wo.z <= 0.0 {
+            return None;
+        }
+        let weights = self.lobe_weights(base_color, outside);
+        let total: f64 = weights.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        let mut pick = rng.gen::<f64>() * total;
+        let mut lobe = weights.len() - 1;
+        for (i, weight) in weights.iter().enumerate() {
+            if pick < *weight {
+                lobe = i;
+                break;
+            }
+            pick -= weight;
+        }
+
+        let (u1, u2) = (rng.gen(), rng.gen());
+        let reflect = |distribution: &Ggx| {
+            let m = distribution.sample_visible_normal(wo, u1, u2);
+            2.0 * wo.dot(&m) * m - *wo
+        };
+        let wi = match lobe {
+            0 => cosine_direction(u1, u2),
+            1 => reflect(&self.specular_lobe),
+            2 => reflect(&self.clearcoat_lobe),
+            _ => self.glass.sample(wo, u1, u2, rng.gen(), outside)?.0,
+        };
+        // the weight allows for every lobe that could have picked `wi`
+        let pdf = self.pdf(base_color, wo, &wi, outside);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some((wi, self.eval(base_color, wo, &wi, outside) * (1.0 / pdf)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Principled, PrincipledParameters};
+    use crate::color::Color;
+    use crate::geom::Vector3;
+    use crate::material::check_sampling;
+    use crate::random;
+
+    /// Average of the weights of `samples` scattered directions, which is
+    /// the fraction of light arriving from everywhere that's scattered
+    /// along `wo`
+    fn albedo(material: &Principled, base_color: Color, wo: &Vector3, samples: usize) -> Color {
+        let mut rng = random::rng();
+        let mut total = Color::BLACK;
+        for _ in 0..samples {
+            if let Some((_, weight)) = material.sample(base_color, wo, true, &mut rng) {
+                total += weight;
+            }
+        }
+        total * (1. / samples as f64)
+    }
+
+    #[test]
+    fn samples_match_eval_and_pdf() {
+        random::reseed(1);
+        let base_color = Color::new(0.8, 0.4, 0.2);
+        let material = Principled::new(PrincipledParameters {
+            metallic: 0.3,
+            roughness: 0.5,
+            sheen: 0.5,
+            clearcoat: 0.7,
+            clearcoat_gloss: 0.3,
+            transmission: 0.5,
+            ..Default::default()
+        });
+        let wo = Vector3::new(0.3, -0.4, 0.7).to_unit_vector();
+        let mut rng = random::rng();
+        for outside in [true, false] {
+            check_sampling(
+                || material.sample(base_color, &wo, outside, &mut rng),
+                |wi| material.eval(base_color, &wo, wi, outside),
+                |wi| material.pdf(base_color, &wo, wi, outside),
+                200_000,
+                0.03,
+            );
+        }
+    }
+
+    #[test]
+    fn metals_reflect_their_base_color() {
+        random::reseed(1);
+        let base_color = Color::new(0.9, 0.6, 0.2);
+        let metal = Principled::new(PrincipledParameters {
+            metallic: 1.,
+            roughness: 0.3,
+            ..Default::default()
+        });
+        let wo = Vector3::new(0., 0., 1.);
+        let reflected = albedo(&metal, base_color, &wo, 50_000);
+        // nothing is diffused or transmitted, and microfacets lose a
+        // little light to shadowing
+        assert!((reflected.red - 0.9).abs() < 0.05, "{:?}", reflected);
+        assert!((reflected.blue - 0.2).abs() < 0.03, "{:?}", reflected);
+        let mut rng = random::rng();
+        for _ in 0..1000 {
+            if let Some((wi, _)) = metal.sample(base_color, &wo, true, &mut rng) {
+                assert!(wi.z > 0.);
+            }
+        }
+    }
+
+    #[test]
+    fn plastic_is_diffuse_with_colorless_highlights() {
+        random::reseed(1);
+        let plastic = Principled::new(PrincipledParameters {
+            roughness: 0.2,
+            ..Default::default()
+        });
+        let base_color = Color::new(0.5, 0.1, 0.1);
+        let wo = Vector3::new(0.6, 0., 0.8);
+        // the mirror direction is bright in every color
+        let mirror = Vector3::new(-0.6, 0., 0.8);
+        let highlight = plastic.eval(base_color, &wo, &mirror, true);
+        assert!(highlight.blue > 1., "{:?}", highlight);
+        // away from it, only the base color is diffused
+        let aside = Vector3::new(0.6, 0., 0.8);
+        let diffuse = plastic.eval(base_color, &wo, &aside, true);
+        assert!(diffuse.red > 4. * diffuse.blue, "{:?}", diffuse);
+        // and light never goes through
+        let below = Vector3::new(-0.3, 0., -0.9).to_unit_vector();
+        assert_eq!(plastic.eval(base_color, &wo, &below, true), Color::BLACK);
+    }
+
+    #[test]
+    fn transmission_lets_tinted_light_through() {
+        random::reseed(1);
+        let glass = Principled::new(PrincipledParameters {
+            roughness: 0.1,
+            transmission: 1.,
+            ..Default::default()
+        });
+        let base_color = Color::new(1., 0.5, 1.);
+        let wo = Vector3::new(0., 0., 1.);
+        let mut rng = random::rng();
+        let mut through = 0;
+        for _ in 0..1000 {
+            if let Some((wi, weight)) = glass.sample(base_color, &wo, true, &mut rng) {
+                if wi.z < 0. {
+                    through += 1;
+                    // half of it on the way in
+                    assert!((weight.green / weight.red - 0.5f64.sqrt()).abs() < 1e-9);
+                }
+            }
+        }
+        // about 4% is reflected head on
+        assert!(through > 900, "{}", through);
+    }
+}
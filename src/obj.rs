@@ -0,0 +1,720 @@
+use crate::color::Color;
+use crate::geom::{Point3, Vector3};
+use crate::material::Material;
+use crate::shapes::{Shape, Triangle, TriangleMesh};
+use crate::texture::Texture;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+// obj.rs
+
+/// Index of refraction used for transparent MTL materials without `Ni`
+const DEFAULT_INDEX_OF_REFRACTION: f64 = 1.5;
+
+/// Diffuse color used for MTL materials without `Kd`
+const DEFAULT_DIFFUSE: Color = Color {
+    red: 0.8,
+    green: 0.8,
+    blue: 0.8,
+};
+
+/// Errors from reading Wavefront OBJ and MTL files
+#[derive(Debug)]
+pub enum ObjError {
+    /// A file could not be read
+    Io { path: PathBuf, error: io::Error },
+    /// A line of a file could not be understood
+    Parse {
+        path: PathBuf,
+        line: usize,
+        message: String,
+    },
+}
+
+impl fmt::Display for ObjError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ObjError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            ObjError::Parse {
+                path,
+                line,
+                message,
+            } => write!(f, "{}:{}: {}", path.display(), line, message),
+        }
+    }
+}
+
+impl std::error::Error for ObjError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ObjError::Io { error, .. } => Some(error),
+            ObjError::Parse { .. } => None,
+        }
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, ObjError> {
+    fs::read_to_string(path).map_err(|error| ObjError::Io {
+        path: path.to_path_buf(),
+        error,
+    })
+}
+
+/// Reads the OBJ file at `path`, along with any MTL libraries it references,
+/// and returns its faces as triangles. Faces with more than three corners
+/// are split into a fan of triangles. Faces that come before any `usemtl`
+/// statement get `default_material`.
+pub fn load_obj(path: &Path, default_material: Material) -> Result<Vec<Shape>, ObjError> {
+    let source = read_file(path)?;
+    parse_obj(&source, path, default_material)
+}
+
+/// Parses the text of an OBJ file. `path` is used in error messages and to
+/// find MTL libraries, which are looked up relative to its directory.
+pub fn parse_obj(
+    source: &str,
+    path: &Path,
+    default_material: Material,
+) -> Result<Vec<Shape>, ObjError> {
+    let model = ObjModel::parse(source, path, default_material)?;
+    let shapes = model
+        .faces
+        .iter()
+        .map(|face| {
+            let [a, b, c] = face.corners;
+            let vertices = (
+                model.positions[a.position],
+                model.positions[b.position],
+                model.positions[c.position],
+            );
+            let material = model.materials[face.material].clone();
+            let triangle = match (a.normal, b.normal, c.normal) {
+                (Some(na), Some(nb), Some(nc)) => Triangle::with_normals(
+                    vertices.0,
+                    vertices.1,
+                    vertices.2,
+                    (model.normals[na], model.normals[nb], model.normals[nc]),
+                    material,
+                ),
+                _ => Triangle::new(vertices.0, vertices.1, vertices.2, material),
+            };
+            Shape::Triangle(triangle)
+        })
+        .collect();
+    Ok(shapes)
+}
+
+/// Same as `load_obj`, but returns one `TriangleMesh` per material instead
+/// of separate triangles, which takes much less memory for large models
+pub fn load_obj_meshes(
+    path: &Path,
+    default_material: Material,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let source = read_file(path)?;
+    parse_obj_meshes(&source, path, default_material)
+}
+
+/// Same as `parse_obj`, but returns one `TriangleMesh` per material
+pub fn parse_obj_meshes(
+    source: &str,
+    path: &Path,
+    default_material: Material,
+) -> Result<Vec<TriangleMesh>, ObjError> {
+    let model = ObjModel::parse(source, path, default_material)?;
+    let meshes = (0..model.materials.len())
+        .filter_map(|material| {
+            let faces: Vec<&ObjFace> = model
+                .faces
+                .iter()
+                .filter(|face| face.material == material)
+                .collect();
+            if faces.is_empty() {
+                None
+            } else {
+                Some(model.make_mesh(&faces, model.materials[material].clone()))
+            }
+        })
+        .collect();
+    Ok(meshes)
+}
+
+/// One corner of a face, as zero-based indices into the model's buffers
+#[derive(Debug, Copy, Clone)]
+struct ObjCorner {
+    position: usize,
+    texture_coordinate: Option<usize>,
+    normal: Option<usize>,
+}
+
+/// A triangle of the model, and the index of its material in `ObjModel::materials`
+#[derive(Debug)]
+struct ObjFace {
+    corners: [ObjCorner; 3],
+    material: usize,
+}
+
+/// Everything we read from an OBJ file, before it's turned into shapes
+#[derive(Debug)]
+struct ObjModel {
+    positions: Vec<Point3>,
+    normals: Vec<Vector3>,
+    texture_coordinates: Vec<(f64, f64)>,
+    faces: Vec<ObjFace>,
+    // the default material comes first, then those from MTL libraries
+    materials: Vec<Material>,
+}
+
+impl ObjModel {
+    fn parse(source: &str, path: &Path, default_material: Material) -> Result<Self, ObjError> {
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut model = ObjModel {
+            positions: Vec::new(),
+            normals: Vec::new(),
+            texture_coordinates: Vec::new(),
+            faces: Vec::new(),
+            materials: vec![default_material],
+        };
+        let mut material_indices: HashMap<String, usize> = HashMap::new();
+        let mut current_material = 0;
+
+        for (line_index, line) in source.lines().enumerate() {
+            let line_number = line_index + 1;
+            let error = |message: String| ObjError::Parse {
+                path: path.to_path_buf(),
+                line: line_number,
+                message,
+            };
+
+            let mut tokens = line.split_whitespace();
+            let keyword = match tokens.next() {
+                Some(keyword) if !keyword.starts_with('#') => keyword,
+                _ => continue,
+            };
+            let arguments: Vec<&str> = tokens.collect();
+
+            match keyword {
+                "v" => {
+                    let [x, y, z] = parse_numbers(keyword, &arguments, 3, 4).map_err(error)?;
+                    model.positions.push(Point3::new(x, y, z));
+                }
+                "vn" => {
+                    let [x, y, z] = parse_numbers(keyword, &arguments, 3, 3).map_err(error)?;
+                    model.normals.push(Vector3::new(x, y, z).to_unit_vector());
+                }
+                "vt" => {
+                    let [u, v] = parse_numbers(keyword, &arguments, 1, 3).map_err(error)?;
+                    model.texture_coordinates.push((u, v));
+                }
+                "f" => {
+                    if arguments.len() < 3 {
+                        return Err(error(format!(
+                            "face needs at least 3 vertices, found {}",
+                            arguments.len()
+                        )));
+                    }
+                    let corners = arguments
+                        .iter()
+                        .map(|corner| model.parse_face_corner(corner))
+                        .collect::<Result<Vec<_>, String>>()
+                        .map_err(error)?;
+
+                    for i in 1..corners.len() - 1 {
+                        model.faces.push(ObjFace {
+                            corners: [corners[0], corners[i], corners[i + 1]],
+                            material: current_material,
+                        });
+                    }
+                }
+                "mtllib" => {
+                    if arguments.is_empty() {
+                        return Err(error("'mtllib' needs a file name".to_string()));
+                    }
+                    for file_name in &arguments {
+                        let mtl_path = base_dir.join(file_name);
+                        let materials = read_file(&mtl_path)
+                            .and_then(|mtl_source| parse_mtl(&mtl_source, &mtl_path))
+                            .map_err(|mtl_error| {
+                                error(format!("can't load material library: {}", mtl_error))
+                            })?;
+                        for (name, material) in materials {
+                            material_indices.insert(name, model.materials.len());
+                            model.materials.push(material);
+                        }
+                    }
+                }
+                "usemtl" => {
+                    let name = arguments.join(" ");
+                    current_material = *material_indices
+                        .get(&name)
+                        .ok_or_else(|| error(format!("unknown material '{}'", name)))?;
+                }
+                // groups, objects, smoothing groups, lines and points don't
+                // affect the triangles we produce
+                _ => {}
+            }
+        }
+
+        Ok(model)
+    }
+
+    /// Resolves one `v`, `v/vt`, `v//vn` or `v/vt/vn` corner of a face
+    fn parse_face_corner(&self, corner: &str) -> Result<ObjCorner, String> {
+        let mut parts = corner.split('/');
+        let position = parts.next().unwrap_or("");
+        let texture_coordinate = parts.next().unwrap_or("");
+        let normal = parts.next().unwrap_or("");
+        if parts.next().is_some() {
+            return Err(format!("malformed face vertex '{}'", corner));
+        }
+
+        let optional_index = |index: &str, count: usize, kind: &str| {
+            if index.is_empty() {
+                Ok(None)
+            } else {
+                resolve_index(index, count, kind).map(Some)
+            }
+        };
+        Ok(ObjCorner {
+            position: resolve_index(position, self.positions.len(), "vertex")?,
+            texture_coordinate: optional_index(
+                texture_coordinate,
+                self.texture_coordinates.len(),
+                "texture coordinate",
+            )?,
+            normal: optional_index(normal, self.normals.len(), "normal")?,
+        })
+    }
+
+    /// Builds a mesh holding only the buffer entries that `faces` use.
+    /// Normals and texture coordinates are kept only if every face has them.
+    fn make_mesh(&self, faces: &[&ObjFace], material: Material) -> TriangleMesh {
+        let corners = || faces.iter().flat_map(|face| face.corners.iter());
+
+        let (positions, position_indices) =
+            compact(&self.positions, faces, |corner| Some(corner.position));
+        let mut mesh = TriangleMesh::new(positions, position_indices.unwrap(), material);
+
+        if corners().all(|corner| corner.normal.is_some()) {
+            let (normals, indices) = compact(&self.normals, faces, |corner| corner.normal);
+            mesh = mesh.with_normals(normals, indices.unwrap());
+        }
+        if corners().all(|corner| corner.texture_coordinate.is_some()) {
+            let (texture_coordinates, indices) =
+                compact(&self.texture_coordinates, faces, |corner| {
+                    corner.texture_coordinate
+                });
+            mesh = mesh.with_texture_coordinates(texture_coordinates, indices.unwrap());
+        }
+        mesh
+    }
+}
+
+/// Copies the elements of `buffer` referred to by `faces` into a new buffer,
+/// and returns it along with the faces' indices into it. The indices are
+/// `None` if any corner has no index for this buffer.
+fn compact<T: Copy, F: Fn(&ObjCorner) -> Option<usize>>(
+    buffer: &[T],
+    faces: &[&ObjFace],
+    index_of: F,
+) -> (Vec<T>, Option<Vec<[u32; 3]>>) {
+    let mut remapped: Vec<Option<u32>> = vec![None; buffer.len()];
+    let mut compacted = Vec::new();
+    let indices = faces
+        .iter()
+        .map(|face| {
+            let mut triangle = [0u32; 3];
+            for (slot, corner) in triangle.iter_mut().zip(face.corners.iter()) {
+                let original = index_of(corner)?;
+                *slot = *remapped[original].get_or_insert_with(|| {
+                    compacted.push(buffer[original]);
+                    (compacted.len() - 1) as u32
+                });
+            }
+            Some(triangle)
+        })
+        .collect();
+    (compacted, indices)
+}
+
+/// Converts a one-based (or, if negative, relative to the end) OBJ index
+/// into a zero-based index into a list of `count` elements
+fn resolve_index(index: &str, count: usize, kind: &str) -> Result<usize, String> {
+    let value: i64 = index
+        .parse()
+        .map_err(|_| format!("invalid {} index '{}'", kind, index))?;
+    let resolved = if value < 0 {
+        count as i64 + value
+    } else {
+        value - 1
+    };
+    if value == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(format!(
+            "{} index {} is out of range ({} defined so far)",
+            kind, value, count
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+/// Parses between `min` and `max` numbers following `keyword`, returning the
+/// first `N` of them
+fn parse_numbers<const N: usize>(
+    keyword: &str,
+    arguments: &[&str],
+    min: usize,
+    max: usize,
+) -> Result<[f64; N], String> {
+    if arguments.len() < min || arguments.len() > max {
+        let expected = if min == max {
+            format!("{}", min)
+        } else {
+            format!("{} to {}", min, max)
+        };
+        return Err(format!(
+            "'{}' needs {} numbers, found {}",
+            keyword,
+            expected,
+            arguments.len()
+        ));
+    }
+    let mut numbers = [0.0; N];
+    for (i, argument) in arguments.iter().enumerate() {
+        let number: f64 = argument
+            .parse()
+            .map_err(|_| format!("invalid number '{}' in '{}'", argument, keyword))?;
+        if i < N {
+            numbers[i] = number;
+        }
+    }
+    Ok(numbers)
+}
+
+fn parse_color(keyword: &str, arguments: &[&str]) -> Result<Color, String> {
+    if arguments.first() == Some(&"spectral") || arguments.first() == Some(&"xyz") {
+        return Err(format!(
+            "'{} {}' colors are not supported",
+            keyword, arguments[0]
+        ));
+    }
+    // a single value means a gray
+    let [r, g, b] = match parse_numbers::<3>(keyword, arguments, 1, 3)? {
+        [r, _, _] if arguments.len() == 1 => [r, r, r],
+        [r, g, b] if arguments.len() == 3 => [r, g, b],
+        _ => return Err(format!("'{}' needs 1 or 3 numbers", keyword)),
+    };
+    Ok(Color::new(r, g, b))
+}
+
+/// The MTL statements we understand, for one `newmtl` block
+#[derive(Debug, Default)]
+struct MtlParameters {
+    diffuse: Option<Color>,
+    specular: Option<Color>,
+    emission: Option<Color>,
+    transmission_filter: Option<Color>,
+    specular_exponent: Option<f64>,
+    index_of_refraction: Option<f64>,
+    opacity: Option<f64>,
+    illumination_model: Option<u32>,
+}
+
+impl MtlParameters {
+    /// Picks the closest of our material types:
+    ///
+    /// * materials with a non-black emissive color `Ke` become
+    ///   `DiffuseLight`
+    /// * transparent materials (`d` < 1, `Tr` > 0, or a refraction
+    ///   illumination model) become `Dielectric` with index `Ni`, letting
+    ///   through `Tf` of each color per unit distance
+    /// * materials with a ray-traced reflection illumination model, or a
+    ///   specular color but no diffuse color, become `Metal` colored by `Ks`,
+    ///   with the fuzz derived from the specular exponent `Ns`
+    /// * everything else becomes `DiffuseNonMetal` colored by `Kd`
+    fn to_material(&self) -> Material {
+        let is_black = |c: Option<Color>| c.is_none_or(|c| c.red.max(c.green).max(c.blue) <= 0.0);
+        let illum = self.illumination_model.unwrap_or(2);
+
+        if let Some(emission) = self.emission.filter(|e| !is_black(Some(*e))) {
+            return Material::DiffuseLight(emission, 1.0);
+        }
+
+        let transparent = self.opacity.is_some_and(|d| d < 1.0) || matches!(illum, 4 | 6 | 7 | 9);
+        if transparent {
+            return Material::Dielectric(
+                self.index_of_refraction
+                    .unwrap_or(DEFAULT_INDEX_OF_REFRACTION),
+                self.transmission_filter.unwrap_or(Color::WHITE),
+            );
+        }
+
+        let reflective = matches!(illum, 3 | 5 | 8) || is_black(self.diffuse);
+        if reflective && !is_black(self.specular) {
+            // the usual conversion from a Phong exponent to a roughness
+            let fuzz = match self.specular_exponent {
+                Some(exponent) => (2.0 / (exponent.max(0.0) + 2.0)).sqrt(),
+                None => 0.0,
+            };
+            return Material::Metal(
+                Texture::Constant(self.specular.unwrap_or(Color::WHITE)),
+                fuzz.min(1.0).into(),
+            );
+        }
+
+        Material::DiffuseNonMetal(Texture::Constant(self.diffuse.unwrap_or(DEFAULT_DIFFUSE)))
+    }
+}
+
+/// Parses the text of an MTL file into materials keyed by name. `path` is
+/// only used in error messages.
+pub fn parse_mtl(source: &str, path: &Path) -> Result<HashMap<String, Material>, ObjError> {
+    let mut materials = HashMap::new();
+    let mut current: Option<(String, MtlParameters)> = None;
+
+    for (line_index, line) in source.lines().enumerate() {
+        let error = |message: String| ObjError::Parse {
+            path: path.to_path_buf(),
+            line: line_index + 1,
+            message,
+        };
+
+        let mut tokens = line.split_whitespace();
+        let keyword = match tokens.next() {
+            Some(keyword) if !keyword.starts_with('#') => keyword,
+            _ => continue,
+        };
+        let arguments: Vec<&str> = tokens.collect();
+
+        if keyword == "newmtl" {
+            if arguments.is_empty() {
+                return Err(error("'newmtl' needs a material name".to_string()));
+            }
+            if let Some((name, parameters)) = current.take() {
+                materials.insert(name, parameters.to_material());
+            }
+            current = Some((arguments.join(" "), MtlParameters::default()));
+            continue;
+        }
+
+        let parameters = match current.as_mut() {
+            Some((_, parameters)) => parameters,
+            None => {
+                return Err(error(format!(
+                    "'{}' comes before any 'newmtl' statement",
+                    keyword
+                )))
+            }
+        };
+        let number = || parse_numbers::<1>(keyword, &arguments, 1, 1).map(|[x]| x);
+
+        match keyword {
+            "Kd" => parameters.diffuse = Some(parse_color(keyword, &arguments).map_err(error)?),
+            "Ks" => parameters.specular = Some(parse_color(keyword, &arguments).map_err(error)?),
+            "Ke" => parameters.emission = Some(parse_color(keyword, &arguments).map_err(error)?),
+            "Tf" => {
+                parameters.transmission_filter =
+                    Some(parse_color(keyword, &arguments).map_err(error)?)
+            }
+            "Ns" => parameters.specular_exponent = Some(number().map_err(error)?),
+            "Ni" => {
+                let index_of_refraction = number().map_err(error)?;
+                if index_of_refraction <= 0.0 {
+                    return Err(error(format!(
+                        "'Ni' must be positive, got {}",
+                        index_of_refraction
+                    )));
+                }
+                parameters.index_of_refraction = Some(index_of_refraction);
+            }
+            "d" => {
+                // `d -halo 0.5` is an old variant we treat like plain `d`
+                let arguments: Vec<&str> = arguments
+                    .iter()
+                    .filter(|a| **a != "-halo")
+                    .copied()
+                    .collect();
+                let [d] = parse_numbers(keyword, &arguments, 1, 1).map_err(error)?;
+                parameters.opacity = Some(d);
+            }
+            "Tr" => parameters.opacity = Some(1.0 - number().map_err(error)?),
+            "illum" => {
+                let model = arguments
+                    .first()
+                    .and_then(|a| a.parse::<u32>().ok())
+                    .filter(|_| arguments.len() == 1)
+                    .ok_or_else(|| error("'illum' needs one whole number".to_string()))?;
+                parameters.illumination_model = Some(model);
+            }
+            // ambient colors, texture maps and so on have no
+            // counterpart in our materials
+            _ => {}
+        }
+    }
+
+    if let Some((name, parameters)) = current {
+        materials.insert(name, parameters.to_material());
+    }
+    Ok(materials)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{load_obj, parse_mtl, parse_obj, parse_obj_meshes, ObjError};
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, INFINITY};
+    use crate::texture::Texture;
+    use std::fs;
+    use std::path::Path;
+
+    const DEFAULT: Material = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+
+    fn assert_parse_error(result: Result<impl std::fmt::Debug, ObjError>, expected_line: usize) {
+        match result {
+            Err(ObjError::Parse { line, .. }) => assert_eq!(line, expected_line),
+            other => panic!(
+                "expected parse error on line {}, got {:?}",
+                expected_line, other
+            ),
+        }
+    }
+
+    #[test]
+    fn can_parse_faces_into_triangles() {
+        let source = "
+# a unit square in the xy-plane and a triangle using negative indices
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vn 0 0 1
+f 1/1/1 2/1/1 3/1/1 4/1/1
+f -4 -3 -2
+";
+        let shapes = parse_obj(source, Path::new("square.obj"), DEFAULT).unwrap();
+        assert_eq!(shapes.len(), 3);
+
+        let r = Ray::new(Point3::new(0.25, 0.75, 1.), Vector3::new(0., 0., -1.));
+        let hits = shapes
+            .iter()
+            .filter_map(|s| s.hit(&r, Interval::new(0., INFINITY)))
+            .count();
+        assert_eq!(hits, 1);
+    }
+
+    #[test]
+    fn can_parse_faces_into_meshes() {
+        let source = "
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+v 5 5 5
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+f 1/1 2/2 3/3 4/4
+";
+        let meshes = parse_obj_meshes(source, Path::new("square.obj"), DEFAULT).unwrap();
+        assert_eq!(meshes.len(), 1);
+        let mesh = &meshes[0];
+        assert_eq!(mesh.len(), 2);
+
+        let r = Ray::new(Point3::new(0.25, 0.75, 1.), Vector3::new(0., 0., -1.));
+        let hit = mesh.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_eq!(hit.p, Point3::new(0.25, 0.75, 0.));
+        // the unused vertex is left out of the mesh
+        assert_eq!(mesh.bounding_box().x.size(), 1.);
+        assert!(mesh.texture_coordinates(0, (1., 0., 0.)).is_some());
+    }
+
+    #[test]
+    fn can_map_mtl_materials() {
+        let source = "
+newmtl matte
+Kd 0.5 0.25 0.125
+
+newmtl mirror
+Kd 0 0 0
+Ks 0.9 0.9 0.9
+Ns 1000
+illum 3
+
+newmtl glass
+Ni 1.33
+d 0.1
+Tf 0.9 1 0.9
+
+newmtl lamp
+Kd 0.8 0.8 0.8
+Ke 4 4 3
+";
+        let materials = parse_mtl(source, Path::new("test.mtl")).unwrap();
+        assert!(matches!(
+            materials["matte"],
+            Material::DiffuseNonMetal(Texture::Constant(Color { red, .. })) if red == 0.5
+        ));
+        assert!(matches!(
+            materials["mirror"],
+            Material::Metal(_, Texture::Constant(Color { red: fuzz, .. })) if fuzz < 0.05
+        ));
+        assert!(matches!(
+            materials["glass"],
+            Material::Dielectric(index, Color { red, .. }) if index == 1.33 && red == 0.9
+        ));
+        assert!(matches!(
+            materials["lamp"],
+            Material::DiffuseLight(Color { blue, .. }, _) if blue == 3.
+        ));
+    }
+
+    #[test]
+    fn reports_line_numbers() {
+        let path = Path::new("bad.obj");
+        assert_parse_error(parse_obj("v 0 0 0\nv 1 x 0\n", path, DEFAULT), 2);
+        assert_parse_error(parse_obj("v 0 0 0\nv 1 0 0\n\nf 1 2 3\n", path, DEFAULT), 4);
+        assert_parse_error(parse_obj("v 0 0 0\nf 1 1\n", path, DEFAULT), 2);
+        assert_parse_error(parse_obj("usemtl missing\n", path, DEFAULT), 1);
+        assert_parse_error(parse_mtl("Kd 1 1 1\n", Path::new("bad.mtl")), 1);
+        assert_parse_error(parse_mtl("newmtl a\nKd 1 1\n", Path::new("bad.mtl")), 2);
+        assert_parse_error(parse_mtl("newmtl a\nNi 0\n", Path::new("bad.mtl")), 2);
+    }
+
+    #[test]
+    fn can_load_obj_with_material_library() {
+        let dir = std::env::temp_dir().join(format!("obj_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        fs::write(dir.join("matte.mtl"), "newmtl matte\nKd 0.5 0.5 0.5\n").unwrap();
+        fs::write(dir.join("shiny.mtl"), "newmtl shiny\nKs 1 1 1\nillum 3\n").unwrap();
+        // one statement can name several libraries
+        fs::write(
+            dir.join("model.obj"),
+            "mtllib matte.mtl shiny.mtl\nv 0 0 0\nv 1 0 0\nv 0 1 0\nf 1 2 3\nusemtl shiny\nf 3 2 1\n",
+        )
+        .unwrap();
+
+        let shapes = load_obj(&dir.join("model.obj"), DEFAULT).unwrap();
+        assert!(matches!(
+            shapes[0].get_material(),
+            Material::DiffuseNonMetal(_)
+        ));
+        assert!(matches!(shapes[1].get_material(), Material::Metal(_, _)));
+
+        let missing = load_obj(&dir.join("missing.obj"), DEFAULT);
+        assert!(matches!(missing, Err(ObjError::Io { .. })));
+        // a library that can't be read is blamed on the line naming it
+        fs::write(
+            dir.join("model.obj"),
+            "v 0 0 0\nmtllib shiny.mtl missing.mtl\n",
+        )
+        .unwrap();
+        assert_parse_error(load_obj(&dir.join("model.obj"), DEFAULT), 2);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
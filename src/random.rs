@@ -0,0 +1,72 @@
+use rand::rngs::SmallRng;
+use rand::{Error, RngCore, SeedableRng};
+use std::cell::RefCell;
+
+// random.rs
+
+thread_local! {
+    static THREAD_RNG: RefCell<SmallRng> = RefCell::new(SmallRng::from_entropy());
+}
+
+/// Handle to the current thread's random number generator. Unlike
+/// `rand::thread_rng()`, the generator can be reseeded with `reseed`, which
+/// is what makes renders with a fixed seed reproducible even though rayon
+/// decides which thread renders which pixel.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct SeededRng;
+
+/// Returns a handle to the current thread's generator
+pub fn rng() -> SeededRng {
+    SeededRng
+}
+
+/// Restarts the current thread's generator from `seed`
+pub fn reseed(seed: u64) {
+    THREAD_RNG.with(|rng| *rng.borrow_mut() = SmallRng::seed_from_u64(seed));
+}
+
+/// Combines two numbers into a well-mixed seed (SplitMix64's finalizer), so
+/// neighbouring pixels get unrelated random sequences
+pub fn mix_seed(seed: u64, index: u64) -> u64 {
+    let mut z = seed ^ index.wrapping_mul(0x9e37_79b9_7f4a_7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+impl RngCore for SeededRng {
+    fn next_u32(&mut self) -> u32 {
+        THREAD_RNG.with(|rng| rng.borrow_mut().next_u32())
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        THREAD_RNG.with(|rng| rng.borrow_mut().next_u64())
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        THREAD_RNG.with(|rng| rng.borrow_mut().fill_bytes(dest))
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        THREAD_RNG.with(|rng| rng.borrow_mut().try_fill_bytes(dest))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{reseed, rng};
+    use rand::Rng;
+
+    #[test]
+    fn reseeding_repeats_sequence() {
+        reseed(42);
+        let first: Vec<f64> = (0..8).map(|_| rng().gen()).collect();
+        reseed(42);
+        let second: Vec<f64> = (0..8).map(|_| rng().gen()).collect();
+        assert_eq!(first, second);
+
+        reseed(43);
+        let third: Vec<f64> = (0..8).map(|_| rng().gen()).collect();
+        assert_ne!(first, third);
+    }
+}
@@ -2,16 +2,33 @@ use crate::geom::{Point3, Vector3};
 
 // ray.rs
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Point3,
     pub direction: Vector3,
+    /// Angle, in radians, of the cone of rays this one stands for, such as
+    /// the rays through one pixel. Zero for a single ray.
+    pub spread: f64,
 }
 
 impl Ray {
     /// Creates a ray at `origin` along direction `direction`
     pub fn new(origin: Point3, direction: Vector3) -> Ray {
-        Ray { origin, direction }
+        Ray {
+            origin,
+            direction,
+            spread: 0.0,
+        }
+    }
+
+    /// The same ray, standing for a cone of rays `spread` radians wide
+    pub fn with_spread(self, spread: f64) -> Ray {
+        Ray { spread, ..self }
+    }
+
+    /// Width of the ray's cone at `t`
+    pub fn width_at(&self, t: f64) -> f64 {
+        self.spread * t * self.direction.norm()
     }
 
     /// Gives the ray at `t`.
@@ -32,6 +49,13 @@ mod tests {
         let r = Ray::new(origin, direction);
         assert_eq!(r.origin, origin);
         assert_eq!(r.direction, direction);
+        assert_eq!(r.width_at(2.), 0.);
+    }
+
+    #[test]
+    fn cones_widen_along_ray() {
+        let r = Ray::new(Point3::origin(), Vector3::new(0., 0., -2.)).with_spread(0.01);
+        assert!((r.width_at(3.) - 0.06).abs() < 1e-12);
     }
 
     #[test]
@@ -0,0 +1,1892 @@
+use crate::bump::Bump;
+use crate::camera::{Camera, CameraConfig, RenderConfig};
+use crate::canvas::Resolution;
+use crate::color::Color;
+use crate::environment::{
+    Environment, EnvironmentMap, Sky, MAX_TURBIDITY, MIN_TURBIDITY, SKY_BOTTOM, SKY_TOP,
+};
+use crate::geom::{Point3, Vector3};
+use crate::image::{read_image, ImageError, ImageFormat};
+use crate::integrator::{IntegratorKind, MisHeuristic};
+use crate::material::{
+    Conductor, Material, MetalPreset, Principled, PrincipledParameters, RoughDielectric,
+};
+use crate::obj::{load_obj_meshes, ObjError};
+use crate::shapes::{HittableObjects, Quad, Shape, Sphere, Triangle};
+use crate::texture::{
+    Filter, ImageTexture, NoisePattern, NoiseTexture, Texture, TextureAxis, WrapMode,
+    DEFAULT_OCTAVES,
+};
+use crate::tonemap::{ToneMapOperator, ToneMapping};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+// scene.rs
+//
+// Scenes are described in JSON files like this one:
+//
+// {
+//     "camera": {
+//         "look_from": [13, 2, 3],
+//         "look_at": [0, 0, 0],
+//         "view_up": [0, 1, 0],
+//         "vertical_fov": 20,
+//         "aperture": 0.1,
+//         "focus_dist": 10
+//     },
+//     "render": {
+//         "resolution": "720p",
+//         "samples_per_pixel": 100,
+//         "max_depth": 50,
+//         "tone_mapping": { "operator": "aces", "exposure": 0.5 }
+//     },
+//     "materials": {
+//         "ground": { "type": "diffuse", "albedo": [0.5, 0.5, 0.5] },
+//         "glass": { "type": "dielectric", "index_of_refraction": 1.5 },
+//         "gold": { "type": "metal", "albedo": [0.8, 0.6, 0.2], "fuzz": 0.1 },
+//         "earth": { "type": "diffuse", "albedo": { "type": "image", "path": "earth.png" } },
+//         "lamp": { "type": "diffuse_light", "color": [1, 0.9, 0.8], "intensity": 4 }
+//     },
+//     "shapes": [
+//         { "type": "sphere", "center": [0, -1000, 0], "radius": 1000, "material": "ground" },
+//         { "type": "triangle", "vertices": [[0, 0, 0], [1, 0, 0], [0, 1, 0]], "material": "gold" },
+//         { "type": "quad", "corner": [-1, 3, -1], "u": [2, 0, 0], "v": [0, 0, 2], "material": "lamp" },
+//         { "type": "mesh", "path": "teapot.obj", "material": "glass" }
+//     ],
+//     "background": [0, 0, 0]
+// }
+//
+// JSON has no NaN or infinity, so numbers only need range checks.
+// Mesh paths are relative to the scene file. A mesh's material is used for
+// faces without an MTL material. `focus_dist` defaults to the distance
+// between `look_from` and `look_at`, and `aperture` to 0 (a pinhole camera).
+// The tone mapping `operator` is one of clamp (the default), reinhard,
+// reinhard_extended (which takes an optional `white` point), filmic or aces,
+// and `exposure` is in stops. `mis_heuristic` sets how light samples and
+// scattered rays are combined: power (the default) or balance. Paths may be
+// ended at random once they've bounced `russian_roulette_depth` times
+// (default 5), and never bounce more than `max_depth` times. Instead of
+// tracing paths, the `integrator` can show the scene's geometry for
+// debugging: { "type": "normals" }, { "type": "depth" }, or
+// { "type": "ambient_occlusion", "distance": 2 }. The default is
+// { "type": "path" }.
+// The albedo of diffuse and metal materials is either a color or a texture:
+//
+//     { "type": "checker", "even": [1, 1, 1], "odd": [0, 0, 0], "size": 0.5 }
+//     { "type": "image", "path": "earth.png" }
+//     { "type": "gradient", "start": [0, 0, 0], "end": [1, 1, 1], "axis": "v" }
+//
+// A checker's squares are cubes filling space, with sides of length `size`
+// (default 1), and `even` and `odd` are colors or textures themselves.
+// Images are stretched over the shape's texture coordinates, with their
+// paths relative to the scene file. They take a few more settings:
+//
+//     { "type": "image", "path": "bricks.png", "wrap": "mirror",
+//       "filter": "nearest", "mipmaps": true, "srgb": false }
+//
+// `wrap` is what happens outside [0, 1]: `repeat` (the default), `clamp` or
+// `mirror`. `filter` is `bilinear` (the default) or `nearest`. Mipmaps blur
+// textures that are seen from far away instead of letting them alias.
+// `srgb` says the file's values are sRGB-encoded, as PNG and PPM colors
+// normally are, and need converting to linear values; it defaults to false
+// for PFM, Radiance and OpenEXR files, and for textures that hold data
+// rather than colors, like fuzz, normal maps and bump maps. Gradients run
+// along the u or v (default) texture coordinate.
+//
+// Noise textures fill space like checkers do, blending from `low` to `high`
+// (textures, colors or numbers, default 0 and 1) by the amount of noise:
+//
+//     { "type": "marble", "scale": 4, "octaves": 7, "seed": 1,
+//       "low": [0.9, 0.9, 0.85], "high": [0.2, 0.2, 0.3] }
+//
+// The types are `perlin`, `turbulence`, `marble`, `wood` (rings round the y
+// axis) and `worley` (cells). Features are about 1 / `scale` across, and
+// `octaves` (default 7) sets how much fine detail turbulence adds. The same
+// `seed` (default 0) always gives the same noise.
+//
+// A metal's `fuzz` is a number from 0 to 1, or a texture whose luminance
+// gives the fuzz at each point, such as
+// { "type": "worley", "scale": 5, "low": 0.05, "high": 0.4 }.
+//
+// Conductors are metals with GGX microfacet roughness, which reflect light
+// more faithfully than `metal`'s fuzz. Give either a measured `metal`
+// (gold, copper, aluminium or silver) or the complex index of refraction's
+// real and imaginary parts, `eta` and `k`, for red, green and blue light:
+//
+//     { "type": "conductor", "metal": "gold", "roughness": 0.3 }
+//     { "type": "conductor", "eta": [0.2, 0.92, 1.1], "k": [3.9, 2.45, 2.14],
+//       "roughness": 0.5, "anisotropy": 0.8 }
+//
+// `roughness` goes from 0, a perfect mirror (the default), to 1, and
+// `anisotropy`, from 0 up to but not including 1, stretches highlights
+// along the texture's u direction, like brushed metal.
+//
+// A dielectric's `attenuation` (default [1, 1, 1]) is the fraction of each
+// color that survives travelling a unit distance through it, so thick glass
+// is darker than thin glass. Giving it a `roughness` above 0 (up to 1)
+// frosts it:
+//
+//     { "type": "dielectric", "index_of_refraction": 1.5, "roughness": 0.2,
+//       "attenuation": [0.9, 0.95, 0.9] }
+//
+// The `principled` material is Disney's, which covers most materials with
+// one set of parameters. All but `index_of_refraction` (default 1.5) go
+// from 0 to 1, and the defaults are shown:
+//
+//     { "type": "principled", "base_color": [0.8, 0.8, 0.8], "metallic": 0,
+//       "roughness": 0.5, "specular": 0.5, "specular_tint": 0, "sheen": 0,
+//       "sheen_tint": 0.5, "clearcoat": 0, "clearcoat_gloss": 1,
+//       "transmission": 0, "index_of_refraction": 1.5 }
+//
+// `base_color` can be a texture. Metals (`metallic` 1) reflect it; other
+// materials diffuse it, under a colorless reflection whose strength is
+// `specular`, or let it through if they have `transmission`. `sheen` adds
+// the soft rim of cloth and `clearcoat` a glossy varnish.
+//
+// Any material can be given a normal map or a bump map by wrapping it:
+//
+//     { "type": "normal_mapped", "material": { "type": "diffuse", "albedo": [0.5, 0.5, 0.5] },
+//       "normal_map": { "type": "image", "path": "bricks_normal.png" } }
+//     { "type": "bump_mapped", "material": { "type": "metal", "albedo": [0.9, 0.9, 0.9] },
+//       "height": { "type": "turbulence", "scale": 8 }, "scale": 0.02 }
+//
+// Normal maps are in tangent space, with red along u, green along v and
+// blue out of the surface. A bump map raises each point by its height
+// texture's luminance times `scale`, in scene units.
+//
+// A quad is the parallelogram with edges `u` and `v` leaving `corner`, and
+// lights only shine from the side that `u × v` points to.
+//
+// Rays that miss everything see the `environment`, which also lights the
+// scene. It's one of
+//
+//     { "type": "black" }
+//     { "type": "constant", "color": [0.1, 0.1, 0.1] }
+//     { "type": "gradient", "bottom": [1, 1, 1], "top": [0.5, 0.7, 1] }
+//     { "type": "image", "path": "sky.hdr", "rotation": 90, "intensity": 2 }
+//     { "type": "sky", "sun_direction": [1, 0.5, 0], "turbidity": 3 }
+//
+// The default is the gradient shown, from the book. `background` is a
+// shorthand for a constant environment. An image is an equirectangular HDR
+// map in any format that can be read (see `image::read_image`), with its
+// path relative to the scene file. `rotation` turns it about the y axis, in
+// degrees, and `intensity` scales it. The sky is a daylight sky with a sun
+// in `sun_direction`, which must be above the horizon. `turbidity`, from
+// 1.7 to 10, is how hazy the air is (default 3), and there's an optional
+// `intensity` too.
+
+/// Everything needed to render an image
+pub struct Scene {
+    pub objects: HittableObjects,
+    pub camera_config: CameraConfig,
+    pub render_config: RenderConfig,
+}
+
+impl Scene {
+    /// Creates the camera, matching the aspect ratio of the rendered image
+    pub fn camera(&self) -> Camera {
+        self.camera_config.build(self.render_config.aspect_ratio())
+    }
+}
+
+/// Errors from loading a scene file
+#[derive(Debug)]
+pub enum SceneError {
+    /// The file could not be read
+    Io { path: PathBuf, error: io::Error },
+    /// The file is not valid JSON, or doesn't have the expected structure
+    Syntax {
+        path: PathBuf,
+        error: serde_json::Error,
+    },
+    /// The file is well formed but describes something impossible.
+    /// `context` says which part of the file is at fault, e.g. `shapes[2]`.
+    Invalid {
+        path: PathBuf,
+        context: String,
+        message: String,
+    },
+    /// A mesh referenced by the scene could not be loaded
+    Obj(ObjError),
+    /// An image referenced by the scene could not be loaded
+    Image(ImageError),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SceneError::Io { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Syntax { path, error } => write!(f, "{}: {}", path.display(), error),
+            SceneError::Invalid {
+                path,
+                context,
+                message,
+            } => write!(f, "{}: {}: {}", path.display(), context, message),
+            SceneError::Obj(error) => write!(f, "{}", error),
+            SceneError::Image(error) => write!(f, "{}", error),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            SceneError::Io { error, .. } => Some(error),
+            SceneError::Syntax { error, .. } => Some(error),
+            SceneError::Invalid { .. } => None,
+            SceneError::Obj(error) => Some(error),
+            SceneError::Image(error) => Some(error),
+        }
+    }
+}
+
+impl From<ObjError> for SceneError {
+    fn from(error: ObjError) -> Self {
+        SceneError::Obj(error)
+    }
+}
+
+impl From<ImageError> for SceneError {
+    fn from(error: ImageError) -> Self {
+        SceneError::Image(error)
+    }
+}
+
+// The structs below mirror the JSON layout. They are validated and turned
+// into the renderer's own types by `SceneDescription::build`.
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDescription {
+    camera: CameraDescription,
+    render: RenderDescription,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDescription>,
+    #[serde(default)]
+    shapes: Vec<ShapeDescription>,
+    background: Option<[f64; 3]>,
+    environment: Option<EnvironmentDescription>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CameraDescription {
+    look_from: [f64; 3],
+    look_at: [f64; 3],
+    view_up: [f64; 3],
+    /// In degrees
+    vertical_fov: f64,
+    #[serde(default)]
+    aperture: f64,
+    focus_dist: Option<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct RenderDescription {
+    resolution: String,
+    samples_per_pixel: usize,
+    max_depth: i32,
+    russian_roulette_depth: Option<i32>,
+    #[serde(default)]
+    tone_mapping: ToneMappingDescription,
+    #[serde(default)]
+    mis_heuristic: MisHeuristicDescription,
+    #[serde(default)]
+    integrator: IntegratorDescription,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum IntegratorDescription {
+    #[default]
+    Path,
+    Normals,
+    Depth,
+    AmbientOcclusion {
+        distance: Option<f64>,
+    },
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MisHeuristicDescription {
+    Balance,
+    #[default]
+    Power,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ToneMappingDescription {
+    #[serde(default)]
+    operator: ToneMapOperatorDescription,
+    #[serde(default)]
+    exposure: f64,
+    white: Option<f64>,
+}
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum ToneMapOperatorDescription {
+    #[default]
+    Clamp,
+    Reinhard,
+    ReinhardExtended,
+    Filmic,
+    Aces,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum MaterialDescription {
+    Diffuse {
+        albedo: TextureDescription,
+    },
+    Metal {
+        albedo: TextureDescription,
+        #[serde(default = "no_fuzz")]
+        fuzz: TextureDescription,
+    },
+    Dielectric {
+        index_of_refraction: f64,
+        #[serde(default = "white")]
+        attenuation: [f64; 3],
+        #[serde(default)]
+        roughness: f64,
+    },
+    DiffuseLight {
+        color: [f64; 3],
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+    Conductor {
+        metal: Option<MetalDescription>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+        #[serde(default)]
+        anisotropy: f64,
+    },
+    NormalMapped {
+        material: Box<MaterialDescription>,
+        normal_map: TextureDescription,
+    },
+    BumpMapped {
+        material: Box<MaterialDescription>,
+        height: TextureDescription,
+        scale: f64,
+    },
+    Principled {
+        #[serde(default = "default_base_color")]
+        base_color: TextureDescription,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "half")]
+        roughness: f64,
+        #[serde(default = "half")]
+        specular: f64,
+        #[serde(default)]
+        specular_tint: f64,
+        #[serde(default)]
+        sheen: f64,
+        #[serde(default = "half")]
+        sheen_tint: f64,
+        #[serde(default)]
+        clearcoat: f64,
+        #[serde(default = "one")]
+        clearcoat_gloss: f64,
+        #[serde(default)]
+        transmission: f64,
+        #[serde(default = "default_index_of_refraction")]
+        index_of_refraction: f64,
+    },
+}
+
+fn default_base_color() -> TextureDescription {
+    TextureDescription::Gray(0.8)
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+fn default_index_of_refraction() -> f64 {
+    1.5
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum EnvironmentDescription {
+    Black,
+    Constant {
+        color: [f64; 3],
+    },
+    Gradient {
+        #[serde(default = "sky_bottom")]
+        bottom: [f64; 3],
+        #[serde(default = "sky_top")]
+        top: [f64; 3],
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        rotation: f64,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+    Sky {
+        sun_direction: [f64; 3],
+        #[serde(default = "default_turbidity")]
+        turbidity: f64,
+        #[serde(default = "one")]
+        intensity: f64,
+    },
+}
+
+fn default_turbidity() -> f64 {
+    3.0
+}
+
+fn sky_bottom() -> [f64; 3] {
+    [SKY_BOTTOM.red, SKY_BOTTOM.green, SKY_BOTTOM.blue]
+}
+
+fn sky_top() -> [f64; 3] {
+    [SKY_TOP.red, SKY_TOP.green, SKY_TOP.blue]
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged, expecting = "a number, a color or a texture")]
+enum TextureDescription {
+    /// A shade of gray
+    Gray(f64),
+    Color([f64; 3]),
+    Texture(TextureKindDescription),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureKindDescription {
+    Checker {
+        even: Box<TextureDescription>,
+        odd: Box<TextureDescription>,
+        #[serde(default = "one")]
+        size: f64,
+    },
+    Image {
+        path: PathBuf,
+        #[serde(default)]
+        wrap: WrapDescription,
+        #[serde(default)]
+        filter: FilterDescription,
+        #[serde(default)]
+        mipmaps: bool,
+        /// Defaults to true for all but high dynamic range files
+        srgb: Option<bool>,
+    },
+    Gradient {
+        start: [f64; 3],
+        end: [f64; 3],
+        #[serde(default)]
+        axis: TextureAxisDescription,
+    },
+    Perlin(NoiseDescription),
+    Turbulence(NoiseDescription),
+    Marble(NoiseDescription),
+    Wood(NoiseDescription),
+    Worley(NoiseDescription),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct NoiseDescription {
+    #[serde(default = "one")]
+    scale: f64,
+    #[serde(default = "default_octaves")]
+    octaves: u32,
+    #[serde(default)]
+    seed: u64,
+    #[serde(default = "black_texture")]
+    low: Box<TextureDescription>,
+    #[serde(default = "white_texture")]
+    high: Box<TextureDescription>,
+}
+
+fn default_octaves() -> u32 {
+    DEFAULT_OCTAVES
+}
+
+fn no_fuzz() -> TextureDescription {
+    TextureDescription::Gray(0.0)
+}
+
+fn black_texture() -> Box<TextureDescription> {
+    Box::new(TextureDescription::Gray(0.0))
+}
+
+fn white_texture() -> Box<TextureDescription> {
+    Box::new(TextureDescription::Gray(1.0))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum MetalDescription {
+    Gold,
+    Copper,
+    #[serde(alias = "aluminum")]
+    Aluminium,
+    Silver,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum WrapDescription {
+    #[default]
+    Repeat,
+    Clamp,
+    Mirror,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum FilterDescription {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+enum TextureAxisDescription {
+    U,
+    #[default]
+    V,
+}
+
+fn white() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ShapeDescription {
+    Sphere {
+        center: [f64; 3],
+        radius: f64,
+        material: String,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        material: String,
+    },
+    Quad {
+        corner: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+    },
+    Mesh {
+        path: PathBuf,
+        material: Option<String>,
+    },
+}
+
+/// Reads and validates the scene file at `path`
+pub fn load_scene(path: &Path) -> Result<Scene, SceneError> {
+    let source = fs::read_to_string(path).map_err(|error| SceneError::Io {
+        path: path.to_path_buf(),
+        error,
+    })?;
+    parse_scene(&source, path)
+}
+
+/// Parses and validates the text of a scene file. `path` is used in error
+/// messages and to find meshes, which are looked up relative to its directory.
+pub fn parse_scene(source: &str, path: &Path) -> Result<Scene, SceneError> {
+    let description: SceneDescription =
+        serde_json::from_str(source).map_err(|error| SceneError::Syntax {
+            path: path.to_path_buf(),
+            error,
+        })?;
+    description.build(path)
+}
+
+fn point(p: [f64; 3]) -> Point3 {
+    Point3::new(p[0], p[1], p[2])
+}
+
+fn vector(v: [f64; 3]) -> Vector3 {
+    Vector3::new(v[0], v[1], v[2])
+}
+
+/// Checks that a color has no negative components
+fn color(c: [f64; 3], name: &str) -> Result<Color, String> {
+    if c.iter().any(|x| *x < 0.0 || !x.is_finite()) {
+        return Err(format!("{} {:?} must not be negative", name, c));
+    }
+    Ok(Color::new(c[0], c[1], c[2]))
+}
+
+impl SceneDescription {
+    fn build(self, path: &Path) -> Result<Scene, SceneError> {
+        let invalid = |context: &str, message: String| SceneError::Invalid {
+            path: path.to_path_buf(),
+            context: context.to_string(),
+            message,
+        };
+
+        let render_config = self
+            .render
+            .build()
+            .map_err(|message| invalid("render", message))?;
+        let camera_config = self
+            .camera
+            .build()
+            .map_err(|message| invalid("camera", message))?;
+
+        let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+        let mut materials: HashMap<&str, Material> = HashMap::new();
+        for (name, description) in self.materials.iter() {
+            let material = description
+                .build(base_dir)
+                .map_err(|message| invalid(&format!("materials.{}", name), message))?;
+            materials.insert(name, material);
+        }
+        let find_material = |name: &str| {
+            materials.get(name).cloned().ok_or_else(|| {
+                let mut known: Vec<&&str> = materials.keys().collect();
+                known.sort();
+                format!("unknown material '{}' (defined: {:?})", name, known)
+            })
+        };
+
+        let mut objects = HittableObjects::new();
+        objects.environment = match (self.background, &self.environment) {
+            (Some(_), Some(_)) => {
+                return Err(invalid(
+                    "background",
+                    "give a background or an environment, not both".to_string(),
+                ))
+            }
+            (Some(background), None) => Environment::Constant(
+                color(background, "background").map_err(|m| invalid("background", m))?,
+            ),
+            (None, Some(environment)) => environment.build(path)?,
+            (None, None) => Environment::default(),
+        };
+        for (i, shape) in self.shapes.iter().enumerate() {
+            let context = format!("shapes[{}]", i);
+            match shape {
+                ShapeDescription::Sphere {
+                    center,
+                    radius,
+                    material,
+                } => {
+                    if *radius <= 0.0 {
+                        return Err(invalid(
+                            &context,
+                            format!("radius must be positive, got {}", radius),
+                        ));
+                    }
+                    let material = find_material(material).map_err(|m| invalid(&context, m))?;
+                    objects.add(Shape::Sphere(Sphere::new(
+                        point(*center),
+                        *radius,
+                        material,
+                    )));
+                }
+                ShapeDescription::Triangle {
+                    vertices,
+                    normals,
+                    material,
+                } => {
+                    let material = find_material(material).map_err(|m| invalid(&context, m))?;
+                    let [a, b, c] = vertices.map(point);
+                    if (b - a).cross(&(c - a)).length_squared() == 0.0 {
+                        return Err(invalid(&context, "triangle has no area".to_string()));
+                    }
+                    let triangle = match normals {
+                        Some(normals) => {
+                            let [na, nb, nc] = normals.map(vector);
+                            if [na, nb, nc].iter().any(|n| n.length_squared() == 0.0) {
+                                return Err(invalid(
+                                    &context,
+                                    "vertex normals must not be zero".to_string(),
+                                ));
+                            }
+                            Triangle::with_normals(
+                                a,
+                                b,
+                                c,
+                                (
+                                    na.to_unit_vector(),
+                                    nb.to_unit_vector(),
+                                    nc.to_unit_vector(),
+                                ),
+                                material,
+                            )
+                        }
+                        None => Triangle::new(a, b, c, material),
+                    };
+                    objects.add(Shape::Triangle(triangle));
+                }
+                ShapeDescription::Quad {
+                    corner,
+                    u,
+                    v,
+                    material,
+                } => {
+                    let material = find_material(material).map_err(|m| invalid(&context, m))?;
+                    let (u, v) = (vector(*u), vector(*v));
+                    if u.cross(&v).length_squared() == 0.0 {
+                        return Err(invalid(&context, "quad has no area".to_string()));
+                    }
+                    objects.add(Shape::Quad(Quad::new(point(*corner), u, v, material)));
+                }
+                ShapeDescription::Mesh {
+                    path: mesh_path,
+                    material,
+                } => {
+                    let material = match material {
+                        Some(name) => find_material(name).map_err(|m| invalid(&context, m))?,
+                        None => {
+                            Material::DiffuseNonMetal(Texture::Constant(Color::new(0.8, 0.8, 0.8)))
+                        }
+                    };
+                    let meshes = load_obj_meshes(&base_dir.join(mesh_path), material)?;
+                    objects.extend(
+                        meshes
+                            .into_iter()
+                            .map(|mesh| Shape::TriangleMesh(Arc::new(mesh))),
+                    );
+                }
+            }
+        }
+
+        Ok(Scene {
+            objects,
+            camera_config,
+            render_config,
+        })
+    }
+}
+
+impl EnvironmentDescription {
+    /// Reads the environment map, if there is one, relative to the scene
+    /// file at `path`
+    fn build(&self, path: &Path) -> Result<Environment, SceneError> {
+        let invalid = |message: String| SceneError::Invalid {
+            path: path.to_path_buf(),
+            context: "environment".to_string(),
+            message,
+        };
+        match self {
+            EnvironmentDescription::Black => Ok(Environment::Black),
+            EnvironmentDescription::Constant { color: c } => {
+                Ok(Environment::Constant(color(*c, "color").map_err(invalid)?))
+            }
+            EnvironmentDescription::Gradient { bottom, top } => Ok(Environment::Gradient {
+                bottom: color(*bottom, "bottom").map_err(invalid)?,
+                top: color(*top, "top").map_err(invalid)?,
+            }),
+            EnvironmentDescription::Image {
+                path: map_path,
+                rotation,
+                intensity,
+            } => {
+                if *intensity < 0.0 {
+                    return Err(invalid(format!(
+                        "intensity must not be negative, got {}",
+                        intensity
+                    )));
+                }
+                let base_dir = path.parent().unwrap_or_else(|| Path::new(""));
+                let image = read_image(&base_dir.join(map_path))?;
+                if image.width() == 0 || image.height() == 0 {
+                    return Err(invalid("the image is empty".to_string()));
+                }
+                Ok(Environment::Map(EnvironmentMap::new(
+                    image, *rotation, *intensity,
+                )))
+            }
+            EnvironmentDescription::Sky {
+                sun_direction,
+                turbidity,
+                intensity,
+            } => {
+                let sun_direction = vector(*sun_direction);
+                if sun_direction.length_squared() == 0.0 {
+                    return Err(invalid("sun_direction must not be zero".to_string()));
+                }
+                if sun_direction.y < 0.0 {
+                    return Err(invalid("the sun must be above the horizon".to_string()));
+                }
+                if !(MIN_TURBIDITY..=MAX_TURBIDITY).contains(turbidity) {
+                    return Err(invalid(format!(
+                        "turbidity must be between {} and {}, got {}",
+                        MIN_TURBIDITY, MAX_TURBIDITY, turbidity
+                    )));
+                }
+                if *intensity < 0.0 {
+                    return Err(invalid(format!(
+                        "intensity must not be negative, got {}",
+                        intensity
+                    )));
+                }
+                Ok(Environment::Sky(Sky::new(
+                    sun_direction,
+                    *turbidity,
+                    *intensity,
+                )))
+            }
+        }
+    }
+}
+
+impl RenderDescription {
+    fn build(&self) -> Result<RenderConfig, String> {
+        let resolution: Resolution = self.resolution.parse()?;
+        if self.samples_per_pixel == 0 {
+            return Err("samples_per_pixel must be at least 1".to_string());
+        }
+        if self.max_depth < 1 {
+            return Err(format!(
+                "max_depth must be at least 1, got {}",
+                self.max_depth
+            ));
+        }
+        let mut render_config =
+            RenderConfig::new(resolution, self.samples_per_pixel, self.max_depth);
+        if let Some(depth) = self.russian_roulette_depth {
+            if depth < 0 {
+                return Err(format!(
+                    "russian_roulette_depth must not be negative, got {}",
+                    depth
+                ));
+            }
+            render_config.russian_roulette_depth = depth;
+        }
+        render_config.tone_mapping = self.tone_mapping.build()?;
+        render_config.mis_heuristic = match self.mis_heuristic {
+            MisHeuristicDescription::Balance => MisHeuristic::Balance,
+            MisHeuristicDescription::Power => MisHeuristic::Power,
+        };
+        render_config.integrator = match self.integrator {
+            IntegratorDescription::Path => IntegratorKind::Path,
+            IntegratorDescription::Normals => IntegratorKind::Normals,
+            IntegratorDescription::Depth => IntegratorKind::Depth,
+            IntegratorDescription::AmbientOcclusion { distance } => {
+                if let Some(distance) = distance.filter(|d| *d <= 0.0) {
+                    return Err(format!(
+                        "ambient occlusion distance must be positive, got {}",
+                        distance
+                    ));
+                }
+                IntegratorKind::AmbientOcclusion { distance }
+            }
+        };
+        Ok(render_config)
+    }
+}
+
+impl ToneMappingDescription {
+    fn build(&self) -> Result<ToneMapping, String> {
+        if let Some(white) = self.white {
+            if self.operator != ToneMapOperatorDescription::ReinhardExtended {
+                return Err("white is only used by the reinhard_extended operator".to_string());
+            }
+            if white <= 0.0 {
+                return Err(format!("white must be positive, got {}", white));
+            }
+        }
+        let operator = match self.operator {
+            ToneMapOperatorDescription::Clamp => ToneMapOperator::Clamp,
+            ToneMapOperatorDescription::Reinhard => ToneMapOperator::Reinhard,
+            ToneMapOperatorDescription::ReinhardExtended => {
+                ToneMapOperator::ReinhardExtended { white: self.white }
+            }
+            ToneMapOperatorDescription::Filmic => ToneMapOperator::Filmic,
+            ToneMapOperatorDescription::Aces => ToneMapOperator::Aces,
+        };
+        Ok(ToneMapping {
+            operator,
+            exposure: self.exposure,
+        })
+    }
+}
+
+impl CameraDescription {
+    fn build(&self) -> Result<CameraConfig, String> {
+        let look_from = point(self.look_from);
+        let look_at = point(self.look_at);
+        let view_up = vector(self.view_up);
+        let view_direction = look_at - look_from;
+
+        if view_direction.length_squared() == 0.0 {
+            return Err("look_from and look_at must be different points".to_string());
+        }
+        if view_up.length_squared() == 0.0 {
+            return Err("view_up must not be a zero-length vector".to_string());
+        }
+        if view_up.cross(&view_direction).length_squared() == 0.0 {
+            return Err("view_up must not be parallel to the viewing direction".to_string());
+        }
+        if !(self.vertical_fov > 0.0 && self.vertical_fov < 180.0) {
+            return Err(format!(
+                "vertical_fov must be between 0 and 180 degrees, got {}",
+                self.vertical_fov
+            ));
+        }
+        if self.aperture < 0.0 {
+            return Err(format!(
+                "aperture must not be negative, got {}",
+                self.aperture
+            ));
+        }
+        let focus_dist = self.focus_dist.unwrap_or_else(|| view_direction.norm());
+        if focus_dist <= 0.0 {
+            return Err(format!("focus_dist must be positive, got {}", focus_dist));
+        }
+
+        Ok(CameraConfig {
+            look_from,
+            look_at,
+            view_up,
+            vertical_fov: self.vertical_fov,
+            aperture: self.aperture,
+            focus_dist,
+        })
+    }
+}
+
+impl TextureDescription {
+    /// Builds the texture, reading images relative to `base_dir`. `name`
+    /// is used in error messages. 8-bit images are decoded from sRGB by
+    /// default only if `is_color`, since other textures hold data.
+    fn build(&self, base_dir: &Path, name: &str, is_color: bool) -> Result<Texture, String> {
+        let kind = match self {
+            TextureDescription::Gray(value) => {
+                return Ok(Texture::Constant(color([*value; 3], name)?))
+            }
+            TextureDescription::Color(c) => return Ok(Texture::Constant(color(*c, name)?)),
+            TextureDescription::Texture(kind) => kind,
+        };
+        let noise = |pattern: NoisePattern, description: &NoiseDescription| {
+            let NoiseDescription {
+                scale,
+                octaves,
+                seed,
+                low,
+                high,
+            } = description;
+            if *scale <= 0.0 {
+                return Err(format!("noise scale must be positive, got {}", scale));
+            }
+            if *octaves == 0 {
+                return Err("noise needs at least one octave".to_string());
+            }
+            let texture = NoiseTexture::new(
+                pattern,
+                *seed,
+                *scale,
+                low.build(base_dir, "low", is_color)?,
+                high.build(base_dir, "high", is_color)?,
+            );
+            Ok(Texture::Noise(Arc::new(texture.with_octaves(*octaves))))
+        };
+        match kind {
+            TextureKindDescription::Checker { even, odd, size } => {
+                if *size <= 0.0 {
+                    return Err(format!("checker size must be positive, got {}", size));
+                }
+                Ok(Texture::Checker {
+                    even: Arc::new(even.build(base_dir, "even", is_color)?),
+                    odd: Arc::new(odd.build(base_dir, "odd", is_color)?),
+                    size: *size,
+                })
+            }
+            TextureKindDescription::Image {
+                path,
+                wrap,
+                filter,
+                mipmaps,
+                srgb,
+            } => {
+                let path = base_dir.join(path);
+                let mut image = read_image(&path).map_err(|error| error.to_string())?;
+                if image.width() == 0 || image.height() == 0 {
+                    return Err(format!("{} has no pixels", path.display()));
+                }
+                // 8-bit color images are sRGB-encoded, while HDR formats
+                // and data hold linear values
+                let is_hdr = ImageFormat::from_path(&path)
+                    .is_some_and(|format| format.is_high_dynamic_range());
+                if srgb.unwrap_or(is_color && !is_hdr) {
+                    image = image.map(|color| color.decode_srgb());
+                }
+                let wrap = match wrap {
+                    WrapDescription::Repeat => WrapMode::Repeat,
+                    WrapDescription::Clamp => WrapMode::Clamp,
+                    WrapDescription::Mirror => WrapMode::Mirror,
+                };
+                let filter = match filter {
+                    FilterDescription::Nearest => Filter::Nearest,
+                    FilterDescription::Bilinear => Filter::Bilinear,
+                };
+                let mut texture = ImageTexture::new(image, wrap, filter);
+                if *mipmaps {
+                    texture = texture.with_mipmaps();
+                }
+                Ok(Texture::Image(Arc::new(texture)))
+            }
+            TextureKindDescription::Gradient { start, end, axis } => Ok(Texture::Gradient {
+                start: color(*start, "start")?,
+                end: color(*end, "end")?,
+                axis: match axis {
+                    TextureAxisDescription::U => TextureAxis::U,
+                    TextureAxisDescription::V => TextureAxis::V,
+                },
+            }),
+            TextureKindDescription::Perlin(description) => noise(NoisePattern::Perlin, description),
+            TextureKindDescription::Turbulence(description) => {
+                noise(NoisePattern::Turbulence, description)
+            }
+            TextureKindDescription::Marble(description) => noise(NoisePattern::Marble, description),
+            TextureKindDescription::Wood(description) => noise(NoisePattern::Wood, description),
+            TextureKindDescription::Worley(description) => noise(NoisePattern::Worley, description),
+        }
+    }
+}
+
+impl MaterialDescription {
+    /// Builds the material, reading any images relative to `base_dir`
+    fn build(&self, base_dir: &Path) -> Result<Material, String> {
+        match self {
+            MaterialDescription::Diffuse { albedo } => Ok(Material::DiffuseNonMetal(
+                albedo.build(base_dir, "albedo", true)?,
+            )),
+            MaterialDescription::Metal { albedo, fuzz } => {
+                // textured fuzz is clamped instead, since noise can't be
+                // checked ahead of time
+                let constant = match fuzz {
+                    TextureDescription::Gray(value) => vec![*value],
+                    TextureDescription::Color(c) => c.to_vec(),
+                    TextureDescription::Texture(_) => vec![],
+                };
+                if let Some(value) = constant.iter().find(|v| !(0.0..=1.0).contains(*v)) {
+                    return Err(format!("fuzz must be between 0 and 1, got {}", value));
+                }
+                Ok(Material::Metal(
+                    albedo.build(base_dir, "albedo", true)?,
+                    fuzz.build(base_dir, "fuzz", false)?,
+                ))
+            }
+            MaterialDescription::Dielectric {
+                index_of_refraction,
+                attenuation,
+                roughness,
+            } => {
+                if *index_of_refraction <= 0.0 {
+                    return Err(format!(
+                        "index_of_refraction must be positive, got {}",
+                        index_of_refraction
+                    ));
+                }
+                if attenuation.iter().any(|&x| x > 1.0) {
+                    return Err(format!("attenuation {:?} must not be above 1", attenuation));
+                }
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err(format!(
+                        "roughness must be between 0 and 1, got {}",
+                        roughness
+                    ));
+                }
+                let attenuation = color(*attenuation, "attenuation")?;
+                if *roughness > 0.0 {
+                    Ok(Material::RoughDielectric(
+                        RoughDielectric::new(*index_of_refraction, *roughness),
+                        attenuation,
+                    ))
+                } else {
+                    Ok(Material::Dielectric(*index_of_refraction, attenuation))
+                }
+            }
+            MaterialDescription::DiffuseLight {
+                color: c,
+                intensity,
+            } => {
+                if *intensity < 0.0 {
+                    return Err(format!("intensity must not be negative, got {}", intensity));
+                }
+                Ok(Material::DiffuseLight(color(*c, "color")?, *intensity))
+            }
+            MaterialDescription::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+                anisotropy,
+            } => {
+                if !(0.0..=1.0).contains(roughness) {
+                    return Err(format!(
+                        "roughness must be between 0 and 1, got {}",
+                        roughness
+                    ));
+                }
+                if !(0.0..1.0).contains(anisotropy) {
+                    return Err(format!(
+                        "anisotropy must be at least 0 and below 1, got {}",
+                        anisotropy
+                    ));
+                }
+                let (eta, k) = match (metal, eta, k) {
+                    (Some(metal), None, None) => {
+                        let preset = match metal {
+                            MetalDescription::Gold => MetalPreset::Gold,
+                            MetalDescription::Copper => MetalPreset::Copper,
+                            MetalDescription::Aluminium => MetalPreset::Aluminium,
+                            MetalDescription::Silver => MetalPreset::Silver,
+                        };
+                        preset.index_of_refraction()
+                    }
+                    (None, Some(eta), Some(k)) => {
+                        if eta.iter().any(|&x| x <= 0.0) {
+                            return Err(format!("eta must be positive, got {:?}", eta));
+                        }
+                        (color(*eta, "eta")?, color(*k, "k")?)
+                    }
+                    _ => return Err("give either a metal or both eta and k".to_string()),
+                };
+                Ok(Material::Conductor(Conductor::new(
+                    eta,
+                    k,
+                    *roughness,
+                    *anisotropy,
+                )))
+            }
+            MaterialDescription::NormalMapped {
+                material,
+                normal_map,
+            } => Ok(Material::Bumped(
+                Box::new(material.build(base_dir)?),
+                Bump::NormalMap(normal_map.build(base_dir, "normal_map", false)?),
+            )),
+            MaterialDescription::BumpMapped {
+                material,
+                height,
+                scale,
+            } => Ok(Material::Bumped(
+                Box::new(material.build(base_dir)?),
+                Bump::Height {
+                    map: height.build(base_dir, "height", false)?,
+                    scale: *scale,
+                },
+            )),
+            MaterialDescription::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                specular_tint,
+                sheen,
+                sheen_tint,
+                clearcoat,
+                clearcoat_gloss,
+                transmission,
+                index_of_refraction,
+            } => {
+                for (name, value) in [
+                    ("metallic", metallic),
+                    ("roughness", roughness),
+                    ("specular", specular),
+                    ("specular_tint", specular_tint),
+                    ("sheen", sheen),
+                    ("sheen_tint", sheen_tint),
+                    ("clearcoat", clearcoat),
+                    ("clearcoat_gloss", clearcoat_gloss),
+                    ("transmission", transmission),
+                ] {
+                    if !(0.0..=1.0).contains(value) {
+                        return Err(format!("{} must be between 0 and 1, got {}", name, value));
+                    }
+                }
+                if *index_of_refraction <= 0.0 {
+                    return Err(format!(
+                        "index_of_refraction must be positive, got {}",
+                        index_of_refraction
+                    ));
+                }
+                Ok(Material::Principled(Box::new(Principled::new(
+                    PrincipledParameters {
+                        base_color: base_color.build(base_dir, "base_color", true)?,
+                        metallic: *metallic,
+                        roughness: *roughness,
+                        specular: *specular,
+                        specular_tint: *specular_tint,
+                        sheen: *sheen,
+                        sheen_tint: *sheen_tint,
+                        clearcoat: *clearcoat,
+                        clearcoat_gloss: *clearcoat_gloss,
+                        transmission: *transmission,
+                        index_of_refraction: *index_of_refraction,
+                    },
+                ))))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_scene, SceneError};
+    use crate::bump::Bump;
+    use crate::color::Color;
+    use crate::environment::{Environment, SKY_TOP};
+    use crate::geom::{Point3, Vector3};
+    use crate::image::{save_image, Image};
+    use crate::integrator::{IntegratorKind, MisHeuristic};
+    use crate::material::{Conductor, Material, MetalPreset, RoughDielectric};
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, Shape, INFINITY};
+    use crate::texture::{Texture, TextureAxis};
+    use crate::tonemap::{ToneMapOperator, ToneMapping};
+    use std::fs;
+    use std::path::Path;
+
+    const SCENE: &str = r#"{
+        "camera": {
+            "look_from": [0, 0, 5],
+            "look_at": [0, 0, 0],
+            "view_up": [0, 1, 0],
+            "vertical_fov": 40
+        },
+        "render": { "resolution": "240p", "samples_per_pixel": 4, "max_depth": 8 },
+        "materials": {
+            "red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] },
+            "glass": { "type": "dielectric", "index_of_refraction": 1.5 }
+        },
+        "shapes": [
+            { "type": "sphere", "center": [0, 0, 0], "radius": 1, "material": "red" },
+            { "type": "triangle", "vertices": [[0, 0, -3], [1, 0, -3], [0, 1, -3]], "material": "glass" }
+        ]
+    }"#;
+
+    fn assert_invalid(source: &str, expected_context: &str, expected_message: &str) {
+        match parse_scene(source, Path::new("test.json")) {
+            Err(SceneError::Invalid {
+                context, message, ..
+            }) => {
+                assert_eq!(context, expected_context);
+                assert!(
+                    message.contains(expected_message),
+                    "'{}' does not mention '{}'",
+                    message,
+                    expected_message
+                );
+            }
+            Err(error) => panic!("expected a validation error, got {}", error),
+            Ok(_) => panic!("expected a validation error"),
+        }
+    }
+
+    #[test]
+    fn can_load_scene() {
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert_eq!(scene.objects.objects.len(), 2);
+        assert_eq!(scene.render_config.height, 240);
+        assert_eq!(scene.render_config.samples_per_pixel, 4);
+
+        let r = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+        let hit = scene.objects.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_eq!(hit.t, 4.);
+    }
+
+    #[test]
+    fn reports_validation_errors() {
+        assert_invalid(
+            &SCENE.replace("\"material\": \"glass\"", "\"material\": \"steel\""),
+            "shapes[1]",
+            "unknown material 'steel'",
+        );
+        assert_invalid(
+            &SCENE.replace("\"radius\": 1", "\"radius\": -1"),
+            "shapes[0]",
+            "radius must be positive",
+        );
+        assert_invalid(
+            &SCENE.replace("\"view_up\": [0, 1, 0]", "\"view_up\": [0, 0, 0]"),
+            "camera",
+            "zero-length",
+        );
+        assert_invalid(
+            &SCENE.replace("\"view_up\": [0, 1, 0]", "\"view_up\": [0, 0, 2]"),
+            "camera",
+            "parallel",
+        );
+        assert_invalid(
+            &SCENE.replace("240p", "999p"),
+            "render",
+            "unknown resolution",
+        );
+        assert_invalid(
+            &SCENE.replace("[0.8, 0.1, 0.1]", "[0.8, -0.1, 0.1]"),
+            "materials.red",
+            "must not be negative",
+        );
+    }
+
+    #[test]
+    fn can_choose_tone_mapping() {
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.tone_mapping, ToneMapping::default());
+
+        let with_tone_mapping = |tone_mapping: &str| {
+            SCENE.replace(
+                "\"max_depth\": 8",
+                &format!("\"max_depth\": 8, \"tone_mapping\": {}", tone_mapping),
+            )
+        };
+        let source =
+            with_tone_mapping(r#"{ "operator": "reinhard_extended", "white": 3, "exposure": -1 }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(
+            scene.render_config.tone_mapping,
+            ToneMapping {
+                operator: ToneMapOperator::ReinhardExtended { white: Some(3.0) },
+                exposure: -1.0
+            }
+        );
+        assert_invalid(
+            &with_tone_mapping(r#"{ "operator": "aces", "white": 3 }"#),
+            "render",
+            "white is only used",
+        );
+    }
+
+    #[test]
+    fn can_load_lights_and_quads() {
+        let source = SCENE
+            .replace(
+                "\"materials\": {",
+                r#""materials": { "lamp": { "type": "diffuse_light", "color": [1, 1, 1], "intensity": 5 },"#,
+            )
+            .replace(
+                "\"shapes\": [",
+                r#""background": [0, 0, 0], "shapes": [
+                    { "type": "quad", "corner": [-1, 2, -1], "u": [2, 0, 0], "v": [0, 0, 2], "material": "lamp" },"#,
+            );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(
+            scene.objects.environment,
+            Environment::Constant(Color::BLACK)
+        );
+        match &scene.objects.objects[0] {
+            Shape::Quad(quad) => {
+                assert_eq!(quad.normal(), Vector3::new(0., -1., 0.));
+                assert!(matches!(
+                    quad.get_material(),
+                    Material::DiffuseLight(_, intensity) if *intensity == 5.
+                ));
+            }
+            shape => panic!("expected a quad, got {:?}", shape),
+        }
+        assert_invalid(
+            &source.replace("\"v\": [0, 0, 2]", "\"v\": [1, 0, 0]"),
+            "shapes[0]",
+            "quad has no area",
+        );
+        assert_invalid(
+            &source.replace("\"intensity\": 5", "\"intensity\": -5"),
+            "materials.lamp",
+            "intensity must not be negative",
+        );
+    }
+
+    #[test]
+    fn can_choose_mis_heuristic() {
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.mis_heuristic, MisHeuristic::Power);
+        let source = SCENE.replace(
+            "\"max_depth\": 8",
+            "\"max_depth\": 8, \"mis_heuristic\": \"balance\"",
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.mis_heuristic, MisHeuristic::Balance);
+    }
+
+    #[test]
+    fn can_choose_russian_roulette_depth() {
+        let with_depth = |depth: i32| {
+            SCENE.replace(
+                "\"max_depth\": 8",
+                &format!("\"max_depth\": 8, \"russian_roulette_depth\": {}", depth),
+            )
+        };
+        let scene = parse_scene(&with_depth(2), Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.russian_roulette_depth, 2);
+        assert_invalid(&with_depth(-1), "render", "must not be negative");
+    }
+
+    #[test]
+    fn can_choose_environment() {
+        let with_environment = |environment: &str| {
+            SCENE.replace(
+                "\"shapes\": [",
+                &format!("\"environment\": {}, \"shapes\": [", environment),
+            )
+        };
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert_eq!(scene.objects.environment, Environment::default());
+        let source = with_environment(r#"{ "type": "gradient", "bottom": [0, 0, 0] }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(
+            scene.objects.environment,
+            Environment::Gradient {
+                bottom: Color::BLACK,
+                top: SKY_TOP
+            }
+        );
+        let source = with_environment(r#"{ "type": "sky", "sun_direction": [0, 1, 1] }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        match &scene.objects.environment {
+            Environment::Sky(sky) => assert_eq!(sky.turbidity(), 3.),
+            environment => panic!("expected a sky, got {:?}", environment),
+        }
+        assert_invalid(
+            &source.replace("[0, 1, 1]", "[0, -1, 1]"),
+            "environment",
+            "above the horizon",
+        );
+        assert_invalid(
+            &source.replace("[0, 1, 1]", "[0, 1, 1], \"turbidity\": 20"),
+            "environment",
+            "turbidity must be between",
+        );
+        let source = with_environment(r#"{ "type": "black" }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(scene.objects.environment, Environment::Black);
+        assert_invalid(
+            &with_environment(r#"{ "type": "constant", "color": [1, -1, 1] }"#),
+            "environment",
+            "must not be negative",
+        );
+        assert_invalid(
+            &source.replace("\"shapes\": [", "\"background\": [0, 0, 0], \"shapes\": ["),
+            "background",
+            "not both",
+        );
+
+        // maps are found next to the scene file
+        let dir = std::env::temp_dir().join(format!("scene_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let sky = Image::from_pixels(2, 1, vec![Color::new(2., 3., 4.); 2]);
+        save_image(&sky, &dir.join("sky.pfm")).unwrap();
+        let source = with_environment(
+            r#"{ "type": "image", "path": "sky.pfm", "rotation": 45, "intensity": 0.5 }"#,
+        );
+        let scene = parse_scene(&source, &dir.join("scene.json")).unwrap();
+        let r = Ray::new(Point3::origin(), Vector3::new(0., 1., 0.));
+        assert_eq!(scene.objects.environment.color(&r), Color::new(1., 1.5, 2.));
+        assert!(matches!(
+            parse_scene(&source, Path::new("test.json")),
+            Err(SceneError::Image(_))
+        ));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn can_load_textures() {
+        let dir = std::env::temp_dir().join(format!("texture_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let pixels = Image::from_pixels(1, 1, vec![Color::new(0.25, 0.5, 1.)]);
+        save_image(&pixels, &dir.join("pixel.pfm")).unwrap();
+        let source = SCENE.replace(
+            r#""albedo": [0.8, 0.1, 0.1]"#,
+            r#""albedo": { "type": "checker", "even": [1, 1, 1],
+                "odd": { "type": "image", "path": "pixel.pfm" }, "size": 2 }"#,
+        );
+        let scene = parse_scene(&source, &dir.join("scene.json")).unwrap();
+        let texture = match scene.objects.objects[0].get_material() {
+            Material::DiffuseNonMetal(texture) => texture,
+            material => panic!("expected a diffuse material, got {:?}", material),
+        };
+        assert_eq!(
+            texture.value((0., 0.), &Point3::new(1., 1., 1.)),
+            Color::WHITE
+        );
+        assert_eq!(
+            texture.value((0., 0.), &Point3::new(3., 1., 1.)),
+            Color::new(0.25, 0.5, 1.)
+        );
+
+        // 8-bit files are decoded from sRGB unless told otherwise
+        let gray = Color::new(0.2, 0.2, 0.2).encode_srgb();
+        save_image(&Image::from_pixels(1, 1, vec![gray]), &dir.join("gray.ppm")).unwrap();
+        for &(options, expected) in &[("", 0.2), (r#", "srgb": false"#, 0.484)] {
+            let source = SCENE.replace(
+                r#""albedo": [0.8, 0.1, 0.1]"#,
+                &format!(
+                    r#""albedo": {{ "type": "image", "path": "gray.ppm", "wrap": "mirror",
+                        "filter": "nearest", "mipmaps": true{} }}"#,
+                    options
+                ),
+            );
+            let scene = parse_scene(&source, &dir.join("scene.json")).unwrap();
+            let texture = match scene.objects.objects[0].get_material() {
+                Material::DiffuseNonMetal(texture) => texture,
+                material => panic!("expected a diffuse material, got {:?}", material),
+            };
+            let value = texture.value((0.5, 0.5), &Point3::origin());
+            assert!(
+                (value.red - expected).abs() < 3e-3,
+                "{} with '{}'",
+                value.red,
+                options
+            );
+        }
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_invalid(
+            &source.replace("\"size\": 2", "\"size\": 0"),
+            "materials.red",
+            "size must be positive",
+        );
+        assert_invalid(&source, "materials.red", "pixel.pfm");
+        let source = SCENE.replace(
+            r#""albedo": [0.8, 0.1, 0.1]"#,
+            r#""albedo": { "type": "gradient", "start": [0, 0, 0], "end": [2, 2, 2] }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert!(matches!(
+            scene.objects.objects[0].get_material(),
+            Material::DiffuseNonMetal(Texture::Gradient {
+                axis: TextureAxis::V,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn can_load_noise_textures() {
+        let source = SCENE.replace(
+            r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+            r#""red": { "type": "diffuse", "albedo": { "type": "marble", "scale": 4, "seed": 9,
+                "low": [0.9, 0.9, 0.8], "high": 0.1 } },
+            "rough": { "type": "metal", "albedo": [0.8, 0.8, 0.8],
+                "fuzz": { "type": "worley", "low": 0.1, "high": 0.5 } }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let texture = match scene.objects.objects[0].get_material() {
+            Material::DiffuseNonMetal(texture @ Texture::Noise(_)) => texture.clone(),
+            material => panic!("expected a noise texture, got {:?}", material),
+        };
+        // the same seed gives the same marble
+        let again = parse_scene(&source, Path::new("test.json")).unwrap();
+        let p = Point3::new(0.3, 0.2, 0.1);
+        match again.objects.objects[0].get_material() {
+            Material::DiffuseNonMetal(same) => {
+                assert_eq!(same.value((0., 0.), &p), texture.value((0., 0.), &p))
+            }
+            material => panic!("expected a diffuse material, got {:?}", material),
+        }
+
+        let source = source.replace(r#""material": "red""#, r#""material": "rough""#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let material = scene.objects.objects[0].get_material();
+        assert!(matches!(material, Material::Metal(_, Texture::Noise(_))));
+        assert!(!material.is_specular());
+
+        assert_invalid(
+            &source.replace(r#""scale": 4"#, r#""scale": -4"#),
+            "materials.red",
+            "scale must be positive",
+        );
+        assert_invalid(
+            &source.replace(r#""low": 0.1"#, r#""low": 0.1, "octaves": 0"#),
+            "materials.rough",
+            "at least one octave",
+        );
+        assert_invalid(
+            &SCENE.replace(
+                r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+                r#""red": { "type": "metal", "albedo": [0.8, 0.1, 0.1], "fuzz": 1.5 }"#,
+            ),
+            "materials.red",
+            "fuzz must be between 0 and 1",
+        );
+        assert!(parse_scene(
+            &source.replace(r#""seed": 9"#, r#""seed": 9, "sharpness": 2"#),
+            Path::new("test.json")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn can_load_normal_and_bump_maps() {
+        let source = SCENE.replace(
+            r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+            r#""red": { "type": "bump_mapped", "height": { "type": "perlin", "scale": 4 }, "scale": 0.05,
+                "material": { "type": "normal_mapped", "normal_map": [0.5, 0.5, 1],
+                    "material": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] } } }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        match scene.objects.objects[0].get_material() {
+            Material::Bumped(inner, Bump::Height { scale, .. }) => {
+                assert_eq!(*scale, 0.05);
+                assert!(matches!(
+                    inner.as_ref(),
+                    Material::Bumped(diffuse, Bump::NormalMap(_))
+                        if matches!(diffuse.as_ref(), Material::DiffuseNonMetal(_))
+                ));
+            }
+            material => panic!("expected a bump mapped material, got {:?}", material),
+        }
+        assert_invalid(
+            &source.replace(
+                r#""albedo": [0.8, 0.1, 0.1]"#,
+                r#""albedo": [0.8, 0.1, -0.1]"#,
+            ),
+            "materials.red",
+            "albedo",
+        );
+        assert!(parse_scene(
+            &source.replace(r#", "scale": 0.05"#, ""),
+            Path::new("test.json")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn image_normal_maps_are_linear() {
+        let dir = std::env::temp_dir().join(format!("normal_map_test_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let flat = Image::from_pixels(1, 1, vec![Color::new(0.5, 0.5, 1.)]);
+        save_image(&flat, &dir.join("flat.png")).unwrap();
+        let source = SCENE.replace(
+            r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+            r#""red": { "type": "normal_mapped", "normal_map": { "type": "image", "path": "flat.png" },
+                "material": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] } }"#,
+        );
+        let scene = parse_scene(&source, &dir.join("scene.json")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        let texture = match scene.objects.objects[0].get_material() {
+            Material::Bumped(_, Bump::NormalMap(texture)) => texture,
+            material => panic!("expected a normal mapped material, got {:?}", material),
+        };
+        let value = texture.value((0.5, 0.5), &Point3::origin());
+        assert!((value.red - 0.5).abs() < 3e-3, "{:?}", value);
+        assert!((value.green - 0.5).abs() < 3e-3, "{:?}", value);
+        assert_eq!(value.blue, 1.);
+    }
+
+    #[test]
+    fn can_load_conductors() {
+        let with_red = |material: &str| {
+            SCENE.replace(
+                r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+                &format!(r#""red": {}"#, material),
+            )
+        };
+        let source = with_red(r#"{ "type": "conductor", "metal": "aluminum", "roughness": 0.2 }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let expected = Conductor::from_preset(MetalPreset::Aluminium, 0.2, 0.);
+        assert!(matches!(
+            scene.objects.objects[0].get_material(),
+            Material::Conductor(conductor) if *conductor == expected
+        ));
+
+        let source = with_red(
+            r#"{ "type": "conductor", "eta": [1, 1, 1], "k": [2, 2, 2], "anisotropy": 0.5 }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let material = scene.objects.objects[0].get_material();
+        assert!(matches!(material, Material::Conductor(_)));
+        // smooth unless told otherwise
+        assert!(material.is_specular());
+
+        for (material, message) in [
+            (
+                r#"{ "type": "conductor" }"#,
+                "either a metal or both eta and k",
+            ),
+            (
+                r#"{ "type": "conductor", "metal": "gold", "k": [1, 1, 1] }"#,
+                "either a metal",
+            ),
+            (
+                r#"{ "type": "conductor", "metal": "gold", "roughness": 2 }"#,
+                "roughness must be between 0 and 1",
+            ),
+            (
+                r#"{ "type": "conductor", "metal": "gold", "anisotropy": 1 }"#,
+                "anisotropy must be",
+            ),
+            (
+                r#"{ "type": "conductor", "eta": [0, 1, 1], "k": [1, 1, 1] }"#,
+                "eta must be positive",
+            ),
+        ] {
+            assert_invalid(&with_red(material), "materials.red", message);
+        }
+        assert!(parse_scene(
+            &with_red(r#"{ "type": "conductor", "metal": "tin" }"#),
+            Path::new("test.json")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn can_load_frosted_glass() {
+        let source = SCENE.replace(
+            r#""glass": { "type": "dielectric", "index_of_refraction": 1.5 }"#,
+            r#""glass": { "type": "dielectric", "index_of_refraction": 1.5, "roughness": 0.3,
+                "attenuation": [0.9, 0.5, 0.5] }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let expected = RoughDielectric::new(1.5, 0.3);
+        assert!(matches!(
+            scene.objects.objects[1].get_material(),
+            Material::RoughDielectric(glass, attenuation)
+                if *glass == expected && attenuation.red == 0.9
+        ));
+        // smooth glass stays smooth
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert!(matches!(
+            scene.objects.objects[1].get_material(),
+            Material::Dielectric(_, _)
+        ));
+
+        for (glass, message) in [
+            (
+                r#"{ "type": "dielectric", "index_of_refraction": 1.5, "roughness": -0.1 }"#,
+                "roughness must be between 0 and 1",
+            ),
+            (
+                r#"{ "type": "dielectric", "index_of_refraction": 1.5, "attenuation": [1, 2, 1] }"#,
+                "must not be above 1",
+            ),
+        ] {
+            let source = SCENE.replace(
+                r#"{ "type": "dielectric", "index_of_refraction": 1.5 }"#,
+                glass,
+            );
+            assert_invalid(&source, "materials.glass", message);
+        }
+    }
+
+    #[test]
+    fn can_load_principled_materials() {
+        let with_red = |material: &str| {
+            SCENE.replace(
+                r#""red": { "type": "diffuse", "albedo": [0.8, 0.1, 0.1] }"#,
+                &format!(r#""red": {}"#, material),
+            )
+        };
+        let source = with_red(
+            r#"{ "type": "principled", "base_color": { "type": "checker", "even": [1, 1, 1], "odd": [0.2, 0.2, 0.2] },
+                "metallic": 1, "roughness": 0.2, "clearcoat": 0.5 }"#,
+        );
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        let material = scene.objects.objects[0].get_material();
+        assert!(matches!(material, Material::Principled(_)));
+        assert!(!material.is_specular());
+        // everything has a default
+        let scene = parse_scene(
+            &with_red(r#"{ "type": "principled" }"#),
+            Path::new("test.json"),
+        )
+        .unwrap();
+        assert!(matches!(
+            scene.objects.objects[0].get_material(),
+            Material::Principled(_)
+        ));
+
+        for (material, message) in [
+            (
+                r#"{ "type": "principled", "sheen": 1.5 }"#,
+                "sheen must be between 0 and 1",
+            ),
+            (
+                r#"{ "type": "principled", "transmission": -1 }"#,
+                "transmission must be between 0 and 1",
+            ),
+            (
+                r#"{ "type": "principled", "index_of_refraction": 0 }"#,
+                "index_of_refraction must be positive",
+            ),
+        ] {
+            assert_invalid(&with_red(material), "materials.red", message);
+        }
+        assert!(parse_scene(
+            &with_red(r#"{ "type": "principled", "glossiness": 1 }"#),
+            Path::new("test.json")
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn can_choose_integrator() {
+        let with_integrator = |integrator: &str| {
+            SCENE.replace(
+                "\"max_depth\": 8",
+                &format!("\"max_depth\": 8, \"integrator\": {}", integrator),
+            )
+        };
+        let scene = parse_scene(SCENE, Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.integrator, IntegratorKind::Path);
+        let source = with_integrator(r#"{ "type": "ambient_occlusion", "distance": 2 }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(
+            scene.render_config.integrator,
+            IntegratorKind::AmbientOcclusion {
+                distance: Some(2.0)
+            }
+        );
+        let source = with_integrator(r#"{ "type": "normals" }"#);
+        let scene = parse_scene(&source, Path::new("test.json")).unwrap();
+        assert_eq!(scene.render_config.integrator, IntegratorKind::Normals);
+        assert_invalid(
+            &with_integrator(r#"{ "type": "ambient_occlusion", "distance": 0 }"#),
+            "render",
+            "distance must be positive",
+        );
+    }
+
+    #[test]
+    fn reports_syntax_errors_with_position() {
+        let source = SCENE.replace(
+            "\"samples_per_pixel\": 4",
+            "\"samples_per_pixel\": \"many\"",
+        );
+        match parse_scene(&source, Path::new("test.json")) {
+            Err(error @ SceneError::Syntax { .. }) => {
+                assert!(error.to_string().contains("line 8"), "{}", error)
+            }
+            _ => panic!("expected a syntax error"),
+        }
+
+        let source = SCENE.replace("\"radius\": 1", "\"radius\": 1, \"radios\": 2");
+        assert!(matches!(
+            parse_scene(&source, Path::new("test.json")),
+            Err(SceneError::Syntax { .. })
+        ));
+    }
+
+    #[test]
+    fn can_load_example_scene() {
+        let source = include_str!("../scenes/spheres.json");
+        assert!(parse_scene(source, Path::new("scenes/spheres.json")).is_ok());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::geom::Point3;
+use crate::ray::Ray;
+use crate::shapes::{Interval, EMPTY_INTERVAL};
+
+/// Axis-aligned bounding box, stored as one `Interval` per axis
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+pub const EMPTY_AABB: Aabb = Aabb {
+    x: EMPTY_INTERVAL,
+    y: EMPTY_INTERVAL,
+    z: EMPTY_INTERVAL,
+};
+
+impl Aabb {
+    pub fn new(x: Interval, y: Interval, z: Interval) -> Self {
+        Aabb { x, y, z }
+    }
+
+    /// Creates the smallest box containing both points, in any order
+    pub fn from_points(a: Point3, b: Point3) -> Self {
+        Aabb {
+            x: Interval::new(a.x.min(b.x), a.x.max(b.x)),
+            y: Interval::new(a.y.min(b.y), a.y.max(b.y)),
+            z: Interval::new(a.z.min(b.z), a.z.max(b.z)),
+        }
+    }
+
+    /// Creates the smallest box containing both boxes
+    pub fn surrounding(&self, other: &Aabb) -> Self {
+        Aabb {
+            x: self.x.enclose(&other.x),
+            y: self.y.enclose(&other.y),
+            z: self.z.enclose(&other.z),
+        }
+    }
+
+    /// Returns the interval of the box along axis `n` (0 = x, 1 = y, 2 = z)
+    pub fn axis(&self, n: usize) -> Interval {
+        match n {
+            0 => self.x,
+            1 => self.y,
+            _ => self.z,
+        }
+    }
+
+    /// Returns the index of the axis along which the box is largest
+    pub fn longest_axis(&self) -> usize {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x > y && x > z {
+            0
+        } else if y > z {
+            1
+        } else {
+            2
+        }
+    }
+
+    /// Total area of the six faces, or zero for an empty box
+    pub fn surface_area(&self) -> f64 {
+        let (x, y, z) = (self.x.size(), self.y.size(), self.z.size());
+        if x < 0.0 || y < 0.0 || z < 0.0 {
+            return 0.0;
+        }
+        2.0 * (x * y + y * z + z * x)
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(self.x.midpoint(), self.y.midpoint(), self.z.midpoint())
+    }
+
+    /// Returns true if the ray passes through the box somewhere in `interval`.
+    /// Uses the slab method: the ray is clipped against each pair of axis
+    /// planes in turn, and misses as soon as the clipped interval is empty.
+    pub fn hit(&self, r: &Ray, interval: Interval) -> bool {
+        let origin = [r.origin.x, r.origin.y, r.origin.z];
+        let direction = [r.direction.x, r.direction.y, r.direction.z];
+        let mut t_min = interval.min;
+        let mut t_max = interval.max;
+
+        for axis in 0..3 {
+            let slab = self.axis(axis);
+            let inverse_direction = 1.0 / direction[axis];
+            let mut t0 = (slab.min - origin[axis]) * inverse_direction;
+            let mut t1 = (slab.max - origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            // Widen the far side by a few ulps so rounding can't make us miss
+            // a shape whose surface lies exactly on the box boundary. Scaling
+            // by the magnitude keeps this a widening when t1 is negative, and
+            // infinities, from rays parallel to the slab, are left alone.
+            if t1.is_finite() {
+                t1 += t1.abs() * 4.0 * f64::EPSILON;
+            }
+
+            t_min = t_min.max(t0);
+            t_max = t_max.min(t1);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Aabb;
+    use crate::geom::{Point3, Vector3};
+    use crate::ray::Ray;
+    use crate::shapes::{Interval, INFINITY};
+
+    #[test]
+    fn can_hit_box() {
+        let bbox = Aabb::from_points(Point3::new(-1., -1., -1.), Point3::new(1., 1., 1.));
+        let interval = Interval::new(0., INFINITY);
+
+        let r = Ray::new(Point3::new(0., 0., -5.), Vector3::new(0., 0., 1.));
+        assert!(bbox.hit(&r, interval));
+
+        let r = Ray::new(Point3::new(0., 2., -5.), Vector3::new(0., 0., 1.));
+        assert!(!bbox.hit(&r, interval));
+
+        // box is behind the ray
+        let r = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., 1.));
+        assert!(!bbox.hit(&r, interval));
+        // unless the interval reaches back to its far side
+        assert!(bbox.hit(&r, Interval::new(-4., INFINITY)));
+    }
+
+    #[test]
+    fn can_hit_box_from_inside() {
+        let bbox = Aabb::from_points(Point3::new(-1., -1., -1.), Point3::new(1., 1., 1.));
+        let interval = Interval::new(0., INFINITY);
+        for direction in [
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., -1., 0.),
+            Vector3::new(-0.3, 0.5, -0.8),
+        ] {
+            let r = Ray::new(Point3::new(0.2, -0.5, 0.9), direction);
+            assert!(bbox.hit(&r, interval), "{:?}", direction);
+        }
+    }
+
+    #[test]
+    fn can_surround_boxes() {
+        let a = Aabb::from_points(Point3::new(0., 0., 0.), Point3::new(1., 1., 1.));
+        let b = Aabb::from_points(Point3::new(-1., 2., 0.5), Point3::new(0.5, 3., 0.75));
+        let c = a.surrounding(&b);
+        assert_eq!(
+            c,
+            Aabb::from_points(Point3::new(-1., 0., 0.), Point3::new(1., 3., 1.))
+        );
+        assert_eq!(c.longest_axis(), 1);
+    }
+}
@@ -0,0 +1,520 @@
+use crate::ray::Ray;
+use crate::shapes::aabb::{Aabb, EMPTY_AABB};
+use crate::shapes::{Intersection, Interval};
+use std::fmt;
+
+/// Relative cost of visiting an interior node, compared to testing one shape
+const TRAVERSAL_COST: f64 = 1.0;
+
+/// How a node's shapes are divided between its two children
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum SplitStrategy {
+    /// Split at the midpoint of the centroid bounds along their longest axis
+    Midpoint,
+    /// Split into two halves with the same number of shapes
+    EqualCount,
+    /// Pick the split with the lowest surface area heuristic cost, trying
+    /// `bins` evenly spaced candidate planes along each axis
+    Sah { bins: usize },
+}
+
+/// Settings for building a `Bvh`
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct BvhConfig {
+    pub strategy: SplitStrategy,
+    /// Nodes with at most this many shapes may become leaves. With
+    /// `SplitStrategy::Sah`, nodes this small are only split when that is
+    /// estimated to be cheaper than testing every shape.
+    pub max_leaf_size: usize,
+}
+
+impl Default for BvhConfig {
+    fn default() -> Self {
+        BvhConfig {
+            strategy: SplitStrategy::Sah { bins: 12 },
+            max_leaf_size: 4,
+        }
+    }
+}
+
+/// Summary of a built `Bvh`, for tuning `BvhConfig`
+#[derive(Debug, Clone, PartialEq)]
+pub struct BvhStats {
+    pub node_count: usize,
+    pub leaf_count: usize,
+    /// Number of nodes on the longest path from the root to a leaf
+    pub depth: usize,
+    pub min_leaf_size: usize,
+    pub max_leaf_size: usize,
+    /// `leaf_size_histogram[n]` is the number of leaves holding `n` shapes
+    pub leaf_size_histogram: Vec<usize>,
+}
+
+impl BvhStats {
+    fn new() -> Self {
+        BvhStats {
+            node_count: 0,
+            leaf_count: 0,
+            depth: 0,
+            min_leaf_size: 0,
+            max_leaf_size: 0,
+            leaf_size_histogram: Vec::new(),
+        }
+    }
+
+    fn record_leaf(&mut self, size: usize, depth: usize) {
+        self.min_leaf_size = if self.leaf_count == 0 {
+            size
+        } else {
+            self.min_leaf_size.min(size)
+        };
+        self.max_leaf_size = self.max_leaf_size.max(size);
+        self.leaf_count += 1;
+        self.depth = self.depth.max(depth);
+        if self.leaf_size_histogram.len() <= size {
+            self.leaf_size_histogram.resize(size + 1, 0);
+        }
+        self.leaf_size_histogram[size] += 1;
+    }
+
+    pub fn mean_leaf_size(&self) -> f64 {
+        let shapes: usize = self
+            .leaf_size_histogram
+            .iter()
+            .enumerate()
+            .map(|(size, count)| size * count)
+            .sum();
+        if self.leaf_count == 0 {
+            0.0
+        } else {
+            shapes as f64 / self.leaf_count as f64
+        }
+    }
+}
+
+impl fmt::Display for BvhStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "BVH: {} nodes, {} leaves, depth {}, leaf size min {} / mean {:.2} / max {}",
+            self.node_count,
+            self.leaf_count,
+            self.depth,
+            self.min_leaf_size,
+            self.mean_leaf_size(),
+            self.max_leaf_size
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+enum BvhNode {
+    /// Holds the shapes `indices[first..first + count]`
+    Leaf {
+        bbox: Aabb,
+        first: usize,
+        count: usize,
+    },
+    /// The left child is always stored right after its parent, so only the
+    /// right child's position needs to be kept
+    Interior {
+        bbox: Aabb,
+        right: usize,
+        axis: usize,
+    },
+}
+
+impl BvhNode {
+    fn bbox(&self) -> &Aabb {
+        match self {
+            BvhNode::Leaf { bbox, .. } => bbox,
+            BvhNode::Interior { bbox, .. } => bbox,
+        }
+    }
+}
+
+/// Bounding volume hierarchy over a list of shapes.
+///
+/// The tree is stored flattened in depth-first order, and refers to shapes
+/// by their index in the list it was built from, so it must be rebuilt
+/// whenever that list changes. It only stores the shapes' bounding boxes;
+/// the shapes themselves stay with the owner of the list.
+#[derive(Debug, Clone)]
+pub struct Bvh {
+    nodes: Vec<BvhNode>,
+    indices: Vec<usize>,
+    stats: BvhStats,
+}
+
+/// Bounding box and centroid of one shape, cached while building
+struct BuildItem {
+    index: usize,
+    bbox: Aabb,
+    centroid: [f64; 3],
+}
+
+impl Bvh {
+    /// Builds the hierarchy for shapes with the given bounding boxes
+    pub fn new(boxes: &[Aabb], config: BvhConfig) -> Self {
+        let mut items: Vec<BuildItem> = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, bbox)| {
+                let c = bbox.centroid();
+                BuildItem {
+                    index,
+                    bbox: *bbox,
+                    centroid: [c.x, c.y, c.z],
+                }
+            })
+            .collect();
+
+        let mut bvh = Bvh {
+            nodes: Vec::with_capacity(2 * boxes.len()),
+            indices: Vec::with_capacity(boxes.len()),
+            stats: BvhStats::new(),
+        };
+        if !items.is_empty() {
+            bvh.build(&mut items, &config, 1);
+        }
+        bvh.stats.node_count = bvh.nodes.len();
+        bvh
+    }
+
+    pub fn stats(&self) -> &BvhStats {
+        &self.stats
+    }
+
+    /// Recursively builds the subtree for `items`, returning the index of its root node
+    fn build(&mut self, items: &mut [BuildItem], config: &BvhConfig, depth: usize) -> usize {
+        let bbox = items
+            .iter()
+            .fold(EMPTY_AABB, |acc, item| acc.surrounding(&item.bbox));
+        let node_index = self.nodes.len();
+
+        if items.len() <= 1 {
+            self.push_leaf(bbox, items, depth);
+            return node_index;
+        }
+
+        let centroid_bounds = items.iter().fold(EMPTY_AABB, |acc, item| {
+            let c = item.bbox.centroid();
+            acc.surrounding(&Aabb::from_points(c, c))
+        });
+        let axis = centroid_bounds.longest_axis();
+
+        let split = match config.strategy {
+            SplitStrategy::Sah { bins } => {
+                split_sah(items, &bbox, &centroid_bounds, bins, config.max_leaf_size)
+            }
+            _ if items.len() <= config.max_leaf_size => None,
+            SplitStrategy::Midpoint => Some(split_midpoint(items, &centroid_bounds, axis)),
+            SplitStrategy::EqualCount => Some(split_equal_count(items, axis)),
+        };
+
+        let (axis, mid) = match split {
+            Some(split) => split,
+            None => {
+                self.push_leaf(bbox, items, depth);
+                return node_index;
+            }
+        };
+
+        // Reserve the interior node, then fill in the right child's position
+        // once the left subtree has been laid out after it
+        self.nodes.push(BvhNode::Interior {
+            bbox,
+            right: 0,
+            axis,
+        });
+        let (left_items, right_items) = items.split_at_mut(mid);
+        self.build(left_items, config, depth + 1);
+        let right_index = self.build(right_items, config, depth + 1);
+        if let BvhNode::Interior { right, .. } = &mut self.nodes[node_index] {
+            *right = right_index;
+        }
+        node_index
+    }
+
+    fn push_leaf(&mut self, bbox: Aabb, items: &[BuildItem], depth: usize) {
+        self.stats.record_leaf(items.len(), depth);
+        let first = self.indices.len();
+        self.indices.extend(items.iter().map(|item| item.index));
+        self.nodes.push(BvhNode::Leaf {
+            bbox,
+            first,
+            count: items.len(),
+        });
+    }
+
+    /// Returns the box enclosing everything in the hierarchy
+    pub fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(EMPTY_AABB, |node| *node.bbox())
+    }
+
+    /// Returns the closest intersection between the ray and the shapes.
+    /// `hit_shape(index, interval)` must intersect the ray with the shape at
+    /// `index` in the list the hierarchy was built from.
+    pub fn hit<'a, F>(
+        &self,
+        r: &Ray,
+        interval: Interval,
+        mut hit_shape: F,
+    ) -> Option<Intersection<'a>>
+    where
+        F: FnMut(usize, Interval) -> Option<Intersection<'a>>,
+    {
+        if self.nodes.is_empty() {
+            return None;
+        }
+
+        let mut closest_intersection: Option<Intersection> = None;
+        let mut closest_hit = interval.max;
+        let direction = [r.direction.x, r.direction.y, r.direction.z];
+
+        let mut stack: Vec<usize> = Vec::with_capacity(64);
+        stack.push(0);
+
+        while let Some(node_index) = stack.pop() {
+            let node = &self.nodes[node_index];
+            let reduced_interval = Interval::new(interval.min, closest_hit);
+            if !node.bbox().hit(r, reduced_interval) {
+                continue;
+            }
+
+            match node {
+                BvhNode::Leaf { first, count, .. } => {
+                    for &index in &self.indices[*first..*first + *count] {
+                        let reduced_interval = Interval::new(interval.min, closest_hit);
+                        if let Some(intersection) = hit_shape(index, reduced_interval) {
+                            if intersection.t < closest_hit {
+                                closest_hit = intersection.t;
+                                closest_intersection = Some(intersection);
+                            }
+                        }
+                    }
+                }
+                BvhNode::Interior { right, axis, .. } => {
+                    // Visit the child nearer to the ray origin first, so the
+                    // interval shrinks as early as possible
+                    let left = node_index + 1;
+                    if direction[*axis] < 0.0 {
+                        stack.push(left);
+                        stack.push(*right);
+                    } else {
+                        stack.push(*right);
+                        stack.push(left);
+                    }
+                }
+            }
+        }
+        closest_intersection
+    }
+}
+
+/// Splits at the midpoint of the centroid bounds along `axis`. Returns the
+/// split axis and the number of items that go to the left child.
+fn split_midpoint(items: &mut [BuildItem], centroid_bounds: &Aabb, axis: usize) -> (usize, usize) {
+    let midpoint = centroid_bounds.axis(axis).midpoint();
+    let mid = partition(items, |item| item.centroid[axis] < midpoint);
+    if mid == 0 || mid == items.len() {
+        // All centroids landed on one side (e.g. they coincide)
+        return split_equal_count(items, axis);
+    }
+    (axis, mid)
+}
+
+/// Splits into two halves by centroid position along `axis`
+fn split_equal_count(items: &mut [BuildItem], axis: usize) -> (usize, usize) {
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| a.centroid[axis].total_cmp(&b.centroid[axis]));
+    (axis, mid)
+}
+
+/// Finds the cheapest split according to the surface area heuristic, which
+/// estimates the cost of a ray visiting a node as the cost of testing each
+/// child's shapes weighted by the chance of hitting that child's box.
+/// Shapes are sorted into `bins` slots by centroid along each axis, and only
+/// the planes between slots are considered. Returns `None` when the node is
+/// small enough and no split beats making it a leaf.
+fn split_sah(
+    items: &mut [BuildItem],
+    bbox: &Aabb,
+    centroid_bounds: &Aabb,
+    bins: usize,
+    max_leaf_size: usize,
+) -> Option<(usize, usize)> {
+    let bins = bins.max(2);
+    let leaf_cost = items.len() as f64;
+    let parent_area = bbox.surface_area();
+
+    // (cost, axis, bin boundary)
+    let mut best: Option<(f64, usize, usize)> = None;
+
+    for axis in 0..3 {
+        let extent = centroid_bounds.axis(axis);
+        if extent.size() <= 0.0 {
+            continue;
+        }
+        let mut counts = vec![0usize; bins];
+        let mut boxes = vec![EMPTY_AABB; bins];
+        for item in items.iter() {
+            let b = bin_index(item.centroid[axis], &extent, bins);
+            counts[b] += 1;
+            boxes[b] = boxes[b].surrounding(&item.bbox);
+        }
+
+        // Sweep from the right to get the area and count of everything
+        // beyond each boundary, then from the left to evaluate each plane
+        let mut right_area = vec![0.0; bins];
+        let mut right_count = vec![0usize; bins];
+        let mut acc_box = EMPTY_AABB;
+        let mut acc_count = 0;
+        for b in (1..bins).rev() {
+            acc_box = acc_box.surrounding(&boxes[b]);
+            acc_count += counts[b];
+            right_area[b] = acc_box.surface_area();
+            right_count[b] = acc_count;
+        }
+
+        let mut acc_box = EMPTY_AABB;
+        let mut acc_count = 0;
+        for b in 1..bins {
+            acc_box = acc_box.surrounding(&boxes[b - 1]);
+            acc_count += counts[b - 1];
+            if acc_count == 0 || right_count[b] == 0 {
+                continue;
+            }
+            let cost = TRAVERSAL_COST
+                + (acc_box.surface_area() * acc_count as f64
+                    + right_area[b] * right_count[b] as f64)
+                    / parent_area;
+            if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                best = Some((cost, axis, b));
+            }
+        }
+    }
+
+    match best {
+        Some((cost, axis, boundary)) => {
+            if cost >= leaf_cost && items.len() <= max_leaf_size {
+                return None;
+            }
+            let extent = centroid_bounds.axis(axis);
+            let mid = partition(items, |item| {
+                bin_index(item.centroid[axis], &extent, bins) < boundary
+            });
+            Some((axis, mid))
+        }
+        // Every centroid coincides, so no plane separates them
+        None if items.len() <= max_leaf_size => None,
+        None => Some(split_equal_count(items, centroid_bounds.longest_axis())),
+    }
+}
+
+/// Returns which of `bins` equal slots across `extent` the coordinate falls in
+fn bin_index(x: f64, extent: &Interval, bins: usize) -> usize {
+    let b = ((x - extent.min) / extent.size() * bins as f64) as usize;
+    b.min(bins - 1)
+}
+
+/// Moves every item satisfying `predicate` to the front of the slice and
+/// returns how many there were
+fn partition<T, F: Fn(&T) -> bool>(items: &mut [T], predicate: F) -> usize {
+    let mut mid = 0;
+    for i in 0..items.len() {
+        if predicate(&items[i]) {
+            items.swap(i, mid);
+            mid += 1;
+        }
+    }
+    mid
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BvhConfig, SplitStrategy};
+    use crate::geom::{Point3, Vector3};
+    use crate::ray::Ray;
+    use crate::shapes::{make_random_scene, HittableObjects, Interval, INFINITY};
+    use rand::prelude::*;
+
+    const STRATEGIES: [SplitStrategy; 3] = [
+        SplitStrategy::Midpoint,
+        SplitStrategy::EqualCount,
+        SplitStrategy::Sah { bins: 12 },
+    ];
+
+    #[test]
+    fn bvh_matches_linear_scan() {
+        let mut objects = make_random_scene();
+        for strategy in STRATEGIES {
+            objects.build_bvh(BvhConfig {
+                strategy,
+                max_leaf_size: 4,
+            });
+            assert_matches_linear_scan(&objects);
+        }
+    }
+
+    #[test]
+    fn stats_describe_tree() {
+        let mut objects = make_random_scene();
+        let n = objects.objects.len();
+        for strategy in STRATEGIES {
+            let stats = objects.build_bvh(BvhConfig {
+                strategy,
+                max_leaf_size: 4,
+            });
+            let shapes_in_leaves: usize = stats
+                .leaf_size_histogram
+                .iter()
+                .enumerate()
+                .map(|(size, count)| size * count)
+                .sum();
+            assert_eq!(shapes_in_leaves, n);
+            // every interior node has exactly two children
+            assert_eq!(stats.node_count, 2 * stats.leaf_count - 1);
+            assert!(stats.min_leaf_size >= 1);
+            assert!(stats.depth > 1 && stats.depth < n);
+        }
+    }
+
+    fn assert_matches_linear_scan(objects: &HittableObjects) {
+        let mut rng = StdRng::seed_from_u64(7);
+        let interval = Interval::new(1e-3_f64, INFINITY);
+        let mut hits = 0;
+
+        for _ in 0..10_000 {
+            let origin = Point3::new(
+                rng.gen_range(-15.0..15.0),
+                rng.gen_range(0.1..5.0),
+                rng.gen_range(-15.0..15.0),
+            );
+            let direction = Vector3::new(
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+                rng.gen_range(-1.0..1.0),
+            );
+            let r = Ray::new(origin, direction);
+
+            let expected = objects.hit_linear(&r, interval);
+            let actual = objects.hit(&r, interval);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    hits += 1;
+                    assert_eq!(expected.t, actual.t);
+                    assert_eq!(expected.p, actual.p);
+                    assert_eq!(expected.normal, actual.normal);
+                }
+                (expected, actual) => panic!(
+                    "BVH disagrees with linear scan for {:?}: {:?} vs {:?}",
+                    r, expected, actual
+                ),
+            }
+        }
+        // make sure the comparison wasn't vacuous
+        assert!(hits > 1000);
+    }
+}
@@ -0,0 +1,300 @@
+use crate::color::Color;
+use crate::geom::{random_unit_vector, Point3, Vector3};
+use crate::material::Material;
+use crate::random;
+use crate::shapes::{Hittable, Intersection, Quad, Shape, Sphere};
+use rand::prelude::*;
+
+// light.rs
+//
+// Emissive shapes are collected into a list of lights as they're added to
+// the scene, so the renderer can aim rays at them instead of waiting for
+// scattered rays to find them by chance.
+
+/// The geometry of a light, stored in the form that's easiest to sample
+#[derive(Debug, Clone)]
+enum Emitter {
+    Sphere {
+        center: Point3,
+        radius: f64,
+    },
+    Quad(Quad),
+    /// A triangle, or one face of a mesh
+    Triangle(Point3, Point3, Point3),
+}
+
+/// A shape that gives off light
+#[derive(Debug, Clone)]
+pub struct Light {
+    emitter: Emitter,
+    /// Radiance leaving the front of the surface
+    radiance: Color,
+    area: f64,
+}
+
+/// A point on a light, chosen by `Lights::sample`
+#[derive(Debug, Copy, Clone)]
+pub struct LightSample {
+    pub point: Point3,
+    /// Unit normal pointing out of the light's front face, the side that
+    /// emits
+    pub normal: Vector3,
+    pub radiance: Color,
+    /// Probability density of choosing `point`, per unit area. This includes
+    /// the probability of choosing the light it's on.
+    pub pdf: f64,
+}
+
+/// Radiance given off by a material, if it's a light
+fn radiance(material: &Material) -> Option<Color> {
+    match material {
+        Material::DiffuseLight(color, intensity) => Some(*color * *intensity),
+        Material::Bumped(material, _) => radiance(material),
+        _ => None,
+    }
+}
+
+fn triangle_area(a: Point3, b: Point3, c: Point3) -> f64 {
+    0.5 * (b - a).cross(&(c - a)).norm()
+}
+
+impl Light {
+    /// Picks a point uniformly over the light's surface, returning it with
+    /// the outward normal there
+    fn sample_point(&self) -> (Point3, Vector3) {
+        let mut rng = random::rng();
+        match &self.emitter {
+            Emitter::Sphere { center, radius } => {
+                let normal = random_unit_vector();
+                (*center + *radius * normal, normal)
+            }
+            Emitter::Quad(quad) => (quad.point_at(rng.gen(), rng.gen()), quad.normal()),
+            Emitter::Triangle(a, b, c) => {
+                // folding the unit square onto the triangle keeps the
+                // density uniform
+                let s = rng.gen::<f64>().sqrt();
+                let t: f64 = rng.gen();
+                let point = *a + s * (1.0 - t) * (*b - *a) + s * t * (*c - *a);
+                (point, (*b - *a).cross(&(*c - *a)).to_unit_vector())
+            }
+        }
+    }
+
+    /// Light is chosen in proportion to the power it gives off
+    fn power(&self) -> f64 {
+        self.area * self.radiance.luminance()
+    }
+}
+
+/// All the lights in a scene
+#[derive(Debug, Clone, Default)]
+pub struct Lights {
+    lights: Vec<Light>,
+    // running total of the lights' power, for choosing one
+    cumulative_power: Vec<f64>,
+}
+
+impl Lights {
+    pub fn new() -> Self {
+        Lights::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.lights.clear();
+        self.cumulative_power.clear();
+    }
+
+    fn push(&mut self, emitter: Emitter, radiance: Color, area: f64) {
+        let light = Light {
+            emitter,
+            radiance,
+            area,
+        };
+        let power = light.power();
+        // lights that give off nothing would never be chosen anyway
+        if power > 0.0 && power.is_finite() {
+            let total = self.total_power();
+            self.lights.push(light);
+            self.cumulative_power.push(total + power);
+        }
+    }
+
+    /// Adds the shape if its material emits light. Each face of an emissive
+    /// mesh becomes a separate light.
+    pub fn add_shape(&mut self, shape: &Shape) {
+        match shape {
+            Shape::Sphere(Sphere {
+                center,
+                radius,
+                material,
+            }) => {
+                if let Some(radiance) = radiance(material) {
+                    let emitter = Emitter::Sphere {
+                        center: *center,
+                        radius: *radius,
+                    };
+                    let area = 4.0 * std::f64::consts::PI * radius * radius;
+                    self.push(emitter, radiance, area);
+                }
+            }
+            Shape::Quad(quad) => {
+                if let Some(radiance) = radiance(quad.get_material()) {
+                    self.push(Emitter::Quad(quad.clone()), radiance, quad.area());
+                }
+            }
+            Shape::Triangle(triangle) => {
+                if let Some(radiance) = radiance(triangle.get_material()) {
+                    let (a, b, c) = triangle.vertices();
+                    self.push(Emitter::Triangle(a, b, c), radiance, triangle_area(a, b, c));
+                }
+            }
+            Shape::TriangleMesh(mesh) => {
+                if let Some(radiance) = radiance(mesh.get_material()) {
+                    for face in 0..mesh.len() {
+                        let (a, b, c) = mesh.vertices(face);
+                        self.push(Emitter::Triangle(a, b, c), radiance, triangle_area(a, b, c));
+                    }
+                }
+            }
+        }
+    }
+
+    fn total_power(&self) -> f64 {
+        self.cumulative_power.last().copied().unwrap_or(0.0)
+    }
+
+    /// Chooses a light, in proportion to its power, and then a point on it.
+    /// Returns `None` if there are no lights.
+    pub fn sample(&self) -> Option<LightSample> {
+        let total = self.total_power();
+        let x = random::rng().gen::<f64>() * total;
+        let index = self.cumulative_power.partition_point(|&power| power <= x);
+        let light = self
+            .lights
+            .get(index.min(self.lights.len().checked_sub(1)?))?;
+        let (point, normal) = light.sample_point();
+        Some(LightSample {
+            point,
+            normal,
+            radiance: light.radiance,
+            // (power / total) * (1 / area)
+            pdf: light.radiance.luminance() / total,
+        })
+    }
+
+    /// Probability density, per unit solid angle as seen from `from`, of
+    /// `sample` picking the point `hit`. Zero if `hit` isn't on a light.
+    pub fn pdf(&self, from: Point3, hit: &Intersection) -> f64 {
+        let total = self.total_power();
+        let radiance = match radiance(hit.material) {
+            Some(radiance) if total > 0.0 => radiance,
+            _ => return 0.0,
+        };
+        let to_hit = hit.p - from;
+        let distance_squared = to_hit.length_squared();
+        // `sample` uses the light's true normal, so shading normals must not
+        // change the density
+        let cosine = hit.geometric_normal.dot(&to_hit).abs() / distance_squared.sqrt();
+        if cosine <= 0.0 {
+            return 0.0;
+        }
+        // the same density per unit area as `sample`, spread over the
+        // solid angle that a unit of the light's area covers
+        radiance.luminance() / total * distance_squared / cosine
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Lights;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::random;
+    use crate::ray::Ray;
+    use crate::shapes::{HittableObjects, Interval, Quad, Shape, Sphere, INFINITY};
+    use crate::texture::Texture;
+
+    fn quad_light(intensity: f64) -> Shape {
+        Shape::Quad(Quad::new(
+            Point3::new(0., 2., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 0., 1.),
+            Material::DiffuseLight(Color::WHITE, intensity),
+        ))
+    }
+
+    #[test]
+    fn lights_are_collected_from_emissive_shapes() {
+        let mut objects = HittableObjects::new();
+        objects.add(Shape::Sphere(Sphere::new(
+            Point3::origin(),
+            1.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        )));
+        objects.add(quad_light(4.));
+        objects.extend([
+            Shape::Sphere(Sphere::new(
+                Point3::new(0., 5., 0.),
+                0.5,
+                Material::DiffuseLight(Color::new(1., 0.5, 0.), 2.),
+            )),
+            // black lights are left out
+            quad_light(0.),
+        ]);
+        assert_eq!(objects.lights().len(), 2);
+        objects.clear();
+        assert!(objects.lights().is_empty());
+    }
+
+    #[test]
+    fn brighter_lights_are_chosen_more_often() {
+        random::reseed(1);
+        let mut lights = Lights::new();
+        lights.add_shape(&quad_light(1.));
+        lights.add_shape(&Shape::Quad(Quad::new(
+            Point3::new(0., -2., 0.),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 0., 1.),
+            Material::DiffuseLight(Color::WHITE, 3.),
+        )));
+        let samples = 10_000;
+        let upper = (0..samples)
+            .filter(|_| lights.sample().unwrap().point.y > 0.)
+            .count();
+        assert!((upper as f64 / samples as f64 - 0.25).abs() < 0.02);
+        // both lights have unit area, so the density is the chance of
+        // choosing the light
+        let sample = lights.sample().unwrap();
+        let expected = if sample.point.y > 0. { 0.25 } else { 0.75 };
+        assert!((sample.pdf - expected).abs() < 1e-12);
+    }
+
+    #[test]
+    fn can_find_pdf_of_hit_light() {
+        let mut objects = HittableObjects::new();
+        objects.add(quad_light(1.));
+        // looking straight up at the light, 2 units away
+        let r = Ray::new(Point3::new(0.5, 0., 0.5), Vector3::new(0., 1., 0.));
+        let hit = objects.hit(&r, Interval::new(1e-3, INFINITY)).unwrap();
+        assert!((objects.lights().pdf(r.origin, &hit) - 4.).abs() < 1e-12);
+        // from the same distance at 60 degrees, the light looks half as big
+        let r = Ray::new(
+            Point3::new(0.5 - 3f64.sqrt(), 1., 0.5),
+            Vector3::new(3f64.sqrt(), 1., 0.),
+        );
+        let mut hit = objects.hit(&r, Interval::new(1e-3, INFINITY)).unwrap();
+        assert!((objects.lights().pdf(r.origin, &hit) - 8.).abs() < 1e-9);
+        // tilting the shading normal, as a normal map would, changes nothing
+        hit.normal = Vector3::new(1., -1., 0.).to_unit_vector();
+        assert!((objects.lights().pdf(r.origin, &hit) - 8.).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,363 @@
+use crate::geom::{Point3, Vector3};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::shapes::aabb::Aabb;
+use crate::shapes::bvh::{Bvh, BvhConfig};
+use crate::shapes::triangle;
+use crate::shapes::{Hittable, Intersection, Interval};
+
+/// Half-width of the interval searched by `compute_intersection`
+const T_TOLERANCE: f64 = 1e-9;
+
+/// A collection of triangles sharing vertex data and a single material.
+///
+/// Each attribute (position, normal, texture coordinate) lives in its own
+/// buffer, and each face has three indices into every buffer it uses, as in
+/// a Wavefront OBJ file. The mesh keeps its own BVH over its faces, so it
+/// can be added to `HittableObjects` as a single shape.
+#[derive(Debug, Clone)]
+pub struct TriangleMesh {
+    positions: Vec<Point3>,
+    normals: Vec<Vector3>,
+    texture_coordinates: Vec<(f64, f64)>,
+    position_indices: Vec<[u32; 3]>,
+    // either empty, or one entry per face
+    normal_indices: Vec<[u32; 3]>,
+    texture_coordinate_indices: Vec<[u32; 3]>,
+    material: Material,
+    bvh: Bvh,
+}
+
+/// Panics unless there is one set of indices per face and they all refer to
+/// elements of a buffer of length `count`
+fn check_indices(indices: &[[u32; 3]], faces: usize, count: usize, kind: &str) {
+    assert_eq!(
+        indices.len(),
+        faces,
+        "mesh has {} faces but {} sets of {} indices",
+        faces,
+        indices.len(),
+        kind
+    );
+    if let Some(index) = indices.iter().flatten().find(|&&i| i as usize >= count) {
+        panic!(
+            "{} index {} is out of range for {} {}s",
+            kind, index, count, kind
+        );
+    }
+}
+
+impl TriangleMesh {
+    /// Creates a flat-shaded mesh. Each entry of `indices` lists the positions
+    /// of one face's corners, counter-clockwise when seen from the front.
+    ///
+    /// Panics if there are no faces or an index is out of range.
+    pub fn new(positions: Vec<Point3>, indices: Vec<[u32; 3]>, material: Material) -> Self {
+        TriangleMesh::with_bvh_config(positions, indices, material, BvhConfig::default())
+    }
+
+    /// Same as `new`, but builds the mesh's BVH with the given settings
+    pub fn with_bvh_config(
+        positions: Vec<Point3>,
+        indices: Vec<[u32; 3]>,
+        material: Material,
+        config: BvhConfig,
+    ) -> Self {
+        // an empty mesh has no bounding box to place in a BVH
+        assert!(!indices.is_empty(), "mesh has no faces");
+        check_indices(&indices, indices.len(), positions.len(), "position");
+        let boxes: Vec<Aabb> = indices
+            .iter()
+            .map(|face| triangle::bounding_box(face_vertices(&positions, face)))
+            .collect();
+        TriangleMesh {
+            positions,
+            normals: Vec::new(),
+            texture_coordinates: Vec::new(),
+            position_indices: indices,
+            normal_indices: Vec::new(),
+            texture_coordinate_indices: Vec::new(),
+            material,
+            bvh: Bvh::new(&boxes, config),
+        }
+    }
+
+    /// Adds vertex normals for smooth shading, with one set of indices into
+    /// `normals` per face.
+    ///
+    /// Panics if an index is out of range or the number of faces differs.
+    pub fn with_normals(mut self, normals: Vec<Vector3>, indices: Vec<[u32; 3]>) -> Self {
+        check_indices(&indices, self.len(), normals.len(), "normal");
+        self.normals = normals;
+        self.normal_indices = indices;
+        self
+    }
+
+    /// Adds (u, v) texture coordinates, with one set of indices into
+    /// `texture_coordinates` per face.
+    ///
+    /// Panics if an index is out of range or the number of faces differs.
+    pub fn with_texture_coordinates(
+        mut self,
+        texture_coordinates: Vec<(f64, f64)>,
+        indices: Vec<[u32; 3]>,
+    ) -> Self {
+        check_indices(
+            &indices,
+            self.len(),
+            texture_coordinates.len(),
+            "texture coordinate",
+        );
+        self.texture_coordinates = texture_coordinates;
+        self.texture_coordinate_indices = indices;
+        self
+    }
+
+    /// Number of faces
+    pub fn len(&self) -> usize {
+        self.position_indices.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.position_indices.is_empty()
+    }
+
+    pub fn vertices(&self, face: usize) -> (Point3, Point3, Point3) {
+        face_vertices(&self.positions, &self.position_indices[face])
+    }
+
+    fn vertex_normals(&self, face: usize) -> Option<(Vector3, Vector3, Vector3)> {
+        let [a, b, c] = self.normal_indices.get(face)?;
+        Some((
+            self.normals[*a as usize],
+            self.normals[*b as usize],
+            self.normals[*c as usize],
+        ))
+    }
+
+    /// Interpolates the texture coordinates of `face` at the point with the
+    /// given barycentric coordinates, if the mesh has texture coordinates
+    pub fn texture_coordinates(
+        &self,
+        face: usize,
+        barycentric: (f64, f64, f64),
+    ) -> Option<(f64, f64)> {
+        let [a, b, c] = self.texture_coordinate_indices.get(face)?;
+        let (ua, va) = self.texture_coordinates[*a as usize];
+        let (ub, vb) = self.texture_coordinates[*b as usize];
+        let (uc, vc) = self.texture_coordinates[*c as usize];
+        let (wa, wb, wc) = barycentric;
+        Some((wa * ua + wb * ub + wc * uc, wa * va + wb * vb + wc * vc))
+    }
+
+    /// How points on `face` move with their texture coordinates, if the
+    /// mesh has them and they don't collapse the face to a line
+    fn texture_derivatives(&self, face: usize) -> Option<(Vector3, Vector3)> {
+        let [a, b, c] = self.texture_coordinate_indices.get(face)?;
+        let (ua, va) = self.texture_coordinates[*a as usize];
+        let (ub, vb) = self.texture_coordinates[*b as usize];
+        let (uc, vc) = self.texture_coordinates[*c as usize];
+        let (du1, dv1, du2, dv2) = (ub - ua, vb - va, uc - ua, vc - va);
+        let determinant = du1 * dv2 - dv1 * du2;
+        if determinant.abs() < 1e-12 {
+            return None;
+        }
+        // solve e1 = du1 dpdu + dv1 dpdv and e2 = du2 dpdu + dv2 dpdv
+        let (p0, p1, p2) = self.vertices(face);
+        let (e1, e2) = (p1 - p0, p2 - p0);
+        let dpdu = (dv2 * e1 - dv1 * e2) / determinant;
+        let dpdv = (du1 * e2 - du2 * e1) / determinant;
+        Some((dpdu, dpdv))
+    }
+
+    fn hit_face(&self, face: usize, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        let vertices = self.vertices(face);
+        let (t, barycentric) = triangle::intersect(r, vertices, interval)?;
+        let mut intersection = triangle::make_intersection(
+            r,
+            t,
+            vertices,
+            self.vertex_normals(face),
+            barycentric,
+            &self.material,
+        );
+        if let Some(uv) = self.texture_coordinates(face, barycentric) {
+            let (dpdu, dpdv) = self
+                .texture_derivatives(face)
+                .unwrap_or((Vector3::new(0., 0., 0.), Vector3::new(0., 0., 0.)));
+            intersection.set_uv(uv, dpdu, dpdv, r);
+        }
+        Some(intersection)
+    }
+}
+
+fn face_vertices(positions: &[Point3], face: &[u32; 3]) -> (Point3, Point3, Point3) {
+    (
+        positions[face[0] as usize],
+        positions[face[1] as usize],
+        positions[face[2] as usize],
+    )
+}
+
+impl Hittable for TriangleMesh {
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    /// Finds the face the ray hits at `t`, searching the BVH again, so it's
+    /// only for rays already known to hit the mesh there. `hit` doesn't use
+    /// it. Panics if no face is hit at `t`.
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_> {
+        self.hit(r, Interval::new(t - T_TOLERANCE, t + T_TOLERANCE))
+            .unwrap_or_else(|| panic!("ray does not hit the mesh at t = {}", t))
+    }
+
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        self.bvh.hit(r, interval, |face, interval| {
+            self.hit_face(face, r, interval)
+        })
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bvh.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TriangleMesh;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, Shape, Triangle, INFINITY};
+    use crate::texture::Texture;
+    use rand::prelude::*;
+
+    const MATERIAL: Material = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+
+    /// A bumpy `n` by `n` grid of quads in the xz-plane, each split in two
+    fn grid(n: u32) -> (Vec<Point3>, Vec<[u32; 3]>) {
+        let mut rng = StdRng::seed_from_u64(3);
+        let mut positions = Vec::new();
+        for i in 0..=n {
+            for k in 0..=n {
+                positions.push(Point3::new(i as f64, rng.gen_range(-0.3..0.3), k as f64));
+            }
+        }
+        let mut indices = Vec::new();
+        let corner = |i: u32, k: u32| i * (n + 1) + k;
+        for i in 0..n {
+            for k in 0..n {
+                indices.push([corner(i, k), corner(i, k + 1), corner(i + 1, k)]);
+                indices.push([corner(i + 1, k), corner(i, k + 1), corner(i + 1, k + 1)]);
+            }
+        }
+        (positions, indices)
+    }
+
+    #[test]
+    fn mesh_matches_separate_triangles() {
+        let (positions, indices) = grid(16);
+        let triangles: Vec<Shape> = indices
+            .iter()
+            .map(|[a, b, c]| {
+                Shape::Triangle(Triangle::new(
+                    positions[*a as usize],
+                    positions[*b as usize],
+                    positions[*c as usize],
+                    MATERIAL,
+                ))
+            })
+            .collect();
+        let mesh = TriangleMesh::new(positions, indices, MATERIAL);
+        assert_eq!(mesh.len(), triangles.len());
+
+        let mut rng = StdRng::seed_from_u64(5);
+        let interval = Interval::new(1e-3, INFINITY);
+        let mut hits = 0;
+        for _ in 0..2000 {
+            let origin = Point3::new(rng.gen_range(0.0..16.0), 5., rng.gen_range(0.0..16.0));
+            let direction = Vector3::new(rng.gen_range(-1.0..1.0), -1., rng.gen_range(-1.0..1.0));
+            let r = Ray::new(origin, direction);
+
+            let expected = triangles
+                .iter()
+                .filter_map(|t| t.hit(&r, interval))
+                .min_by(|a, b| a.t.total_cmp(&b.t));
+            let actual = mesh.hit(&r, interval);
+            match (expected, actual) {
+                (None, None) => {}
+                (Some(expected), Some(actual)) => {
+                    hits += 1;
+                    assert_eq!(expected.t, actual.t);
+                    assert_eq!(expected.normal, actual.normal);
+                }
+                (expected, actual) => panic!("{:?} vs {:?}", expected, actual),
+            }
+        }
+        assert!(hits > 1000);
+    }
+
+    #[test]
+    fn can_interpolate_shared_attributes() {
+        let positions = vec![
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+        ];
+        let up = Vector3::new(0., 0., 1.);
+        let tilted = Vector3::new(1., 0., 1.).to_unit_vector();
+        let mesh = TriangleMesh::new(positions, vec![[0, 1, 2]], MATERIAL)
+            .with_normals(vec![up, tilted], vec![[0, 1, 0]])
+            .with_texture_coordinates(vec![(0., 0.), (1., 0.), (0., 1.)], vec![[0, 1, 2]]);
+
+        let r = Ray::new(Point3::new(0.25, 0.5, 1.), Vector3::new(0., 0., -1.));
+        let hit = mesh.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert!(hit.normal.x > 0.);
+        let (u, v) = mesh
+            .texture_coordinates(0, hit.barycentric.unwrap())
+            .unwrap();
+        assert!((u - 0.25).abs() < 1e-9 && (v - 0.5).abs() < 1e-9);
+
+        let from_t = mesh.compute_intersection(&r, hit.t);
+        assert_eq!(from_t.p, hit.p);
+    }
+
+    #[test]
+    fn can_find_texture_derivatives() {
+        let positions = vec![
+            Point3::new(0., 0., 0.),
+            Point3::new(2., 0., 0.),
+            Point3::new(0., 1., 0.),
+        ];
+        let r = Ray::new(Point3::new(0.5, 0.25, 1.), Vector3::new(0., 0., -1.));
+        // stretched along x, and then mirrored as well
+        let cases = vec![
+            (vec![(0., 0.), (1., 0.), (0., 1.)], Vector3::new(2., 0., 0.)),
+            (
+                vec![(1., 0.), (0., 0.), (1., 1.)],
+                Vector3::new(-2., 0., 0.),
+            ),
+        ];
+        for (texture_coordinates, dpdu) in cases {
+            let mesh = TriangleMesh::new(positions.clone(), vec![[0, 1, 2]], MATERIAL)
+                .with_texture_coordinates(texture_coordinates, vec![[0, 1, 2]]);
+            let hit = mesh.hit(&r, Interval::new(0., INFINITY)).unwrap();
+            assert!((hit.dpdu - dpdu).norm() < 1e-12, "{:?}", hit.dpdu);
+            assert!((hit.dpdv - Vector3::new(0., 1., 0.)).norm() < 1e-12);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "out of range")]
+    fn rejects_bad_indices() {
+        TriangleMesh::new(vec![Point3::origin(); 3], vec![[0, 1, 3]], MATERIAL);
+    }
+
+    #[test]
+    #[should_panic(expected = "no faces")]
+    fn rejects_empty_meshes() {
+        TriangleMesh::new(vec![Point3::origin(); 3], vec![], MATERIAL);
+    }
+}
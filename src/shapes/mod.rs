@@ -1,11 +1,34 @@
 use crate::color::Color;
+use crate::environment::Environment;
 use crate::geom::{Point3, Vector3};
 use crate::material::{select_material, Material};
+use crate::random;
 use crate::ray::Ray;
+use crate::texture::Texture;
 use rand::prelude::*;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+pub mod aabb;
+pub mod bvh;
+pub mod light;
+pub mod mesh;
+pub mod quad;
+pub mod triangle;
+
+use aabb::Aabb;
+use bvh::{Bvh, BvhConfig, BvhStats};
+use light::Lights;
+pub use mesh::TriangleMesh;
+pub use quad::Quad;
+pub use triangle::Triangle;
 
 pub const INFINITY: f64 = f64::INFINITY;
 
+/// Rays leaving a surface ignore hits closer than this, so they don't hit
+/// the surface they left because of rounding errors
+pub const SURFACE_EPSILON: f64 = 1e-3;
+
 /// Determines degree of membership in a real-valued
 /// interval with a minimum and a maximum
 #[derive(Debug, Copy, Clone, PartialEq, PartialOrd)]
@@ -26,6 +49,29 @@ impl Interval {
     pub fn surrounds(&self, x: f64) -> bool {
         self.min < x && x < self.max
     }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    pub fn midpoint(&self) -> f64 {
+        0.5 * (self.min + self.max)
+    }
+
+    /// Widens the interval symmetrically so it is at least `size` long
+    pub fn pad_to(&self, size: f64) -> Interval {
+        if self.size() >= size {
+            return *self;
+        }
+        let half = 0.5 * size;
+        let midpoint = self.midpoint();
+        Interval::new(midpoint - half, midpoint + half)
+    }
+
+    /// Returns the smallest interval containing both intervals
+    pub fn enclose(&self, other: &Interval) -> Interval {
+        Interval::new(self.min.min(other.min), self.max.max(other.max))
+    }
 }
 
 pub const EMPTY_INTERVAL: Interval = Interval {
@@ -40,34 +86,33 @@ pub const UNIVERSE: Interval = Interval {
 pub trait Hittable {
     fn get_material(&self) -> &Material;
     /// Computes the intersection between a ray and a shape at t
-    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection;
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_>;
     /// Returns the intersection between a ray and a shape, if there is one
-    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection>;
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>>;
+    /// Returns a box that fully encloses the shape
+    fn bounding_box(&self) -> Aabb;
 }
 
-/// Shape structs
+// Shape structs
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Clone)]
 pub struct Sphere {
     center: Point3,
     radius: f64,
     material: Material,
 }
 
-// #[derive(Debug, Copy, Clone)]
-// pub struct Triangle {
-//     vertices: (Point3, Point3, Point3),
-//     material: Material,
-// }
-
 /// `Shape` represents a geometric shape in the scene which can be hit by rays.
 /// Using an enum gives us a Shape type without needing generics, which would
 /// make a list of Hittable objects need dyn
-
-#[derive(Debug, Copy, Clone)]
+///
+/// Meshes are shared rather than copied, since they can be very large.
+#[derive(Debug, Clone)]
 pub enum Shape {
     Sphere(Sphere),
-    // Triangle(Triangle),
+    Triangle(Triangle),
+    TriangleMesh(Arc<TriangleMesh>),
+    Quad(Quad),
 }
 
 /// Records the details of a `Ray` hitting a `Hittable` shape (with
@@ -79,12 +124,26 @@ pub struct Intersection<'a> {
     /// point where ray hits shape
     pub p: Point3,
     pub normal: Vector3,
+    /// the surface's own normal, on the same side as `normal`, which
+    /// interpolated vertex normals and bump maps leave alone
+    pub geometric_normal: Vector3,
     pub ray_hit_outer_surface: bool,
+    /// weights of a triangle's three vertices at `p`, for shapes that have them
+    pub barycentric: Option<(f64, f64, f64)>,
+    /// texture coordinates (u, v) at `p`, from 0 to 1 across the shape
+    pub uv: (f64, f64),
+    /// width of the ray's cone at `p`, measured in texture coordinates, for
+    /// choosing how blurry a texture lookup should be
+    pub footprint: f64,
+    /// how `p` moves as u and v increase, which gives the tangent frame
+    /// that normal maps are drawn in. Zero if unknown.
+    pub dpdu: Vector3,
+    pub dpdv: Vector3,
     /// object that is hit by a ray
     pub material: &'a Material, // TODO: replace with material, since that's all we need for now?
 }
 
-/// Shape struct impls
+// Shape struct impls
 
 impl Sphere {
     pub fn new(center: Point3, radius: f64, material: Material) -> Self {
@@ -96,18 +155,47 @@ impl Sphere {
     }
 }
 
+/// Texture coordinates of the point on a unit sphere at `p`. `u` goes
+/// round the y axis from -x, through +z, and `v` goes from the bottom pole
+/// to the top, so an equirectangular image wraps round it.
+fn sphere_uv(p: &Vector3) -> (f64, f64) {
+    let theta = (-p.y).clamp(-1.0, 1.0).acos();
+    let phi = (-p.z).atan2(p.x) + PI;
+    (phi / (2.0 * PI), theta / PI)
+}
+
+/// How the point on a sphere of radius `radius` at unit normal `n` moves
+/// with its texture coordinates. Both are zero at the poles, where u isn't
+/// defined.
+fn sphere_derivatives(n: &Vector3, radius: f64) -> (Vector3, Vector3) {
+    // the radius of the circle of latitude through `n`
+    let ring = (n.x * n.x + n.z * n.z).sqrt();
+    if ring <= 0.0 {
+        return (Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 0.0));
+    }
+    // u goes once round the circle, and v half way round a great circle
+    let along_ring = Vector3::new(n.z, 0.0, -n.x) / ring;
+    let dpdu = 2.0 * PI * radius * ring * along_ring;
+    let dpdv = PI * radius * n.cross(&along_ring);
+    (dpdu, dpdv)
+}
+
 impl Hittable for Sphere {
     fn get_material(&self) -> &Material {
         &self.material
     }
 
-    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection {
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_> {
         let intersection_point = r.at(t);
         let normal: Vector3 = (intersection_point - self.center) / self.radius;
-        Intersection::new(r, t, intersection_point, normal, self.get_material())
+        let mut intersection =
+            Intersection::new(r, t, intersection_point, normal, self.get_material());
+        let (dpdu, dpdv) = sphere_derivatives(&normal, self.radius);
+        intersection.set_uv(sphere_uv(&normal), dpdu, dpdv, r);
+        intersection
     }
 
-    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection> {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
         let oc = r.origin - self.center;
         let a = r.direction.length_squared();
         let half_b = r.direction.dot(&oc);
@@ -128,24 +216,47 @@ impl Hittable for Sphere {
 
         None
     }
+
+    fn bounding_box(&self) -> Aabb {
+        let extent = Vector3::new(self.radius, self.radius, self.radius);
+        Aabb::from_points(self.center - extent, self.center + extent)
+    }
 }
 
 impl Hittable for Shape {
     fn get_material(&self) -> &Material {
         match self {
             Shape::Sphere(sphere) => sphere.get_material(),
+            Shape::Triangle(triangle) => triangle.get_material(),
+            Shape::TriangleMesh(mesh) => mesh.get_material(),
+            Shape::Quad(quad) => quad.get_material(),
         }
     }
 
-    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection {
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_> {
         match self {
             Shape::Sphere(sphere) => sphere.compute_intersection(r, t),
+            Shape::Triangle(triangle) => triangle.compute_intersection(r, t),
+            Shape::TriangleMesh(mesh) => mesh.compute_intersection(r, t),
+            Shape::Quad(quad) => quad.compute_intersection(r, t),
         }
     }
 
-    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection> {
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
         match self {
             Shape::Sphere(sphere) => sphere.hit(r, interval),
+            Shape::Triangle(triangle) => triangle.hit(r, interval),
+            Shape::TriangleMesh(mesh) => mesh.hit(r, interval),
+            Shape::Quad(quad) => quad.hit(r, interval),
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        match self {
+            Shape::Sphere(sphere) => sphere.bounding_box(),
+            Shape::Triangle(triangle) => triangle.bounding_box(),
+            Shape::TriangleMesh(mesh) => mesh.bounding_box(),
+            Shape::Quad(quad) => quad.bounding_box(),
         }
     }
 }
@@ -169,35 +280,124 @@ impl<'a> Intersection<'a> {
             t,
             p,
             normal: new_normal,
+            geometric_normal: new_normal,
             ray_hit_outer_surface,
+            barycentric: None,
+            uv: (0.0, 0.0),
+            footprint: 0.0,
+            dpdu: Vector3::new(0.0, 0.0, 0.0),
+            dpdv: Vector3::new(0.0, 0.0, 0.0),
             material,
         }
     }
+
+    /// Sets the texture coordinates and their derivatives, along with the
+    /// ray's footprint in them
+    pub fn set_uv(&mut self, uv: (f64, f64), dpdu: Vector3, dpdv: Vector3, r: &Ray) {
+        self.uv = uv;
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        // the surface area covered by a unit square of texture coordinates
+        let uv_area = dpdu.cross(&dpdv).norm();
+        self.footprint = if uv_area > 0.0 {
+            r.width_at(self.t) / uv_area.sqrt()
+        } else {
+            0.0
+        };
+    }
 }
 
 pub struct HittableObjects {
     // The HittableObjects list will own its objects, so no lifetime
     // parameter needed
     pub objects: Vec<Shape>,
+    /// What rays that miss every object see
+    pub environment: Environment,
+    // Acceleration structure over `objects`, if one has been built
+    bvh: Option<Bvh>,
+    // The emissive objects, kept up to date by `add`, `extend` and `clear`
+    lights: Lights,
+}
+
+impl Default for HittableObjects {
+    fn default() -> Self {
+        HittableObjects::new()
+    }
+}
+
+impl Extend<Shape> for HittableObjects {
+    /// Adds every item. Any previously built BVH is discarded.
+    fn extend<I: IntoIterator<Item = Shape>>(&mut self, iter: I) {
+        for object in iter {
+            self.lights.add_shape(&object);
+            self.objects.push(object);
+        }
+        self.bvh = None;
+    }
 }
 
 impl HittableObjects {
     pub fn new() -> HittableObjects {
         HittableObjects {
             objects: Vec::new(),
+            environment: Environment::default(),
+            bvh: None,
+            lights: Lights::new(),
         }
     }
 
-    /// Add item
+    /// Add item. Any previously built BVH is discarded.
     pub fn add(&mut self, object: Shape) {
+        self.lights.add_shape(&object);
         self.objects.push(object);
+        self.bvh = None;
     }
 
     pub fn clear(&mut self) {
         self.objects.clear();
+        self.lights.clear();
+        self.bvh = None;
     }
 
-    pub fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection> {
+    /// The objects that give off light. Shapes pushed directly onto
+    /// `objects`, rather than through `add` or `extend`, are missing from it.
+    pub fn lights(&self) -> &Lights {
+        &self.lights
+    }
+
+    /// Builds a bounding volume hierarchy over the current objects, so that
+    /// `hit` no longer has to test every object. Call this once the scene is
+    /// complete, before rendering.
+    pub fn build_bvh(&mut self, config: BvhConfig) -> BvhStats {
+        let boxes: Vec<Aabb> = self.objects.iter().map(|o| o.bounding_box()).collect();
+        let bvh = Bvh::new(&boxes, config);
+        let stats = bvh.stats().clone();
+        self.bvh = Some(bvh);
+        stats
+    }
+
+    /// Returns the closest intersection of the ray with any of the objects
+    pub fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        let mut intersection = self.closest_hit(r, interval)?;
+        let material = intersection.material;
+        material.perturb_normal(&mut intersection);
+        Some(intersection)
+    }
+
+    /// Same as `hit`, but without the material's normal or bump map, which
+    /// rays that only check for a hit don't need
+    fn closest_hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        match &self.bvh {
+            Some(bvh) => bvh.hit(r, interval, |index, interval| {
+                self.objects[index].hit(r, interval)
+            }),
+            None => self.hit_linear(r, interval),
+        }
+    }
+
+    /// Same as `hit`, but tests every object in turn instead of using the
+    /// BVH, and leaves out normal and bump maps
+    pub fn hit_linear(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
         let mut closest_intersection: Option<Intersection> = None;
         let mut closest_hit = interval.max;
 
@@ -214,47 +414,36 @@ impl HittableObjects {
         closest_intersection
     }
 
-    pub fn compute_ray_color(&self, r: Ray, depth: i32) -> Color {
-        if depth <= 0 {
-            // If ray has bounced more than allowed number of bounces,
-            // stop collecting light for it
-            return Color::BLACK;
-        }
-
-        let intersection = self.hit(&r, Interval::new(1e-3_f64, INFINITY));
-
-        match intersection {
-            Some(intersect) => {
-                let intersection_material = intersect.material;
-                let ray_and_color = intersection_material.scatter(r, &intersect);
+    /// Whether anything lies on the straight line between two points,
+    /// not counting the surfaces the points are on
+    pub fn is_occluded(&self, from: Point3, to: Point3) -> bool {
+        let to_target = to - from;
+        let distance = to_target.norm();
+        let r = Ray::new(from, to_target / distance);
+        self.closest_hit(
+            &r,
+            Interval::new(SURFACE_EPSILON, distance - SURFACE_EPSILON),
+        )
+        .is_some()
+    }
 
-                match ray_and_color {
-                    Some((scattered_ray, attenuation)) => {
-                        attenuation.mult(self.compute_ray_color(scattered_ray, depth - 1))
-                    }
-                    None => Color::BLACK,
-                }
-            }
-            None => {
-                let ray_direction = r.direction.to_unit_vector();
-                // y is [-1,1], so t is [0,1]
-                let t = 0.5 * (ray_direction.y + 1.0);
-                // linear interpolation between while and a light blue, based on y-component of ray
-                // blendedValue = (1−t)*startValue + t * endValue
-                (1.0 - t) * Color::WHITE + t * Color::new(0.5, 0.7, 1.0)
-            }
-        }
+    /// Whether anything lies in `direction` from `from`, not counting the
+    /// surface `from` is on
+    pub fn is_occluded_towards(&self, from: Point3, direction: Vector3) -> bool {
+        let r = Ray::new(from, direction);
+        self.closest_hit(&r, Interval::new(SURFACE_EPSILON, INFINITY))
+            .is_some()
     }
 }
 
-pub fn make_random_scene<'a>() -> HittableObjects {
+pub fn make_random_scene() -> HittableObjects {
     let mut objects = HittableObjects::new();
 
-    let ground_material = Material::DiffuseNonMetal(Color::new(0.5, 0.5, 0.5));
+    let ground_material = Material::DiffuseNonMetal(Texture::Constant(Color::new(0.5, 0.5, 0.5)));
     let mut sphere = Sphere::new(Point3::new(0., -1000., 0.), 1000., ground_material);
     objects.add(Shape::Sphere(sphere));
 
-    let mut rng = rand::thread_rng();
+    let mut rng = random::rng();
 
     for a in -11..11 {
         for b in -11..11 {
@@ -278,13 +467,162 @@ pub fn make_random_scene<'a>() -> HittableObjects {
     objects.add(Shape::Sphere(sphere));
 
     let albedo = Color::new(0.4, 0.2, 0.1);
-    let material2 = Material::DiffuseNonMetal(albedo);
+    let material2 = Material::DiffuseNonMetal(Texture::Constant(albedo));
     sphere = Sphere::new(Point3::new(-4., 1., 0.), 1., material2);
     objects.add(Shape::Sphere(sphere));
 
-    let material3 = Material::Metal(Color::new(0.7, 0.6, 0.5), 0.);
+    let material3 = Material::Metal(Texture::Constant(Color::new(0.7, 0.6, 0.5)), 0.0.into());
     sphere = Sphere::new(Point3::new(4., 1., 0.), 1., material3);
     objects.add(Shape::Sphere(sphere));
 
     objects
 }
+
+/// The Cornell box: a 555-unit room with a light in the ceiling, holding two
+/// white boxes. Seen from `(278, 278, -800)`, the green wall is on the left
+/// and the red wall on the right. It's only lit by the ceiling light.
+pub fn make_cornell_box() -> HittableObjects {
+    let mut objects = HittableObjects::new();
+    objects.environment = Environment::Black;
+
+    let red = Material::DiffuseNonMetal(Texture::Constant(Color::new(0.65, 0.05, 0.05)));
+    let white = Material::DiffuseNonMetal(Texture::Constant(Color::new(0.73, 0.73, 0.73)));
+    let green = Material::DiffuseNonMetal(Texture::Constant(Color::new(0.12, 0.45, 0.15)));
+    let light = Material::DiffuseLight(Color::WHITE, 15.);
+
+    let x = Vector3::new(555., 0., 0.);
+    let y = Vector3::new(0., 555., 0.);
+    let z = Vector3::new(0., 0., 555.);
+    let far_corner = Point3::new(555., 555., 555.);
+    let walls = vec![
+        Quad::new(Point3::new(555., 0., 0.), y, z, green),
+        Quad::new(Point3::origin(), y, z, red),
+        Quad::new(Point3::origin(), x, z, white.clone()),
+        Quad::new(far_corner, -x, -z, white.clone()),
+        Quad::new(Point3::new(0., 0., 555.), x, y, white.clone()),
+        // the light faces down into the room
+        Quad::new(
+            Point3::new(343., 554., 332.),
+            Vector3::new(-130., 0., 0.),
+            Vector3::new(0., 0., -105.),
+            light,
+        ),
+    ];
+    objects.extend(walls.into_iter().map(Shape::Quad));
+
+    // boxes standing on the floor, turned by `degrees` about the vertical axis
+    let mut add_box = |corner: Point3, size: Vector3, degrees: f64| {
+        let (sin, cos) = degrees.to_radians().sin_cos();
+        let turn = |v: Vector3| Vector3::new(cos * v.x + sin * v.z, v.y, -sin * v.x + cos * v.z);
+        objects.extend(quad::make_box(
+            corner,
+            turn(Vector3::new(size.x, 0., 0.)),
+            Vector3::new(0., size.y, 0.),
+            turn(Vector3::new(0., 0., size.z)),
+            white.clone(),
+        ));
+    };
+    add_box(
+        Point3::new(265., 0., 295.),
+        Vector3::new(165., 330., 165.),
+        15.,
+    );
+    add_box(
+        Point3::new(130., 0., 65.),
+        Vector3::new(165., 165., 165.),
+        -18.,
+    );
+
+    objects
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        sphere_derivatives, sphere_uv, Hittable, HittableObjects, Interval, Shape, Sphere, INFINITY,
+    };
+    use crate::bump::Bump;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::texture::Texture;
+    use std::f64::consts::PI;
+
+    #[test]
+    fn can_find_sphere_texture_coordinates() {
+        // u starts at -x and goes round through +z, and v goes up from -y
+        let cases = vec![
+            (Vector3::new(-1., 0., 0.), (0., 0.5)),
+            (Vector3::new(0., 0., -1.), (0.75, 0.5)),
+            (Vector3::new(1., 0., 0.), (0.5, 0.5)),
+            (Vector3::new(0., 0., 1.), (0.25, 0.5)),
+            (Vector3::new(0., 1., 0.), (0.5, 1.)),
+            (Vector3::new(0., -1., 0.), (0.5, 0.)),
+        ];
+        for (p, (u, v)) in cases {
+            let uv = sphere_uv(&p);
+            assert!(
+                (uv.0 - u).abs() < 1e-12 && (uv.1 - v).abs() < 1e-12,
+                "{:?} at {:?}",
+                uv,
+                p
+            );
+        }
+
+        let sphere = Sphere::new(
+            Point3::new(0., 0., -5.),
+            2.,
+            Material::DiffuseNonMetal(Texture::Constant(Color::WHITE)),
+        );
+        let r = Ray::new(Point3::origin(), Vector3::new(0., 0., -1.));
+        let hit = sphere.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert!((hit.uv.0 - 0.25).abs() < 1e-12 && (hit.uv.1 - 0.5).abs() < 1e-12);
+    }
+
+    #[test]
+    fn sphere_derivatives_follow_texture_coordinates() {
+        let radius = 2.;
+        let point = |u: f64, v: f64| {
+            // the inverse of `sphere_uv`
+            let (phi, theta) = (2. * PI * u, PI * v);
+            radius
+                * Vector3::new(
+                    -theta.sin() * phi.cos(),
+                    -theta.cos(),
+                    theta.sin() * phi.sin(),
+                )
+        };
+        let h = 1e-6;
+        for &(u, v) in &[(0.1, 0.3), (0.6, 0.5), (0.9, 0.8)] {
+            let n = point(u, v) / radius;
+            let uv = sphere_uv(&n);
+            assert!((uv.0 - u).abs() < 1e-9 && (uv.1 - v).abs() < 1e-9);
+            let (dpdu, dpdv) = sphere_derivatives(&n, radius);
+            let expected_dpdu = (point(u + h, v) - point(u, v)) / h;
+            let expected_dpdv = (point(u, v + h) - point(u, v)) / h;
+            assert!((dpdu - expected_dpdu).norm() < 1e-4, "{:?}", dpdu);
+            assert!((dpdv - expected_dpdv).norm() < 1e-4, "{:?}", dpdv);
+        }
+    }
+
+    #[test]
+    fn hits_are_shaded_with_normal_maps() {
+        let flat = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+        // tilted towards u, which runs round the sphere
+        let bumped = Material::Bumped(
+            Box::new(flat),
+            Bump::NormalMap(Color::new(1., 0.5, 1.).into()),
+        );
+        let mut objects = HittableObjects::new();
+        objects.add(Shape::Sphere(Sphere::new(Point3::origin(), 1., bumped)));
+        let r = Ray::new(Point3::new(0., 0., 5.), Vector3::new(0., 0., -1.));
+        let hit = objects.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        let half = 0.5f64.sqrt();
+        assert!((hit.normal - Vector3::new(half, 0., half)).norm() < 1e-9);
+        // the map doesn't move the surface
+        assert_eq!(hit.p, Point3::new(0., 0., 1.));
+        let unmapped = objects.hit_linear(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_eq!(unmapped.normal, Vector3::new(0., 0., 1.));
+    }
+}
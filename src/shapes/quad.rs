@@ -0,0 +1,223 @@
+use crate::geom::{Point3, Vector3};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::shapes::aabb::Aabb;
+use crate::shapes::{Hittable, Intersection, Interval, Shape};
+
+/// Denominators smaller than this mean the ray is parallel to the quad
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// Quads lying in an axis-aligned plane get boxes at least this thick
+const MIN_BOX_THICKNESS: f64 = 1e-6;
+
+/// A parallelogram with one corner at `corner` and edges `u` and `v`
+/// leading to the two neighbouring corners. The front face is the one that
+/// `u × v` points out of.
+#[derive(Debug, Clone)]
+pub struct Quad {
+    corner: Point3,
+    u: Vector3,
+    v: Vector3,
+    // unit normal, and the plane's distance from the origin along it
+    normal: Vector3,
+    d: f64,
+    // u × v divided by its squared length, for finding a hit's (u, v) coordinates
+    w: Vector3,
+    material: Material,
+}
+
+impl Quad {
+    pub fn new(corner: Point3, u: Vector3, v: Vector3, material: Material) -> Self {
+        let n = u.cross(&v);
+        let normal = n.to_unit_vector();
+        Quad {
+            corner,
+            u,
+            v,
+            normal,
+            d: normal.dot(&corner.as_vector()),
+            w: n / n.length_squared(),
+            material,
+        }
+    }
+
+    /// Unit normal pointing out of the front face
+    pub fn normal(&self) -> Vector3 {
+        self.normal
+    }
+
+    /// The point `alpha` of the way along `u` and `beta` of the way along `v`
+    pub fn point_at(&self, alpha: f64, beta: f64) -> Point3 {
+        self.corner + alpha * self.u + beta * self.v
+    }
+
+    /// The coordinates of `p` along the two edges, which are between 0 and
+    /// 1 for points on the quad. Also its texture coordinates.
+    pub fn coordinates(&self, p: Point3) -> (f64, f64) {
+        let p = p - self.corner;
+        (self.w.dot(&p.cross(&self.v)), self.w.dot(&self.u.cross(&p)))
+    }
+
+    pub fn area(&self) -> f64 {
+        self.u.cross(&self.v).norm()
+    }
+}
+
+impl Hittable for Quad {
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_> {
+        let p = r.at(t);
+        let mut intersection = Intersection::new(r, t, p, self.normal, &self.material);
+        intersection.set_uv(self.coordinates(p), self.u, self.v, r);
+        intersection
+    }
+
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        let denominator = self.normal.dot(&r.direction);
+        if denominator.abs() < PARALLEL_EPSILON {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(&r.origin.as_vector())) / denominator;
+        if !interval.surrounds(t) {
+            return None;
+        }
+
+        let (alpha, beta) = self.coordinates(r.at(t));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+        Some(self.compute_intersection(r, t))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonal = Aabb::from_points(self.corner, self.corner + self.u + self.v);
+        let other_diagonal = Aabb::from_points(self.corner + self.u, self.corner + self.v);
+        let bbox = Aabb::surrounding(&diagonal, &other_diagonal);
+        Aabb::new(
+            bbox.x.pad_to(MIN_BOX_THICKNESS),
+            bbox.y.pad_to(MIN_BOX_THICKNESS),
+            bbox.z.pad_to(MIN_BOX_THICKNESS),
+        )
+    }
+}
+
+/// Returns the six faces of the box with one corner at `corner` and edges
+/// `a`, `b` and `c` leading away from it. The edges don't need to be
+/// axis-aligned, so rotated boxes can be made by rotating the edges.
+/// The faces point outwards when `a × b` points the same way as `c`.
+pub fn make_box(
+    corner: Point3,
+    a: Vector3,
+    b: Vector3,
+    c: Vector3,
+    material: Material,
+) -> Vec<Shape> {
+    let opposite = corner + a + b + c;
+    [
+        (corner, b, a),
+        (corner, c, b),
+        (corner, a, c),
+        (opposite, -a, -b),
+        (opposite, -b, -c),
+        (opposite, -c, -a),
+    ]
+    .iter()
+    .map(|&(q, u, v)| Shape::Quad(Quad::new(q, u, v, material.clone())))
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{make_box, Quad};
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, INFINITY};
+    use crate::texture::Texture;
+
+    const MATERIAL: Material = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+
+    /// The unit square in the xy-plane, facing +z
+    fn unit_square() -> Quad {
+        Quad::new(
+            Point3::origin(),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 1., 0.),
+            MATERIAL,
+        )
+    }
+
+    #[test]
+    fn can_hit_quad() {
+        let quad = unit_square();
+        let r = Ray::new(Point3::new(0.5, 0.25, 3.), Vector3::new(0., 0., -1.));
+        let hit = quad.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_eq!(hit.t, 3.);
+        assert_eq!(hit.normal, Vector3::new(0., 0., 1.));
+        assert!(hit.ray_hit_outer_surface);
+        assert_eq!(hit.uv, (0.5, 0.25));
+        assert_eq!(hit.footprint, 0.);
+        let hit = quad
+            .hit(&r.with_spread(0.01), Interval::new(0., INFINITY))
+            .unwrap();
+        assert!((hit.footprint - 0.03).abs() < 1e-12);
+
+        let r = Ray::new(Point3::new(0.5, 0.25, -3.), Vector3::new(0., 0., 1.));
+        let hit = quad.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert!(!hit.ray_hit_outer_surface);
+        assert_eq!(quad.area(), 1.);
+    }
+
+    #[test]
+    fn rays_miss_outside_quad() {
+        let quad = Quad::new(
+            Point3::new(1., 1., 1.),
+            Vector3::new(2., 0., 0.),
+            Vector3::new(1., 1., 0.),
+            MATERIAL,
+        );
+        let down = Vector3::new(0., 0., -1.);
+        let interval = Interval::new(0., INFINITY);
+        // inside the parallelogram, and just outside its slanted edges
+        assert!(quad
+            .hit(&Ray::new(Point3::new(3.5, 1.9, 5.), down), interval)
+            .is_some());
+        assert!(quad
+            .hit(&Ray::new(Point3::new(1.5, 1.9, 5.), down), interval)
+            .is_none());
+        assert!(quad
+            .hit(&Ray::new(Point3::new(2.5, 1.1, 5.), down), interval)
+            .is_some());
+        assert!(quad
+            .hit(&Ray::new(Point3::new(2.5, 0.9, 5.), down), interval)
+            .is_none());
+        // parallel to the plane
+        let sideways = Ray::new(Point3::new(0., 1.5, 1.), Vector3::new(1., 0., 0.));
+        assert!(quad.hit(&sideways, interval).is_none());
+    }
+
+    #[test]
+    fn box_faces_point_outwards() {
+        let faces = make_box(
+            Point3::origin(),
+            Vector3::new(1., 0., 0.),
+            Vector3::new(0., 2., 0.),
+            Vector3::new(0., 0., 3.),
+            MATERIAL,
+        );
+        assert_eq!(faces.len(), 6);
+        let center = Point3::new(0.5, 1., 1.5);
+        for face in &faces {
+            // a ray from the center hits each face from the inside
+            let bbox = face.bounding_box();
+            let face_center = Point3::new(bbox.x.midpoint(), bbox.y.midpoint(), bbox.z.midpoint());
+            let r = Ray::new(center, face_center - center);
+            let hit = face.hit(&r, Interval::new(0., INFINITY)).unwrap();
+            assert!(!hit.ray_hit_outer_surface);
+        }
+    }
+}
@@ -0,0 +1,362 @@
+use crate::geom::{Point3, Vector3};
+use crate::material::Material;
+use crate::ray::Ray;
+use crate::shapes::aabb::Aabb;
+use crate::shapes::{Hittable, Intersection, Interval};
+
+/// Determinants smaller than this, relative to the lengths of the ray's
+/// direction and the triangle's edges, mean the ray is parallel to the
+/// triangle
+const PARALLEL_EPSILON: f64 = 1e-12;
+
+/// Triangles lying in an axis-aligned plane get boxes at least this thick
+const MIN_BOX_THICKNESS: f64 = 1e-6;
+
+/// A triangle with vertices in counter-clockwise order when seen from the
+/// front, and optionally a normal at each vertex for smooth shading
+#[derive(Debug, Clone)]
+pub struct Triangle {
+    vertices: (Point3, Point3, Point3),
+    normals: Option<(Vector3, Vector3, Vector3)>,
+    material: Material,
+}
+
+impl Triangle {
+    /// Creates a flat-shaded triangle
+    pub fn new(a: Point3, b: Point3, c: Point3, material: Material) -> Self {
+        Triangle {
+            vertices: (a, b, c),
+            normals: None,
+            material,
+        }
+    }
+
+    /// Creates a triangle whose shading normal is interpolated between the
+    /// given vertex normals
+    pub fn with_normals(
+        a: Point3,
+        b: Point3,
+        c: Point3,
+        normals: (Vector3, Vector3, Vector3),
+        material: Material,
+    ) -> Self {
+        Triangle {
+            vertices: (a, b, c),
+            normals: Some(normals),
+            material,
+        }
+    }
+
+    pub fn vertices(&self) -> (Point3, Point3, Point3) {
+        self.vertices
+    }
+
+    /// Unit normal of the triangle's plane, pointing out of the front face
+    pub fn face_normal(&self) -> Vector3 {
+        let (a, b, c) = self.vertices;
+        (b - a).cross(&(c - a)).to_unit_vector()
+    }
+}
+
+impl Hittable for Triangle {
+    fn get_material(&self) -> &Material {
+        &self.material
+    }
+
+    fn compute_intersection(&self, r: &Ray, t: f64) -> Intersection<'_> {
+        let barycentric = barycentric_coordinates(self.vertices, r.at(t));
+        make_intersection(
+            r,
+            t,
+            self.vertices,
+            self.normals,
+            barycentric,
+            &self.material,
+        )
+    }
+
+    fn hit(&self, r: &Ray, interval: Interval) -> Option<Intersection<'_>> {
+        let (t, barycentric) = intersect(r, self.vertices, interval)?;
+        Some(make_intersection(
+            r,
+            t,
+            self.vertices,
+            self.normals,
+            barycentric,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        bounding_box(self.vertices)
+    }
+}
+
+// The functions below work on bare vertices so they can be shared with
+// `TriangleMesh`, whose triangles don't exist as separate `Triangle`s
+
+/// Möller–Trumbore intersection, which finds `t` and the barycentric
+/// coordinates of the hit point together without computing the plane
+pub(crate) fn intersect(
+    r: &Ray,
+    vertices: (Point3, Point3, Point3),
+    interval: Interval,
+) -> Option<(f64, (f64, f64, f64))> {
+    let (a, b, c) = vertices;
+    let e1 = b - a;
+    let e2 = c - a;
+
+    let p = r.direction.cross(&e2);
+    let determinant = e1.dot(&p);
+    // the determinant grows with the edges and direction, so tiny triangles
+    // would look parallel to every ray if it weren't scaled; comparing
+    // squares saves the square roots
+    let scale_squared = e1.length_squared() * e2.length_squared() * r.direction.length_squared();
+    if determinant * determinant <= PARALLEL_EPSILON * PARALLEL_EPSILON * scale_squared {
+        return None;
+    }
+    let inverse_determinant = 1.0 / determinant;
+
+    let s = r.origin - a;
+    let u = s.dot(&p) * inverse_determinant;
+    if !(0.0..=1.0).contains(&u) {
+        return None;
+    }
+
+    let q = s.cross(&e1);
+    let v = r.direction.dot(&q) * inverse_determinant;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+
+    let t = e2.dot(&q) * inverse_determinant;
+    if !interval.surrounds(t) {
+        return None;
+    }
+    Some((t, (1.0 - u - v, u, v)))
+}
+
+/// Returns the weights of the three vertices at point `p` in the triangle's plane
+pub(crate) fn barycentric_coordinates(
+    vertices: (Point3, Point3, Point3),
+    p: Point3,
+) -> (f64, f64, f64) {
+    // Solve p = a + v * (b - a) + w * (c - a) for the weights of b and c
+    let (a, b, c) = vertices;
+    let e1 = b - a;
+    let e2 = c - a;
+    let ap = p - a;
+    let d11 = e1.dot(&e1);
+    let d12 = e1.dot(&e2);
+    let d22 = e2.dot(&e2);
+    let dp1 = ap.dot(&e1);
+    let dp2 = ap.dot(&e2);
+    let denominator = d11 * d22 - d12 * d12;
+    let v = (d22 * dp1 - d12 * dp2) / denominator;
+    let w = (d11 * dp2 - d12 * dp1) / denominator;
+    (1.0 - v - w, v, w)
+}
+
+/// Builds the intersection at `t`, where `barycentric` holds the weights of
+/// the three vertices at the hit point. With vertex normals, the shading
+/// normal is interpolated between them.
+pub(crate) fn make_intersection<'a>(
+    r: &Ray,
+    t: f64,
+    vertices: (Point3, Point3, Point3),
+    normals: Option<(Vector3, Vector3, Vector3)>,
+    barycentric: (f64, f64, f64),
+    material: &'a Material,
+) -> Intersection<'a> {
+    let (a, b, c) = vertices;
+    let face_normal = (b - a).cross(&(c - a)).to_unit_vector();
+    let mut intersection = Intersection::new(r, t, r.at(t), face_normal, material);
+    intersection.barycentric = Some(barycentric);
+    // as if the vertices had texture coordinates (0, 0), (1, 0) and (0, 1)
+    intersection.set_uv((barycentric.1, barycentric.2), b - a, c - a, r);
+
+    if let Some((na, nb, nc)) = normals {
+        let (wa, wb, wc) = barycentric;
+        let mut shading_normal = (wa * na + wb * nb + wc * nc).to_unit_vector();
+        // keep the shading normal on the same side as the face normal, even
+        // if the vertex normals were given for the other winding
+        if shading_normal.dot(&face_normal) < 0.0 {
+            shading_normal = -shading_normal;
+        }
+        intersection.normal = if intersection.ray_hit_outer_surface {
+            shading_normal
+        } else {
+            -shading_normal
+        };
+    }
+    intersection
+}
+
+pub(crate) fn bounding_box(vertices: (Point3, Point3, Point3)) -> Aabb {
+    let (a, b, c) = vertices;
+    let min = Point3::new(
+        a.x.min(b.x).min(c.x),
+        a.y.min(b.y).min(c.y),
+        a.z.min(b.z).min(c.z),
+    );
+    let max = Point3::new(
+        a.x.max(b.x).max(c.x),
+        a.y.max(b.y).max(c.y),
+        a.z.max(b.z).max(c.z),
+    );
+    let bbox = Aabb::from_points(min, max);
+    Aabb::new(
+        bbox.x.pad_to(MIN_BOX_THICKNESS),
+        bbox.y.pad_to(MIN_BOX_THICKNESS),
+        bbox.z.pad_to(MIN_BOX_THICKNESS),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Triangle;
+    use crate::color::Color;
+    use crate::geom::{Point3, Vector3};
+    use crate::material::Material;
+    use crate::ray::Ray;
+    use crate::shapes::{Hittable, Interval, INFINITY};
+    use crate::texture::Texture;
+
+    const MATERIAL: Material = Material::DiffuseNonMetal(Texture::Constant(Color::WHITE));
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    fn unit_triangle() -> Triangle {
+        Triangle::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+            MATERIAL,
+        )
+    }
+
+    #[test]
+    fn can_hit_triangle() {
+        let triangle = unit_triangle();
+        let r = Ray::new(Point3::new(0.25, 0.25, 2.), Vector3::new(0., 0., -1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_close(hit.t, 2.);
+        assert_eq!(hit.p, Point3::new(0.25, 0.25, 0.));
+        assert_eq!(hit.normal, Vector3::new(0., 0., 1.));
+        assert!(hit.ray_hit_outer_surface);
+    }
+
+    #[test]
+    fn can_hit_tiny_triangle() {
+        let size = 1e-7;
+        let triangle = Triangle::new(
+            Point3::new(0., 0., 0.),
+            Point3::new(size, 0., 0.),
+            Point3::new(0., size, 0.),
+            MATERIAL,
+        );
+        let r = Ray::new(
+            Point3::new(0.25 * size, 0.25 * size, 1.),
+            Vector3::new(0., 0., -1.),
+        );
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_close(hit.t, 1.);
+    }
+
+    #[test]
+    fn can_hit_back_face() {
+        let triangle = unit_triangle();
+        let r = Ray::new(Point3::new(0.25, 0.25, -2.), Vector3::new(0., 0., 1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert!(!hit.ray_hit_outer_surface);
+        assert_eq!(hit.normal, Vector3::new(0., 0., -1.));
+    }
+
+    #[test]
+    fn rays_miss_outside_edges_and_interval() {
+        let triangle = unit_triangle();
+        let down = Vector3::new(0., 0., -1.);
+        let interval = Interval::new(0., INFINITY);
+        for (x, y) in [(-0.1, 0.5), (0.5, -0.1), (0.6, 0.6)] {
+            let r = Ray::new(Point3::new(x, y, 2.), down);
+            assert!(triangle.hit(&r, interval).is_none());
+        }
+
+        // parallel to the triangle's plane
+        let r = Ray::new(Point3::new(-1., 0.25, 0.), Vector3::new(1., 0., 0.));
+        assert!(triangle.hit(&r, interval).is_none());
+
+        // hit lies beyond the interval
+        let r = Ray::new(Point3::new(0.25, 0.25, 2.), down);
+        assert!(triangle.hit(&r, Interval::new(0., 1.)).is_none());
+    }
+
+    #[test]
+    fn can_compute_barycentric_coordinates() {
+        let triangle = unit_triangle();
+        let r = Ray::new(Point3::new(0.2, 0.5, 1.), Vector3::new(0., 0., -1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        let (wa, wb, wc) = hit.barycentric.unwrap();
+        assert_close(wa, 0.3);
+        assert_close(wb, 0.2);
+        assert_close(wc, 0.5);
+
+        // computing the intersection from t alone gives the same weights
+        let from_t = triangle.compute_intersection(&r, hit.t);
+        let (ua, ub, uc) = from_t.barycentric.unwrap();
+        assert_close(ua, wa);
+        assert_close(ub, wb);
+        assert_close(uc, wc);
+    }
+
+    #[test]
+    fn can_interpolate_vertex_normals() {
+        let tilted = Vector3::new(1., 0., 1.).to_unit_vector();
+        let up = Vector3::new(0., 0., 1.);
+        let triangle = Triangle::with_normals(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+            (up, tilted, up),
+            MATERIAL,
+        );
+
+        // at vertex b the normal is b's normal
+        let r = Ray::new(Point3::new(1. - 1e-9, 0., 1.), Vector3::new(0., 0., -1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_close(hit.normal.dot(&tilted), 1.);
+
+        // in between, the normal is unit length and leans towards b
+        let r = Ray::new(Point3::new(0.5, 0.25, 1.), Vector3::new(0., 0., -1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_close(hit.normal.norm(), 1.);
+        assert!(hit.normal.x > 0.);
+
+        // from behind, the shading normal flips along with the face normal
+        let r = Ray::new(Point3::new(0.5, 0.25, -1.), Vector3::new(0., 0., 1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert!(hit.normal.z < 0. && hit.normal.x < 0.);
+
+        // vertex normals facing away from the winding are turned around
+        let down = Vector3::new(0., 0., -1.);
+        let triangle = Triangle::with_normals(
+            Point3::new(0., 0., 0.),
+            Point3::new(1., 0., 0.),
+            Point3::new(0., 1., 0.),
+            (down, down, down),
+            MATERIAL,
+        );
+        let r = Ray::new(Point3::new(0.25, 0.25, 1.), Vector3::new(0., 0., -1.));
+        let hit = triangle.hit(&r, Interval::new(0., INFINITY)).unwrap();
+        assert_eq!(hit.normal, up);
+    }
+
+    #[test]
+    fn flat_triangle_has_nonempty_box() {
+        let bbox = unit_triangle().bounding_box();
+        assert!(bbox.z.size() > 0.);
+        assert_eq!(bbox.x.size(), 1.);
+    }
+}
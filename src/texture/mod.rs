@@ -0,0 +1,505 @@
+use crate::color::Color;
+use crate::geom::Point3;
+use crate::image::Image;
+use crate::shapes::Intersection;
+use std::f64::consts::PI;
+use std::sync::Arc;
+
+mod noise;
+
+pub use noise::{Perlin, Worley};
+
+// texture/mod.rs
+//
+// Textures vary a material's color across its surface. They're looked up
+// with the hit point's texture coordinates (u, v), which run from 0 to 1
+// across each shape, or with the hit point itself for solid textures that
+// fill space.
+//
+// Image textures can be mipmapped: a chain of copies, each half the size of
+// the one before, so a lookup whose footprint covers many texels reads one
+// texel of a smaller copy instead of aliasing.
+//
+// Noise textures are solid: they're looked up with the hit point, so
+// objects look carved out of them rather than wrapped in them.
+
+/// Octaves of turbulence that noise textures use unless told otherwise
+pub const DEFAULT_OCTAVES: u32 = 7;
+
+/// How image textures treat coordinates outside [0, 1]
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    /// Tiles the image
+    Repeat,
+    /// Stretches the pixels along the edges
+    Clamp,
+    /// Tiles the image, flipping every other tile
+    Mirror,
+}
+
+/// How image textures blend the pixels around a lookup
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+    /// The pixel the lookup falls in
+    Nearest,
+    /// A blend of the four pixels nearest the lookup
+    Bilinear,
+}
+
+/// An image for looking up with texture coordinates, with (0, 0) at its
+/// bottom left corner
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageTexture {
+    /// The image, followed by its mipmaps, if any
+    levels: Vec<Image>,
+    wrap: WrapMode,
+    filter: Filter,
+}
+
+/// Which texture coordinate a gradient runs along
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureAxis {
+    U,
+    V,
+}
+
+/// The patterns noise textures can make
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum NoisePattern {
+    /// Plain Perlin noise
+    Perlin,
+    /// Layers of Perlin noise at finer and finer scales
+    Turbulence,
+    /// Bands along z, warped by turbulence
+    Marble,
+    /// Rings round the y axis, warped by turbulence
+    Wood,
+    /// The distance to the nearest of a scattering of points, which makes
+    /// cells
+    Worley,
+}
+
+/// A solid texture blending between two textures by the amount of noise
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoiseTexture {
+    pattern: NoisePattern,
+    perlin: Perlin,
+    worley: Worley,
+    /// Features are about `1 / scale` across
+    scale: f64,
+    octaves: u32,
+    low: Texture,
+    high: Texture,
+}
+
+/// A color that varies over a surface
+#[derive(Debug, Clone, PartialEq)]
+pub enum Texture {
+    /// The same color everywhere
+    Constant(Color),
+    /// A checkerboard of cubes with sides of length `size`, filling space
+    /// and alternating between two textures
+    Checker {
+        even: Arc<Texture>,
+        odd: Arc<Texture>,
+        size: f64,
+    },
+    /// An image stretched over the texture coordinates
+    Image(Arc<ImageTexture>),
+    /// Procedural noise filling space
+    Noise(Arc<NoiseTexture>),
+    /// Blends linearly from `start`, where the coordinate along `axis` is
+    /// 0, to `end`, where it's 1
+    Gradient {
+        start: Color,
+        end: Color,
+        axis: TextureAxis,
+    },
+}
+
+impl From<Color> for Texture {
+    fn from(color: Color) -> Self {
+        Texture::Constant(color)
+    }
+}
+
+/// A shade of gray, for textures that stand for a number
+impl From<f64> for Texture {
+    fn from(value: f64) -> Self {
+        Texture::Constant(Color::new(value, value, value))
+    }
+}
+
+/// Maps a pixel index that may be outside the image, which is `size`
+/// pixels across, to one inside it
+fn wrap_index(i: i64, size: usize, wrap: WrapMode) -> usize {
+    let size = size as i64;
+    let i = match wrap {
+        WrapMode::Repeat => i.rem_euclid(size),
+        WrapMode::Clamp => i.clamp(0, size - 1),
+        WrapMode::Mirror => {
+            let i = i.rem_euclid(2 * size);
+            if i < size {
+                i
+            } else {
+                2 * size - 1 - i
+            }
+        }
+    };
+    i as usize
+}
+
+/// Halves the image in each direction, averaging each block of 2x2 pixels.
+/// An odd row or column at the end is averaged into the last pixel.
+fn downsample(image: &Image) -> Image {
+    let (width, height) = (image.width(), image.height());
+    let (half_width, half_height) = ((width / 2).max(1), (height / 2).max(1));
+    let mut half = Image::new(half_width, half_height);
+    for y in 0..half_height {
+        let rows = 2 * y..if y + 1 == half_height {
+            height
+        } else {
+            2 * y + 2
+        };
+        for x in 0..half_width {
+            let columns = 2 * x..if x + 1 == half_width {
+                width
+            } else {
+                2 * x + 2
+            };
+            let mut sum = Color::BLACK;
+            for j in rows.clone() {
+                for i in columns.clone() {
+                    sum += image.get(i, j);
+                }
+            }
+            let count = rows.len() * columns.len();
+            half.set(x, y, sum * (1.0 / count as f64));
+        }
+    }
+    half
+}
+
+impl ImageTexture {
+    /// Panics if the image is empty
+    pub fn new(image: Image, wrap: WrapMode, filter: Filter) -> Self {
+        assert!(
+            image.width() > 0 && image.height() > 0,
+            "image textures need at least one pixel"
+        );
+        ImageTexture {
+            levels: vec![image],
+            wrap,
+            filter,
+        }
+    }
+
+    /// Adds mipmaps, halving the image down to a single pixel
+    pub fn with_mipmaps(mut self) -> Self {
+        self.levels.truncate(1);
+        loop {
+            let last = &self.levels[self.levels.len() - 1];
+            if last.width() == 1 && last.height() == 1 {
+                return self;
+            }
+            let next = downsample(last);
+            self.levels.push(next);
+        }
+    }
+
+    /// The number of images in the mipmap chain, including the full size one
+    pub fn levels(&self) -> usize {
+        self.levels.len()
+    }
+
+    /// The color at `uv`, blurred over a region `footprint` wide if the
+    /// texture has mipmaps
+    pub fn value(&self, uv: (f64, f64), footprint: f64) -> Color {
+        let image = &self.levels[0];
+        let texels = footprint * image.width().max(image.height()) as f64;
+        // each level halves the size, so covering 2^n texels calls for level n
+        let level = if texels > 1.0 { texels.log2() } else { 0.0 };
+        let level = level.min((self.levels.len() - 1) as f64);
+        let lower = level.floor();
+        let t = level - lower;
+        let color = self.level_value(lower as usize, uv);
+        if t > 0.0 {
+            (1.0 - t) * color + t * self.level_value(lower as usize + 1, uv)
+        } else {
+            color
+        }
+    }
+
+    fn level_value(&self, level: usize, uv: (f64, f64)) -> Color {
+        let image = &self.levels[level];
+        let (width, height) = (image.width(), image.height());
+        // rows go down the image, while v goes up
+        let x = uv.0 * width as f64;
+        let y = (1.0 - uv.1) * height as f64;
+        let texel = |i: f64, j: f64| {
+            image.get(
+                wrap_index(i as i64, width, self.wrap),
+                wrap_index(j as i64, height, self.wrap),
+            )
+        };
+        match self.filter {
+            Filter::Nearest => texel(x.floor(), y.floor()),
+            Filter::Bilinear => {
+                // pixel centers are half a pixel in from their corners
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let top = (1.0 - tx) * texel(x0, y0) + tx * texel(x0 + 1.0, y0);
+                let bottom = (1.0 - tx) * texel(x0, y0 + 1.0) + tx * texel(x0 + 1.0, y0 + 1.0);
+                (1.0 - ty) * top + ty * bottom
+            }
+        }
+    }
+}
+
+impl NoiseTexture {
+    /// Blends from `low` where there's no noise to `high` where there's the
+    /// most. The same `seed` always gives the same noise.
+    pub fn new(pattern: NoisePattern, seed: u64, scale: f64, low: Texture, high: Texture) -> Self {
+        NoiseTexture {
+            pattern,
+            perlin: Perlin::new(seed),
+            worley: Worley::new(seed),
+            scale,
+            octaves: DEFAULT_OCTAVES,
+            low,
+            high,
+        }
+    }
+
+    /// Sets the octaves of turbulence, for the patterns that use it
+    pub fn with_octaves(mut self, octaves: u32) -> Self {
+        self.octaves = octaves;
+        self
+    }
+
+    /// How much noise there is at `p`, from 0 to 1
+    pub fn amount(&self, p: &Point3) -> f64 {
+        let s = Point3::new(self.scale * p.x, self.scale * p.y, self.scale * p.z);
+        let amount = match self.pattern {
+            NoisePattern::Perlin => 0.5 * (1.0 + self.perlin.noise(&s)),
+            NoisePattern::Turbulence => self.perlin.turbulence(&s, self.octaves),
+            NoisePattern::Marble => {
+                let phase = s.z + 10.0 * self.perlin.turbulence(&s, self.octaves);
+                0.5 * (1.0 + phase.sin())
+            }
+            NoisePattern::Wood => {
+                let radius = (s.x * s.x + s.z * s.z).sqrt();
+                let rings = radius + 0.5 * self.perlin.turbulence(&s, self.octaves);
+                // each ring darkens smoothly from its inside to its outside
+                0.5 * (1.0 - (2.0 * PI * rings).cos())
+            }
+            NoisePattern::Worley => self.worley.distance(&s),
+        };
+        amount.clamp(0.0, 1.0)
+    }
+
+    fn value(&self, uv: (f64, f64), footprint: f64, p: &Point3) -> Color {
+        let t = self.amount(p);
+        (1.0 - t) * self.low.filtered_value(uv, footprint, p)
+            + t * self.high.filtered_value(uv, footprint, p)
+    }
+}
+
+impl Texture {
+    /// The color where a ray hit the surface
+    pub fn value_at(&self, hit: &Intersection) -> Color {
+        self.filtered_value(hit.uv, hit.footprint, &hit.p)
+    }
+
+    /// The color at texture coordinates `uv`, on the surface at `p`
+    pub fn value(&self, uv: (f64, f64), p: &Point3) -> Color {
+        self.filtered_value(uv, 0.0, p)
+    }
+
+    /// The color at texture coordinates `uv`, on the surface at `p`,
+    /// averaged over a region `footprint` wide in texture coordinates
+    pub fn filtered_value(&self, uv: (f64, f64), footprint: f64, p: &Point3) -> Color {
+        match self {
+            Texture::Constant(color) => *color,
+            Texture::Checker { even, odd, size } => {
+                let cell = (p.x / size).floor() + (p.y / size).floor() + (p.z / size).floor();
+                if cell.rem_euclid(2.0) == 0.0 {
+                    even.filtered_value(uv, footprint, p)
+                } else {
+                    odd.filtered_value(uv, footprint, p)
+                }
+            }
+            Texture::Image(image) => image.value(uv, footprint),
+            Texture::Noise(noise) => noise.value(uv, footprint, p),
+            Texture::Gradient { start, end, axis } => {
+                let t = match axis {
+                    TextureAxis::U => uv.0,
+                    TextureAxis::V => uv.1,
+                }
+                .clamp(0.0, 1.0);
+                (1.0 - t) * *start + t * *end
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Filter, ImageTexture, NoisePattern, NoiseTexture, Texture, TextureAxis, WrapMode};
+    use crate::color::Color;
+    use crate::geom::Point3;
+    use crate::image::Image;
+    use std::sync::Arc;
+
+    const RED: Color = Color {
+        red: 1.,
+        green: 0.,
+        blue: 0.,
+    };
+
+    #[test]
+    fn can_look_up_textures() {
+        let origin = Point3::origin();
+        assert_eq!(Texture::from(RED).value((0.3, 0.8), &origin), RED);
+
+        let checker = Texture::Checker {
+            even: Arc::new(Texture::Constant(Color::WHITE)),
+            odd: Arc::new(Texture::Constant(RED)),
+            size: 0.5,
+        };
+        assert_eq!(
+            checker.value((0., 0.), &Point3::new(0.1, 0.1, 0.1)),
+            Color::WHITE
+        );
+        assert_eq!(checker.value((0., 0.), &Point3::new(0.6, 0.1, 0.1)), RED);
+        assert_eq!(checker.value((0., 0.), &Point3::new(-0.1, 0.1, 0.1)), RED);
+        assert_eq!(
+            checker.value((0., 0.), &Point3::new(-0.1, -0.1, 0.1)),
+            Color::WHITE
+        );
+
+        let gradient = Texture::Gradient {
+            start: Color::BLACK,
+            end: Color::WHITE,
+            axis: TextureAxis::V,
+        };
+        assert_eq!(
+            gradient.value((0.9, 0.25), &origin),
+            Color::new(0.25, 0.25, 0.25)
+        );
+        assert_eq!(gradient.value((0., 2.), &origin), Color::WHITE);
+    }
+
+    #[test]
+    fn images_have_v_going_up() {
+        // red along the top row, white along the bottom
+        let image = Image::from_pixels(2, 2, vec![RED, RED, Color::WHITE, Color::WHITE]);
+        let texture = Texture::Image(Arc::new(ImageTexture::new(
+            image,
+            WrapMode::Clamp,
+            Filter::Nearest,
+        )));
+        let origin = Point3::origin();
+        assert_eq!(texture.value((0.2, 0.9), &origin), RED);
+        assert_eq!(texture.value((0.7, 0.1), &origin), Color::WHITE);
+        assert_eq!(texture.value((1., 1.), &origin), RED);
+        assert_eq!(texture.value((-1., -1.), &origin), Color::WHITE);
+    }
+
+    #[test]
+    fn can_wrap_coordinates() {
+        // black, gray and white from left to right
+        let gray = Color::new(0.5, 0.5, 0.5);
+        let image = Image::from_pixels(3, 1, vec![Color::BLACK, gray, Color::WHITE]);
+        let lookup = |wrap: WrapMode, u: f64| {
+            ImageTexture::new(image.clone(), wrap, Filter::Nearest).value((u, 0.5), 0.)
+        };
+        assert_eq!(lookup(WrapMode::Repeat, 1.1), Color::BLACK);
+        assert_eq!(lookup(WrapMode::Repeat, -0.1), Color::WHITE);
+        assert_eq!(lookup(WrapMode::Clamp, 1.1), Color::WHITE);
+        assert_eq!(lookup(WrapMode::Clamp, -5.), Color::BLACK);
+        assert_eq!(lookup(WrapMode::Mirror, 1.1), Color::WHITE);
+        assert_eq!(lookup(WrapMode::Mirror, 1.5), gray);
+        assert_eq!(lookup(WrapMode::Mirror, -0.1), Color::BLACK);
+        assert_eq!(lookup(WrapMode::Mirror, 2.1), Color::BLACK);
+    }
+
+    #[test]
+    fn can_filter_bilinearly() {
+        let image = Image::from_pixels(2, 1, vec![Color::BLACK, Color::WHITE]);
+        let texture = ImageTexture::new(image, WrapMode::Clamp, Filter::Bilinear);
+        // halfway between the pixel centers
+        assert_eq!(texture.value((0.5, 0.5), 0.), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(
+            texture.value((0.375, 0.5), 0.),
+            Color::new(0.25, 0.25, 0.25)
+        );
+        // clamping holds the edge pixels' colors out to the edges
+        assert_eq!(texture.value((0.1, 0.5), 0.), Color::BLACK);
+        assert_eq!(texture.value((1., 0.5), 0.), Color::WHITE);
+    }
+
+    #[test]
+    fn mipmaps_average_over_footprint() {
+        // a 4x2 checkerboard of red and white pixels
+        let pixels = (0..8)
+            .map(|i| {
+                if (i + i / 4) % 2 == 0 {
+                    RED
+                } else {
+                    Color::WHITE
+                }
+            })
+            .collect();
+        let image = Image::from_pixels(4, 2, pixels);
+        let texture = ImageTexture::new(image, WrapMode::Repeat, Filter::Nearest);
+        assert_eq!(texture.levels(), 1);
+        assert_eq!(texture.value((0.1, 0.9), 1.), RED);
+
+        let texture = texture.with_mipmaps();
+        assert_eq!(texture.levels(), 3);
+        let pink = Color::new(1., 0.5, 0.5);
+        assert_eq!(texture.value((0.1, 0.9), 0.), RED);
+        assert_eq!(texture.value((0.1, 0.9), 0.5), pink);
+        assert_eq!(texture.value((0.1, 0.9), 10.), pink);
+        // footprints between levels blend them
+        let blend = texture.value((0.1, 0.9), 0.25 * 2f64.sqrt());
+        assert!((blend.green - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn noise_textures_blend_between_textures() {
+        let patterns = vec![
+            NoisePattern::Perlin,
+            NoisePattern::Turbulence,
+            NoisePattern::Marble,
+            NoisePattern::Wood,
+            NoisePattern::Worley,
+        ];
+        for pattern in patterns {
+            let texture = NoiseTexture::new(pattern, 1, 4., Texture::from(0.), RED.into());
+            let same = NoiseTexture::new(pattern, 1, 4., Texture::from(0.), RED.into());
+            let mut amounts = Vec::new();
+            for i in 0..200 {
+                let x = i as f64 * 0.0731;
+                let p = Point3::new(x, 0.3 * x, -0.2 * x);
+                let amount = texture.amount(&p);
+                assert!((0. ..=1.).contains(&amount), "{:?}", pattern);
+                assert_eq!(amount, same.amount(&p));
+                amounts.push(amount);
+            }
+            let mean = amounts.iter().sum::<f64>() / amounts.len() as f64;
+            assert!(
+                amounts.iter().any(|&a| (a - mean).abs() > 0.1),
+                "{:?}",
+                pattern
+            );
+
+            let p = Point3::new(0.1, 0.2, 0.3);
+            let t = texture.amount(&p);
+            let color = Texture::Noise(Arc::new(texture)).value((0., 0.), &p);
+            assert_eq!(color, Color::new(t, 0., 0.));
+        }
+    }
+}
@@ -0,0 +1,211 @@
+use crate::geom::{Point3, Vector3};
+use crate::random::mix_seed;
+use rand::rngs::SmallRng;
+use rand::{Rng, SeedableRng};
+
+// texture/noise.rs
+//
+// Noise functions for procedural textures. Both kinds are built from a
+// seed instead of the renderer's random numbers, so a texture looks the same
+// in every render, and on every thread.
+
+/// Number of gradients, and the length of each permutation table
+const POINT_COUNT: usize = 256;
+
+/// Ken Perlin's gradient noise, as in "Ray Tracing: The Next Week": a
+/// random gradient at each lattice point, blended smoothly in between
+#[derive(Debug, Clone, PartialEq)]
+pub struct Perlin {
+    gradients: Vec<Vector3>,
+    permutations: [Vec<usize>; 3],
+}
+
+/// Uniformly random unit vector, from a generator of its own
+fn random_unit_vector(rng: &mut SmallRng) -> Vector3 {
+    loop {
+        let v = Vector3::new(
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+            rng.gen_range(-1.0..1.0),
+        );
+        let length_squared = v.length_squared();
+        if length_squared > 1e-6 && length_squared <= 1.0 {
+            return v / length_squared.sqrt();
+        }
+    }
+}
+
+fn permutation(rng: &mut SmallRng) -> Vec<usize> {
+    let mut p: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        p.swap(i, rng.gen_range(0..=i));
+    }
+    p
+}
+
+impl Perlin {
+    pub fn new(seed: u64) -> Self {
+        let mut rng = SmallRng::seed_from_u64(seed);
+        let gradients = (0..POINT_COUNT)
+            .map(|_| random_unit_vector(&mut rng))
+            .collect();
+        let permutations = [
+            permutation(&mut rng),
+            permutation(&mut rng),
+            permutation(&mut rng),
+        ];
+        Perlin {
+            gradients,
+            permutations,
+        }
+    }
+
+    fn gradient(&self, i: i64, j: i64, k: i64) -> Vector3 {
+        let [x, y, z] = &self.permutations;
+        let mask = POINT_COUNT as i64 - 1;
+        let index = x[(i & mask) as usize] ^ y[(j & mask) as usize] ^ z[(k & mask) as usize];
+        self.gradients[index]
+    }
+
+    /// Noise at `p`, between -1 and 1, and 0 at every lattice point
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x.floor(), p.y.floor(), p.z.floor());
+        let (u, v, w) = (p.x - i, p.y - j, p.z - k);
+        // Hermite smoothing hides the lattice
+        let smooth = |t: f64| t * t * (3.0 - 2.0 * t);
+        let (uu, vv, ww) = (smooth(u), smooth(v), smooth(w));
+
+        let mut sum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let gradient = self.gradient(i as i64 + di, j as i64 + dj, k as i64 + dk);
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let offset = Vector3::new(u - fi, v - fj, w - fk);
+                    sum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * gradient.dot(&offset);
+                }
+            }
+        }
+        // the sum can reach a little past ±1 near the cube's diagonals
+        sum.clamp(-1.0, 1.0)
+    }
+
+    /// Sum of `octaves` layers of noise, each at twice the frequency and
+    /// half the strength of the one before. Never negative, and below 2.
+    pub fn turbulence(&self, p: &Point3, octaves: u32) -> f64 {
+        let mut sum = 0.0;
+        let mut p = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            sum += weight * self.noise(&p);
+            weight *= 0.5;
+            p = Point3::new(2.0 * p.x, 2.0 * p.y, 2.0 * p.z);
+        }
+        sum.abs()
+    }
+}
+
+/// Steven Worley's cellular noise: one feature point at a random place in
+/// each unit cube, with the noise being the distance to the nearest one
+#[derive(Debug, Clone, PartialEq)]
+pub struct Worley {
+    seed: u64,
+}
+
+/// Maps a hash to a number in [0, 1)
+fn unit_interval(hash: u64) -> f64 {
+    (hash >> 11) as f64 / (1u64 << 53) as f64
+}
+
+impl Worley {
+    pub fn new(seed: u64) -> Self {
+        Worley { seed }
+    }
+
+    /// The feature point in the cell whose lowest corner is (i, j, k)
+    fn feature_point(&self, i: i64, j: i64, k: i64) -> Point3 {
+        let cell = mix_seed(mix_seed(mix_seed(self.seed, i as u64), j as u64), k as u64);
+        Point3::new(
+            i as f64 + unit_interval(mix_seed(cell, 0)),
+            j as f64 + unit_interval(mix_seed(cell, 1)),
+            k as f64 + unit_interval(mix_seed(cell, 2)),
+        )
+    }
+
+    /// Distance from `p` to the nearest feature point. At most √3, and
+    /// rarely more than 1.
+    pub fn distance(&self, p: &Point3) -> f64 {
+        let (i, j, k) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+        let mut nearest = f64::INFINITY;
+        // the nearest point is nearly always in this cell or one of its
+        // neighbors, and the rare misses don't show
+        for di in -1..=1 {
+            for dj in -1..=1 {
+                for dk in -1..=1 {
+                    let point = self.feature_point(i + di, j + dj, k + dk);
+                    nearest = nearest.min((point - *p).length_squared());
+                }
+            }
+        }
+        nearest.sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Perlin, Worley};
+    use crate::geom::Point3;
+
+    #[test]
+    fn perlin_noise_is_smooth_and_seeded() {
+        let perlin = Perlin::new(7);
+        assert_eq!(perlin.noise(&Point3::new(3., -2., 5.)), 0.);
+        let p = Point3::new(0.3, 1.7, -2.2);
+        let nearby = Point3::new(0.3001, 1.7, -2.2);
+        assert!((perlin.noise(&p) - perlin.noise(&nearby)).abs() < 1e-3);
+        assert_eq!(perlin.noise(&p), Perlin::new(7).noise(&p));
+        assert_ne!(perlin.noise(&p), Perlin::new(8).noise(&p));
+
+        let mut sum = 0.;
+        let mut largest: f64 = 0.;
+        for i in 0..1000 {
+            let x = i as f64 * 0.137;
+            let noise = perlin.noise(&Point3::new(x, 0.5 * x, 0.31));
+            sum += noise;
+            largest = largest.max(noise.abs());
+        }
+        assert!((sum / 1000.).abs() < 0.1);
+        assert!(largest > 0.2 && largest <= 1.);
+        let turbulence = perlin.turbulence(&p, 7);
+        assert!((0. ..2.).contains(&turbulence));
+    }
+
+    #[test]
+    fn worley_noise_finds_nearest_feature_point() {
+        let worley = Worley::new(3);
+        for i in 0..100 {
+            let x = i as f64 * 0.173;
+            let p = Point3::new(x, -0.7 * x, 0.5);
+            let distance = worley.distance(&p);
+            assert!((0. ..=3f64.sqrt()).contains(&distance));
+            // by brute force over a wider neighborhood
+            let (ci, cj, ck) = (p.x.floor() as i64, p.y.floor() as i64, p.z.floor() as i64);
+            let mut nearest = f64::INFINITY;
+            for di in -2..=2 {
+                for dj in -2..=2 {
+                    for dk in -2..=2 {
+                        let point = worley.feature_point(ci + di, cj + dj, ck + dk);
+                        nearest = nearest.min((point - p).norm());
+                    }
+                }
+            }
+            assert_eq!(distance, nearest);
+        }
+        let p = Point3::new(0.4, 0.4, 0.4);
+        assert_eq!(worley.distance(&p), Worley::new(3).distance(&p));
+        assert_ne!(worley.distance(&p), Worley::new(4).distance(&p));
+    }
+}
@@ -0,0 +1,203 @@
+use crate::color::Color;
+use crate::image::Image;
+
+// tonemap.rs
+
+/// Constants of Hable's filmic curve: shoulder strength, linear strength,
+/// linear angle, toe strength, toe numerator and toe denominator
+const FILMIC_A: f64 = 0.15;
+const FILMIC_B: f64 = 0.50;
+const FILMIC_C: f64 = 0.10;
+const FILMIC_D: f64 = 0.20;
+const FILMIC_E: f64 = 0.02;
+const FILMIC_F: f64 = 0.30;
+/// Linear value that the filmic curve maps to white
+const FILMIC_WHITE: f64 = 11.2;
+/// Hable brightens the input before applying the curve
+const FILMIC_EXPOSURE_BIAS: f64 = 2.0;
+
+/// Curves that compress linear radiance, which can be arbitrarily bright,
+/// into the [0, 1] range of a display
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    /// Clips values above 1
+    Clamp,
+    /// Reinhard et al.'s `x / (1 + x)`, which never quite reaches white
+    Reinhard,
+    /// Reinhard's curve adjusted so that `white`, and anything brighter,
+    /// becomes white. Without `white`, the brightest value in the image is used.
+    ReinhardExtended { white: Option<f64> },
+    /// John Hable's filmic curve, from Uncharted 2
+    Filmic,
+    /// Krzysztof Narkowicz's fit of the ACES filmic curve
+    Aces,
+}
+
+/// Turns a linear, high dynamic range image into one that can be displayed.
+/// The colors are scaled by the exposure, compressed by the tone mapping
+/// operator, and then encoded as sRGB.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    /// Brightness adjustment in stops: each stop doubles the brightness
+    pub exposure: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: 0.0,
+        }
+    }
+}
+
+fn filmic_curve(x: f64) -> f64 {
+    (x * (FILMIC_A * x + FILMIC_C * FILMIC_B) + FILMIC_D * FILMIC_E)
+        / (x * (FILMIC_A * x + FILMIC_B) + FILMIC_D * FILMIC_F)
+        - FILMIC_E / FILMIC_F
+}
+
+fn aces_curve(x: f64) -> f64 {
+    let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+    (x * (a * x + b)) / (x * (c * x + d) + e)
+}
+
+impl ToneMapOperator {
+    /// Maps one linear color channel to [0, 1]. `white` is the value of
+    /// `ReinhardExtended`'s white point to use.
+    fn map_channel(&self, x: f64, white: f64) -> f64 {
+        let x = x.max(0.0);
+        let mapped = match self {
+            ToneMapOperator::Clamp => x,
+            ToneMapOperator::Reinhard => x / (1.0 + x),
+            ToneMapOperator::ReinhardExtended { .. } => x * (1.0 + x / (white * white)) / (1.0 + x),
+            ToneMapOperator::Filmic => {
+                filmic_curve(FILMIC_EXPOSURE_BIAS * x) / filmic_curve(FILMIC_WHITE)
+            }
+            ToneMapOperator::Aces => aces_curve(x),
+        };
+        mapped.min(1.0)
+    }
+}
+
+impl ToneMapping {
+    /// Maps a linear color to a linear color in [0, 1], before sRGB encoding.
+    /// `white` is used by `ReinhardExtended` when it has no white point.
+    fn map_with_white(&self, color: Color, white: f64) -> Color {
+        let color = color * 2f64.powf(self.exposure);
+        let white = match self.operator {
+            ToneMapOperator::ReinhardExtended { white: Some(white) } => white,
+            _ => white,
+        };
+        Color::new(
+            self.operator.map_channel(color.red, white),
+            self.operator.map_channel(color.green, white),
+            self.operator.map_channel(color.blue, white),
+        )
+    }
+
+    /// Maps a linear color to a linear color in [0, 1]. `ReinhardExtended`
+    /// without a white point uses 1 here, since there's no image to look at.
+    pub fn map(&self, color: Color) -> Color {
+        self.map_with_white(color, 1.0)
+    }
+
+    /// Tone maps and sRGB-encodes every pixel, ready to be written to a PPM
+    /// or PNG file
+    pub fn to_display(&self, image: &Image) -> Image {
+        // the brightest channel after exposure, for ReinhardExtended
+        let brightest = image
+            .pixels()
+            .iter()
+            .map(|color| color.red.max(color.green).max(color.blue))
+            .fold(0.0, f64::max)
+            * 2f64.powf(self.exposure);
+        // anything below 1 would darken the image rather than compress it
+        let white = brightest.max(1.0);
+        image.map(|color| self.map_with_white(color, white).encode_srgb())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ToneMapOperator, ToneMapping};
+    use crate::color::Color;
+    use crate::image::Image;
+
+    const OPERATORS: [ToneMapOperator; 5] = [
+        ToneMapOperator::Clamp,
+        ToneMapOperator::Reinhard,
+        ToneMapOperator::ReinhardExtended { white: Some(4.0) },
+        ToneMapOperator::Filmic,
+        ToneMapOperator::Aces,
+    ];
+
+    fn gray(value: f64) -> Color {
+        Color::new(value, value, value)
+    }
+
+    #[test]
+    fn operators_are_monotonic_and_bounded() {
+        for operator in OPERATORS {
+            let mapping = ToneMapping {
+                operator,
+                exposure: 0.0,
+            };
+            assert!(mapping.map(Color::BLACK).red.abs() < 1e-9, "{:?}", operator);
+            let mut previous = 0.0;
+            for i in 1..1000 {
+                let mapped = mapping.map(gray(i as f64 * 0.05)).red;
+                assert!(mapped >= previous, "{:?} at {}", operator, i);
+                assert!(mapped <= 1.0, "{:?} at {}", operator, i);
+                previous = mapped;
+            }
+        }
+    }
+
+    #[test]
+    fn can_map_known_values() {
+        let map = |operator, value| {
+            ToneMapping {
+                operator,
+                exposure: 0.0,
+            }
+            .map(gray(value))
+            .red
+        };
+        assert_eq!(map(ToneMapOperator::Clamp, 0.25), 0.25);
+        assert_eq!(map(ToneMapOperator::Clamp, 3.0), 1.0);
+        assert_eq!(map(ToneMapOperator::Reinhard, 1.0), 0.5);
+        let white = ToneMapOperator::ReinhardExtended { white: Some(4.0) };
+        assert!((map(white, 4.0) - 1.0).abs() < 1e-12);
+        assert!(map(white, 1.0) > map(ToneMapOperator::Reinhard, 1.0));
+        assert!((map(ToneMapOperator::Filmic, 11.2 / 2.0) - 1.0).abs() < 1e-12);
+        assert!((map(ToneMapOperator::Aces, 1.0) - 0.8038).abs() < 1e-4);
+    }
+
+    #[test]
+    fn each_stop_of_exposure_doubles_brightness() {
+        let mapping = ToneMapping {
+            operator: ToneMapOperator::Clamp,
+            exposure: 2.0,
+        };
+        assert_eq!(mapping.map(gray(0.125)), gray(0.5));
+        let mapping = ToneMapping {
+            exposure: -1.0,
+            ..mapping
+        };
+        assert_eq!(mapping.map(gray(0.5)), gray(0.25));
+    }
+
+    #[test]
+    fn extended_reinhard_defaults_to_brightest_pixel() {
+        let image = Image::from_pixels(2, 1, vec![gray(0.5), Color::new(8.0, 0.0, 0.0)]);
+        let mapping = ToneMapping {
+            operator: ToneMapOperator::ReinhardExtended { white: None },
+            exposure: 0.0,
+        };
+        let display = mapping.to_display(&image);
+        assert!((display.get(1, 0).red - 1.0).abs() < 1e-12);
+        assert!(display.get(0, 0).red < 1.0);
+    }
+}